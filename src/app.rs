@@ -1,19 +1,38 @@
-use glam::{Mat3, Mat4, Vec2, vec2};
+use glam::{Mat3, Mat4, Vec2, Vec3, Vec4, vec2, vec4};
 use wasm_bindgen::prelude::*;
 use web_sys::{HtmlCanvasElement, KeyboardEvent, WebGl2RenderingContext, window};
+use web_time::Instant;
 
 use crate::{
+    audio::Audio,
+    background::{Backdrop, Background, BackgroundConfig},
     document,
+    drawable::Drawable,
+    effects::Effects,
     font::Font,
+    key_bindings::{Action, KeyBindings, key_name},
     mine_shaft::MineShaft,
-    post_processor::PostProcessor,
-    scribe::{Color, Scribe},
-    text::{Align, Text},
+    physics::{GravityZone, PhysicsConfig},
+    polyline::{CapStyle, LineStyle},
+    post_processor::{Border, CrtParams, FxaaParams, PostProcessor, ToneMap, Upscale},
+    render_target::RenderTarget,
+    replay::{Input, Replay},
+    rng::{Rng, daily_seed, random_seed},
+    scribe::{Color, Scribe, ScribeStats},
+    settings::{Difficulty, Settings, SettingsItem},
+    storage, svg,
+    text::{Align, BakedLabel, BlockLayout, FontId, Text},
+    touch_controls::TouchLayout,
+    uniform_block::{CAMERA_BINDING, CAMERA_BLOCK_SIZE, UniformBlock, camera_block},
 };
 
 pub struct Entity {
     pub transform: Mat3,
     pub vel: Vec2,
+    /// Anticlockwise turning speed, in radians per second
+    pub angular_vel: f32,
+    /// Radius of the circle used for collision against the cave walls
+    pub radius: f32,
 }
 
 impl Entity {
@@ -24,133 +43,1675 @@ impl Entity {
     pub fn forward(&self) -> Vec2 {
         self.transform.transform_vector2(vec2(0.0, 1.0))
     }
+
+    /// Anticlockwise rotation in radians, where 0 faces straight up
+    pub fn rotation(&self) -> f32 {
+        let forward = self.forward();
+        f32::atan2(-forward.x, forward.y)
+    }
+
+    pub fn set_pos(&mut self, pos: Vec2) {
+        self.transform = Mat3::from_scale_angle_translation(Vec2::ONE, self.rotation(), pos);
+    }
+
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.transform = Mat3::from_scale_angle_translation(Vec2::ONE, rotation, self.pos());
+    }
+
+    /// Moves along the current velocity for one fixed update
+    pub fn drift(&mut self, dt: f32) {
+        self.transform = Mat3::from_translation(self.vel * dt) * self.transform;
+    }
+
+    /// Flies for one fixed update: speeds up by `thrust` along the nose, turns by `turn` in
+    /// [-1, 1] where negative turns left, and falls by `gravity`
+    pub fn fly(&mut self, physics: &PhysicsConfig, thrust: f32, turn: f32, gravity: Vec2, dt: f32) {
+        self.vel += self.forward() * thrust * dt;
+
+        // turning right is clockwise, so a negative angular velocity
+        let turn = turn.clamp(-1.0, 1.0);
+        if physics.turn_acceleration > 0.0 {
+            self.angular_vel -= turn * physics.turn_acceleration * dt;
+            // damp exponentially, so the spin settles the same at any tick rate
+            self.angular_vel *= (-physics.angular_damping * dt).exp();
+            self.angular_vel = self
+                .angular_vel
+                .clamp(-physics.turn_rate, physics.turn_rate);
+        } else {
+            self.angular_vel = -turn * physics.turn_rate;
+        }
+        if self.angular_vel != 0.0 {
+            self.transform *= Mat3::from_angle(self.angular_vel * dt);
+        }
+
+        self.vel += gravity * dt;
+        self.vel = self.vel.clamp_length_max(physics.max_speed);
+    }
+}
+
+/// The player's ship, as drawn this frame
+struct Ship<'a> {
+    entity: &'a Entity,
+    thrust: bool,
+    /// The ship blinks while invulnerable
+    visible: bool,
+}
+
+impl Drawable for Ship<'_> {
+    fn draw(&self, scribe: &mut Scribe, _text: &mut Text, _alpha: f32) {
+        let p = |v| self.entity.transform.transform_point2(v);
+
+        if self.visible {
+            let ship = [p(vec2(-7.0, -7.0)), p(vec2(7.0, -7.0)), p(vec2(0.0, 7.0))];
+            // a dim halo around the hull picks the ship out against the walls
+            scribe.draw_glow_line(&ship, 1.0, 3.0, true, Color::WHITE);
+        }
+
+        // draw engine exhaust, a plume tapering away from the ship
+        if self.thrust {
+            let exhaust = [p(vec2(0.0, -8.5)), p(vec2(0.0, -13.0))];
+            scribe.draw_tapered_line(&exhaust, &[5.0, 0.5], Color::YELLOW);
+        }
+    }
+}
+
+/// Names an entity owned by the `AppState`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EntityId {
+    PlayerShip,
+}
+
+/// What the camera is centred on
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CameraTarget {
+    /// A fixed point in world space
+    Point(Vec2),
+    /// Follows an entity as it moves
+    Entity(EntityId),
+}
+
+/// How the cave is drawn
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CaveRenderStyle {
+    /// Only the outline of the walls
+    Outline,
+    /// The solid rock is filled, with no outline
+    Filled,
+    /// The solid rock is filled, and outlined on top
+    FilledOutlined,
+}
+
+impl CaveRenderStyle {
+    /// Parses the lowercase name of a style, such as `"filled-outlined"`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "outline" => Some(CaveRenderStyle::Outline),
+            "filled" => Some(CaveRenderStyle::Filled),
+            "filled-outlined" => Some(CaveRenderStyle::FilledOutlined),
+            _ => None,
+        }
+    }
 }
 
+/// How the cave walls are coloured
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CaveTint {
+    /// Plain white walls
+    Flat,
+    /// Walls shift from pale to red as the cave gets deeper
+    Depth,
+    /// Walls are brighter the more they face the light from above
+    Lit,
+}
+
+impl CaveTint {
+    /// Parses the lowercase name of a tint, such as `"lit"`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "flat" => Some(CaveTint::Flat),
+            "depth" => Some(CaveTint::Depth),
+            "lit" => Some(CaveTint::Lit),
+            _ => None,
+        }
+    }
+}
+
+// The discriminants are exposed to JS through `AppHandle::current_state`, so new states must
+// only ever be appended
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
 enum GameState {
-    SplashScreen,
-    InGame,
-    GameOver,
+    SplashScreen = 0,
+    InGame = 1,
+    GameOver = 2,
+    Settings = 3,
+    Victory = 4,
+    /// A run on hold, which carries on exactly where it left off
+    Paused = 5,
+}
+
+/// A transition between game states, with a snapshot of the run at that moment
+#[derive(Clone, Copy, Debug)]
+pub struct StateChange {
+    /// The new state, numbered as in `AppState::current_state`
+    pub state: u8,
+    pub seed: u32,
+    pub depth: usize,
+    pub health: usize,
+    pub gems: usize,
+    /// A point per metre of depth, plus `GEM_SCORE` per gem
+    pub score: usize,
+    /// Practice runs shouldn't count towards leaderboards
+    pub practice: bool,
 }
 
 pub struct AppState {
+    camera: UniformBlock,
+    start_time: Instant,
     scribe: Scribe,
     post_process: PostProcessor,
+    /// Sound effects, or `None` where the browser has no audio to give us
+    audio: Option<Audio>,
     text: Text,
-    thrust: bool,
-    turn_left: bool,
-    turn_right: bool,
+    font: FontId,
+    /// The splash screen title, which never changes, so is rendered once rather than every frame
+    title_label: Option<BakedLabel>,
+    /// Thrust amount in [0, 1], which only gamepad triggers set between the two
+    thrust: f32,
+    /// How long thrust has been held, in seconds
+    thrust_held: f32,
+    /// Signed turn amount in [-1, 1], negative turns left and positive turns right
+    turn_input: f32,
+    /// Whether the turning keys are held, which cancel out when both are
+    left_held: bool,
+    right_held: bool,
     player_ship: Entity,
+    camera_target: CameraTarget,
+    /// Corners of the rectangle the view is kept within, if any
+    camera_bounds: Option<(Vec2, Vec2)>,
+    /// Where the camera has caught up to, easing towards its focus each fixed update
+    camera_pos: Vec2,
+    /// Roughly how many seconds the camera takes to catch up with its focus. 0 locks it there
+    camera_smoothing: f32,
     mine_shaft: MineShaft,
+    background: BackgroundConfig,
+    backdrop: Background,
+    cave_style: CaveRenderStyle,
+    cave_tint: CaveTint,
+    effects: Effects,
     max_depth: usize,
+    /// Gems waiting to be picked up in the current run
+    gems: Vec<Vec2>,
+    /// Fuel cans waiting to be picked up in the current run
+    fuel_cans: Vec<Vec2>,
+    /// Number of `PICKUP_BAND_HEIGHT` bands of the shaft pickups have been scattered over so far
+    pickup_bands: u32,
+    gems_collected: usize,
+    gem_color: Color,
+    fuel_color: Color,
+    /// Seconds of thrust left. The engine does nothing once it runs dry
+    fuel: f32,
     health: usize,
     invulnerability_ticks: usize,
+    /// Strength of the red flash after taking damage, decaying from 1 to 0
+    damage_flash: f32,
+    /// Remaining fixed updates to freeze the simulation for after a hard impact
+    hitstop_ticks: usize,
+    /// How hard the view is shaking after an impact, decaying from 1 to 0
+    shake: f32,
+    /// Randomness for purely visual effects, such as the shake and the exhaust. Kept apart from
+    /// `rng` so they can't change a replay
+    visual_rng: Rng,
+    /// Exhaust particles due but not yet emitted, carried between ticks
+    exhaust_backlog: f32,
     game_state: GameState,
+    /// Seed of the current run, from which all gameplay randomness derives
+    seed: u32,
+    rng: Rng,
+    settings: Settings,
+    /// The ship's transform at the moment the last run ended, until a thumbnail has been taken
+    pending_thumbnail: Option<Mat3>,
+    /// A PNG `data:` URL of the final frame of the last run, kept in local storage between visits
+    last_run_thumbnail: Option<String>,
+    /// Where thumbnails are rendered, kept around for the next run unless the canvas changes shape
+    thumbnail_target: Option<RenderTarget>,
+    /// Index into `SettingsItem::ALL` of the highlighted settings menu entry
+    settings_selection: usize,
+    /// Where the settings menu goes back to when closed, either the splash screen or a paused run
+    settings_return: GameState,
+    physics: PhysicsConfig,
+    /// Overrides the difficulty's starting health when set
+    starting_health: Option<usize>,
+    /// Depth that ends the run in victory. `None` plays endlessly
+    goal_depth: Option<usize>,
+    /// Where the camera looked last frame, for motion blur
+    last_camera_focus: Option<Vec2>,
+    /// Whether the current run is a practice run, where the ship takes no damage
+    practice: bool,
+    key_bindings: KeyBindings,
+    touch_layout: TouchLayout,
+    /// Whether fingers on the screen set the thrust and turning last, so lifting them all stops
+    /// the ship without overriding the keyboard otherwise
+    touch_steering: bool,
+    /// Whether a gamepad set the thrust and turning last, as with `touch_steering`
+    pad_steering: bool,
+    /// Which gamepad buttons were down last poll, as `(confirm, start)`, so holding one acts once
+    pad_buttons: (bool, bool),
+    /// Whether to overlay collision information, toggled with the backquote key
+    debug: bool,
+    /// Vertex counts from the scribe over the last frame, for the debug overlay
+    scribe_stats: ScribeStats,
+    /// While debugging, the simulation can be frozen and advanced one update at a time
+    frame_stepping: bool,
+    step_once: bool,
+    /// Whether the game stops rendering and simulating while the canvas can't be seen
+    pause_when_hidden: bool,
+    /// Whether the game stops rendering and simulating while the page doesn't have focus
+    pause_on_blur: bool,
+    /// Regions where gravity pulls other than downwards. The first zone containing the ship wins
+    gravity_zones: Vec<GravityZone>,
+    /// Which way gravity currently pulls, turning towards the zone the ship is in
+    gravity_dir: Vec2,
+    /// Opacity of the backdrop behind menus
+    menu_dim: f32,
+    /// State transitions not yet reported to the page
+    state_changes: Vec<StateChange>,
+    /// Fixed updates since the current run started, for timing it
+    run_ticks: u32,
+    /// Inputs of the current run, unless it's a practice run or a replay
+    recording: Option<Replay>,
+    /// The recording of the last run to finish
+    last_replay: Option<Replay>,
+    /// A replay being played back in place of the player's input
+    playback: Option<Replay>,
+    /// How long the last winning run took, and the best time for its goal, in seconds
+    victory_times: Option<(f32, f32)>,
+}
+
+const FONT: &[u8] = include_bytes!("../assets/KarmaticArcade-6Yrp1.ttf");
+
+/// Size of the marching squares cells the cave walls are traced with, in world units
+const CAVE_RESOLUTION: f32 = 5.0;
+
+/// Half the height of the view, in world units
+const VIEW_HALF_HEIGHT: f32 = 100.0;
+
+/// Collision radius of the player's ship
+const SHIP_RADIUS: f32 = 7.0;
+
+/// Impacts slower than this make no sound, so sliding along a wall stays quiet
+const IMPACT_SOUND_MIN_SPEED: f32 = 5.0;
+
+/// Impacts slower than this don't freeze the game
+const HITSTOP_MIN_SPEED: f32 = 20.0;
+
+/// Longest freeze after an impact, in fixed updates
+const MAX_HITSTOP_TICKS: usize = 9;
+
+/// Default for `AppStateBuilder::camera_smoothing`, in seconds
+const DEFAULT_CAMERA_SMOOTHING: f32 = 0.15;
+
+/// Exhaust particles emitted a second when the engine first fires
+const MIN_EXHAUST_RATE: f32 = 20.0;
+
+/// Exhaust particles emitted a second once the engine has been running for `EXHAUST_RAMP_TIME`
+const MAX_EXHAUST_RATE: f32 = 80.0;
+
+/// Seconds of thrust for the exhaust to build up to its full rate
+const EXHAUST_RAMP_TIME: f32 = 0.5;
+
+/// Furthest the view is thrown by the hardest impact, in world units
+const MAX_SHAKE_OFFSET: f32 = 3.0;
+
+/// Seconds the shake from the hardest impact takes to settle
+const SHAKE_DURATION: f32 = 0.4;
+
+/// Seconds the red flash lasts after taking damage
+const DAMAGE_FLASH_DURATION: f32 = 0.5;
+
+/// Peak strength of the damage flash
+const DAMAGE_FLASH_INTENSITY: f32 = 0.5;
+
+/// Colour of the whole screen flash on taking damage, with its peak strength as the alpha
+const SCREEN_FLASH_COLOR: Vec4 = vec4(1.0, 0.15, 0.1, 0.3);
+
+/// Seconds the whole screen flash lasts
+const SCREEN_FLASH_DURATION: f32 = 0.15;
+
+/// How fast gravity swings round to a new direction, in radians per second
+const GRAVITY_TURN_RATE: f32 = std::f32::consts::PI;
+
+/// Height in pixels of the thumbnail taken when a run ends
+const THUMBNAIL_HEIGHT: i32 = 90;
+
+/// Local storage key the last run's thumbnail is saved under
+const THUMBNAIL_KEY: &str = "fathom.last_run_thumbnail";
+
+const SETTINGS_FONT_SIZE: f32 = 5.0;
+
+/// The smaller lines of help on the splash screen and debug overlay
+const HINT_LAYOUT: BlockLayout = BlockLayout {
+    font_size: 3.0,
+    line_gap: 4.0,
+    align: Align::Center,
+};
+
+const TITLE_FONT_SIZE: f32 = 18.0;
+
+/// Characters of text room is made for up front, enough for the busiest menu. The debug
+/// overlay shows the peak actually queued, to tune this against
+const TEXT_RESERVE_GLYPHS: usize = 384;
+
+/// Bezier segments each reserved character is expected to need, on average
+const TEXT_SEGMENTS_PER_GLYPH: usize = 16;
+
+/// Brightness the scene must reach to bloom on the splash screen, high so the title stays crisp
+const SPLASH_BLOOM_THRESHOLD: f32 = 0.8;
+
+/// Brightness the scene must reach to bloom while flying, low so the cave glows
+const IN_GAME_BLOOM_THRESHOLD: f32 = 0.35;
+
+/// Brightness the scene must reach to bloom in the other menus
+const MENU_BLOOM_THRESHOLD: f32 = 0.5;
+
+/// How fast the background grid fades in and out on the splash screen, in radians per second
+const SPLASH_GRID_PULSE: f32 = 1.5;
+
+/// Fill colour of solid rock, when the cave is drawn filled
+const ROCK_COLOR: Vec4 = vec4(0.02, 0.02, 0.15, 1.0);
+
+/// Depth at which the cave walls reach their deepest tint
+const DEEP_TINT_DEPTH: f32 = 3000.0;
+
+/// Colour of the cave walls at a given world height, from pale near the surface to red deep down
+fn depth_tint(y: f32) -> Vec4 {
+    const SHALLOW: Vec4 = vec4(0.85, 0.9, 1.0, 1.0);
+    const DEEP: Vec4 = vec4(1.0, 0.2, 0.15, 1.0);
+
+    let t = (-y / DEEP_TINT_DEPTH).clamp(0.0, 1.0);
+    SHALLOW.lerp(DEEP, t)
+}
+
+/// Colour gems are drawn in
+const GEM_COLOR: Vec4 = vec4(0.3, 1.0, 0.8, 1.0);
+
+/// Half the height of a gem, in world units
+const GEM_SIZE: f32 = 3.0;
+
+/// Points a gem is worth, on top of a point per metre of depth
+const GEM_SCORE: usize = 50;
+
+/// Colour fuel cans are drawn in
+const FUEL_COLOR: Vec4 = vec4(1.0, 0.55, 0.1, 1.0);
+
+/// Half the height of a fuel can, in world units
+const FUEL_CAN_SIZE: f32 = 3.0;
+
+/// Seconds of thrust a full tank holds
+const MAX_FUEL: f32 = 30.0;
+
+/// Length of the fuel gauge when the tank is full, in world units
+const FUEL_GAUGE_LENGTH: f32 = 30.0;
+
+/// Seconds of thrust each fuel can adds, up to a full tank
+const FUEL_CAN_AMOUNT: f32 = 10.0;
+
+/// How near the ship's centre must come to a gem or fuel can to pick it up
+const PICKUP_RADIUS: f32 = SHIP_RADIUS + GEM_SIZE;
+
+/// Pickups are scattered over the shaft in bands of this height, as the ship nears each one
+const PICKUP_BAND_HEIGHT: f32 = 400.0;
+
+/// How far below the deepest point reached pickups are already in place, so they're never seen
+/// appearing
+const PICKUP_LOOKAHEAD: f32 = 400.0;
+
+const GEMS_PER_BAND: usize = 3;
+
+const FUEL_CANS_PER_BAND: usize = 1;
+
+/// How far either side of the middle of the shaft pickups may be, which covers the side tunnels
+const PICKUP_SPREAD: f32 = 300.0;
+
+/// How far pickups sit from the walls, so they can be reached without scraping a wall
+const PICKUP_CLEARANCE: f32 = 12.0;
+
+/// Mixes the band into the run's seed, so neighbouring bands get unrelated pickups
+const PICKUP_BAND_HASH: u32 = 0x85eb_ca6b;
+
+/// Sets fuel cans' seeds apart from the gems' in the same band
+const FUEL_SEED_SALT: u32 = 0x27d4_eb2f;
+
+/// Particles in the burst when a gem is picked up
+const GEM_SPARKLES: usize = 8;
+
+/// Direction towards the light on `CaveTint::Lit` walls, a little off vertical
+const WALL_LIGHT_DIR: Vec2 = vec2(0.3, 0.95);
+
+/// Brightness of `CaveTint::Lit` walls facing away from the light
+const WALL_AMBIENT: f32 = 0.35;
+
+/// Colour of a wall facing along `normal`, for `CaveTint::Lit`
+fn wall_light(normal: Vec2) -> Vec4 {
+    let diffuse = normal.dot(WALL_LIGHT_DIR.normalize()).max(0.0);
+    let brightness = WALL_AMBIENT + (1.0 - WALL_AMBIENT) * diffuse;
+    Vec3::splat(brightness).extend(1.0)
+}
+
+/// What to press on the pause menu, naming the first key bound to pause
+fn pause_hint(bindings: &KeyBindings) -> String {
+    match bindings.pause.first() {
+        Some(code) => format!(
+            "Press {} to resume, O for settings, Q to quit",
+            key_name(code)
+        ),
+        None => "Press O for settings, Q to quit".to_string(),
+    }
+}
+
+/// Removes and returns the points within `radius` of `p`
+fn take_within(points: &mut Vec<Vec2>, p: Vec2, radius: f32) -> Vec<Vec2> {
+    let (taken, kept) = std::mem::take(points)
+        .into_iter()
+        .partition(|point| point.distance(p) <= radius);
+    *points = kept;
+    taken
+}
+
+/// How much of the scene blooms in each state: little on the splash screen, and plenty in play
+fn bloom_threshold(state: GameState) -> f32 {
+    match state {
+        GameState::SplashScreen => SPLASH_BLOOM_THRESHOLD,
+        GameState::InGame => IN_GAME_BLOOM_THRESHOLD,
+        _ => MENU_BLOOM_THRESHOLD,
+    }
+}
+
+/// Vertical center of a row in the settings menu
+fn settings_row_y(center_y: f32, row: usize) -> f32 {
+    center_y + 25.0 - row as f32 * 10.0
+}
+
+/// Configures an `AppState` before creating it. The defaults are the standard game, and pages
+/// set the rest through the options passed to `fathom_start`
+pub struct AppStateBuilder {
+    font: Option<Vec<u8>>,
+    starting_health: Option<usize>,
+    difficulty: Option<Difficulty>,
+    physics: PhysicsConfig,
+    shaft_size: Vec2,
+    cave_noise: Option<(u32, f32, f32)>,
+    background: BackgroundConfig,
+    cave_style: CaveRenderStyle,
+    cave_tint: CaveTint,
+    border: Option<Border>,
+    text_shadows: bool,
+    motion_blur: f32,
+    camera_bounds: Option<(Vec2, Vec2)>,
+    camera_smoothing: f32,
+    key_bindings: KeyBindings,
+    touch_layout: TouchLayout,
+    goal_depth: Option<usize>,
+    pause_when_hidden: bool,
+    pause_on_blur: bool,
+    gravity_zones: Vec<GravityZone>,
+    menu_backdrop: Vec4,
+    internal_resolution: Option<(i32, i32)>,
+    upscale: Upscale,
+    supersample: i32,
+    bloom_scale: f32,
+    grain: f32,
+}
+
+impl Default for AppStateBuilder {
+    fn default() -> Self {
+        Self {
+            font: None,
+            starting_health: None,
+            difficulty: None,
+            physics: PhysicsConfig::default(),
+            shaft_size: vec2(760.0, 340.0),
+            cave_noise: None,
+            background: BackgroundConfig::default(),
+            cave_style: CaveRenderStyle::Outline,
+            cave_tint: CaveTint::Depth,
+            border: None,
+            text_shadows: false,
+            motion_blur: 0.0,
+            camera_bounds: None,
+            camera_smoothing: DEFAULT_CAMERA_SMOOTHING,
+            key_bindings: KeyBindings::default(),
+            touch_layout: TouchLayout::default(),
+            goal_depth: None,
+            pause_when_hidden: true,
+            pause_on_blur: false,
+            gravity_zones: vec![],
+            menu_backdrop: vec4(0.0, 0.0, 0.0, 0.6),
+            internal_resolution: None,
+            upscale: Upscale::Nearest,
+            supersample: 1,
+            bloom_scale: 1.0,
+            grain: 0.03,
+        }
+    }
+}
+
+impl AppStateBuilder {
+    /// A TrueType font to draw all text with, instead of the built-in one
+    pub fn font(mut self, data: Vec<u8>) -> Self {
+        self.font = Some(data);
+        self
+    }
+
+    /// Health at the start of each run, regardless of difficulty
+    pub fn starting_health(mut self, health: usize) -> Self {
+        self.starting_health = Some(health);
+        self
+    }
+
+    /// Difficulty to play at, instead of the one saved in the player's settings
+    pub fn difficulty(mut self, difficulty: Difficulty) -> Self {
+        self.difficulty = Some(difficulty);
+        self
+    }
+
+    pub fn physics(mut self, physics: PhysicsConfig) -> Self {
+        self.physics = physics;
+        self
+    }
+
+    /// Size of the area of the mine shaft meshed around the camera each frame
+    pub fn shaft_size(mut self, width: f32, height: f32) -> Self {
+        self.shaft_size = vec2(width, height);
+        self
+    }
+
+    /// Shapes the fractal noise the cave walls are carved with: how many `octaves` of noise to
+    /// sum, how much faster each varies than the last (`lacunarity`), and how much weaker it is
+    /// (`persistence`). A single octave gives the smooth walls of plain Perlin noise. Defaults to
+    /// 4 octaves, each twice the frequency and half the strength of the last
+    pub fn cave_noise(mut self, octaves: u32, lacunarity: f32, persistence: f32) -> Self {
+        self.cave_noise = Some((octaves, lacunarity, persistence));
+        self
+    }
+
+    pub fn background(mut self, background: BackgroundConfig) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub fn cave_style(mut self, style: CaveRenderStyle) -> Self {
+        self.cave_style = style;
+        self
+    }
+
+    pub fn cave_tint(mut self, tint: CaveTint) -> Self {
+        self.cave_tint = tint;
+        self
+    }
+
+    /// Frames the playfield with a glowing border. Off by default
+    pub fn border(mut self, border: Option<Border>) -> Self {
+        self.border = border;
+        self
+    }
+
+    /// Draws soft shadows beneath all text, for legibility over bright scenes. Off by default
+    pub fn text_shadows(mut self, enabled: bool) -> Self {
+        self.text_shadows = enabled;
+        self
+    }
+
+    /// Smears the view along the camera's movement. 1 streaks each frame across the distance
+    /// moved since the last; 0, the default, disables it
+    pub fn motion_blur(mut self, strength: f32) -> Self {
+        self.motion_blur = strength;
+        self
+    }
+
+    /// Roughly how many seconds the camera takes to catch up with the ship. 0 locks it to the ship
+    pub fn camera_smoothing(mut self, seconds: f32) -> Self {
+        self.camera_smoothing = seconds;
+        self
+    }
+
+    /// Which keys fly the ship and pause. WASD and the arrow keys by default, with P or Escape to
+    /// pause. Bindings that use any of `RESERVED_KEYS` are ignored
+    pub fn key_bindings(mut self, bindings: KeyBindings) -> Self {
+        match bindings.reserved_clash() {
+            Some(code) => log::warn!("{code} is reserved, keeping the default key bindings"),
+            None => self.key_bindings = bindings,
+        }
+        self
+    }
+
+    /// Which parts of the screen fly the ship when touched. Thirds across the screen by default,
+    /// with thrust along the bottom of the middle
+    pub fn touch_layout(mut self, layout: TouchLayout) -> Self {
+        self.touch_layout = layout;
+        self
+    }
+
+    /// Keeps the view within the rectangle between two corners. `None`, the default, follows the
+    /// ship anywhere
+    pub fn camera_bounds(mut self, bounds: Option<(Vec2, Vec2)>) -> Self {
+        self.camera_bounds = bounds;
+        self
+    }
+
+    pub fn goal_depth(mut self, goal_depth: Option<usize>) -> Self {
+        self.goal_depth = goal_depth;
+        self
+    }
+
+    pub fn pause_when_hidden(mut self, enabled: bool) -> Self {
+        self.pause_when_hidden = enabled;
+        self
+    }
+
+    pub fn pause_on_blur(mut self, enabled: bool) -> Self {
+        self.pause_on_blur = enabled;
+        self
+    }
+
+    /// Regions of the world where gravity pulls in another direction. The ship still thrusts and
+    /// turns relative to itself, so these make for sections that have to be flown differently
+    pub fn gravity_zones(mut self, zones: Vec<GravityZone>) -> Self {
+        self.gravity_zones = zones;
+        self
+    }
+
+    /// Colour and opacity of the backdrop that darkens the scene behind menus. Black at 60%
+    /// opacity by default; zero opacity leaves the scene undimmed
+    pub fn menu_backdrop(mut self, color: Vec4) -> Self {
+        self.menu_backdrop = color;
+        self
+    }
+
+    /// Renders at a fixed size, such as 1280x720, scaled to fit the canvas. `None`, the default,
+    /// renders at the canvas's own resolution
+    pub fn internal_resolution(mut self, resolution: Option<(i32, i32)>) -> Self {
+        self.internal_resolution = resolution;
+        self
+    }
+
+    /// How a fixed internal resolution is scaled to the canvas. Nearest by default
+    pub fn upscale(mut self, upscale: Upscale) -> Self {
+        self.upscale = upscale;
+        self
+    }
+
+    /// Renders the scene at this many times the resolution in each axis, up to 4, and averages
+    /// it back down, smoothing thin lines at the cost of fill rate. 1, the default, disables it
+    pub fn supersample(mut self, factor: i32) -> Self {
+        self.supersample = factor;
+        self
+    }
+
+    /// Blurs the bloom at this fraction of the resolution, down to 1/8, which costs far less and
+    /// looks much the same. 1, the default, blurs at full resolution
+    pub fn bloom_scale(mut self, scale: f32) -> Self {
+        self.bloom_scale = scale;
+        self
+    }
+
+    /// Strength of the animated film grain over the final image, from 0 for none to 1 for
+    /// nothing but noise. 0.03 by default, which is felt more than seen
+    pub fn grain(mut self, intensity: f32) -> Self {
+        self.grain = intensity;
+        self
+    }
+
+    pub fn build(self, context: &WebGl2RenderingContext) -> Result<AppState, JsValue> {
+        // before any renderer is made, so each finds its program ready to claim
+        crate::precompile_shaders(context);
+
+        let mut settings = Settings::load();
+        if let Some(difficulty) = self.difficulty {
+            settings.difficulty = difficulty;
+        }
+
+        let seed = random_seed();
+
+        let mut text = Text::new(context);
+        let font = match &self.font {
+            Some(data) => text.add_font(Font::from_slice(data, 0)),
+            None => text.add_font(Font::from_slice(FONT, 0)),
+        };
+        text.reserve(TEXT_RESERVE_GLYPHS, TEXT_SEGMENTS_PER_GLYPH);
+        if self.text_shadows {
+            text.set_shadow(vec2(0.5, -0.5), vec4(0.0, 0.0, 0.0, 0.8), 1.0);
+        }
+
+        let mut app_state = AppState {
+            camera: UniformBlock::new(context, CAMERA_BLOCK_SIZE),
+            start_time: Instant::now(),
+            scribe: Scribe::new(context),
+            post_process: PostProcessor::new(context)?,
+            audio: Audio::new()
+                .inspect_err(|e| log::warn!("running without sound: {:?}", e))
+                .ok(),
+            text,
+            font,
+            title_label: None,
+            thrust: 0.0,
+            thrust_held: 0.0,
+            turn_input: 0.0,
+            left_held: false,
+            right_held: false,
+            player_ship: Entity {
+                transform: Mat3::IDENTITY,
+                vel: Vec2::ZERO,
+                angular_vel: 0.0,
+                radius: SHIP_RADIUS,
+            },
+            camera_target: CameraTarget::Entity(EntityId::PlayerShip),
+            camera_bounds: None,
+            camera_pos: Vec2::ZERO,
+            camera_smoothing: self.camera_smoothing,
+            mine_shaft: MineShaft::with_seed(self.shaft_size.x, self.shaft_size.y, seed),
+            background: self.background,
+            backdrop: Background::new(context)?,
+            cave_style: self.cave_style,
+            cave_tint: self.cave_tint,
+            effects: Effects::new(),
+            max_depth: 0,
+            gems: vec![],
+            fuel_cans: vec![],
+            pickup_bands: 1,
+            gems_collected: 0,
+            gem_color: Color::WHITE,
+            fuel_color: Color::WHITE,
+            fuel: MAX_FUEL,
+            health: self
+                .starting_health
+                .unwrap_or(settings.difficulty.starting_health()),
+            invulnerability_ticks: 0,
+            damage_flash: 0.0,
+            hitstop_ticks: 0,
+            shake: 0.0,
+            visual_rng: Rng::new(random_seed()),
+            exhaust_backlog: 0.0,
+            game_state: GameState::SplashScreen,
+            seed,
+            rng: Rng::new(seed),
+            settings,
+            pending_thumbnail: None,
+            last_run_thumbnail: storage::load(THUMBNAIL_KEY),
+            thumbnail_target: None,
+            settings_selection: 0,
+            settings_return: GameState::SplashScreen,
+            physics: self.physics,
+            starting_health: self.starting_health,
+            goal_depth: self.goal_depth,
+            last_camera_focus: None,
+            practice: false,
+            key_bindings: self.key_bindings,
+            touch_layout: self.touch_layout,
+            touch_steering: false,
+            pad_steering: false,
+            pad_buttons: (false, false),
+            debug: false,
+            scribe_stats: ScribeStats::default(),
+            frame_stepping: false,
+            step_once: false,
+            pause_when_hidden: self.pause_when_hidden,
+            pause_on_blur: self.pause_on_blur,
+            gravity_zones: self.gravity_zones,
+            gravity_dir: Vec2::NEG_Y,
+            menu_dim: self.menu_backdrop.w,
+            state_changes: Vec::new(),
+            run_ticks: 0,
+            recording: None,
+            last_replay: None,
+            playback: None,
+            victory_times: None,
+        };
+        app_state.set_camera_bounds(self.camera_bounds);
+        app_state.gem_color = app_state.scribe.define_color("gem", GEM_COLOR);
+        app_state.fuel_color = app_state.scribe.define_color("fuel", FUEL_COLOR);
+        if let Some((octaves, lacunarity, persistence)) = self.cave_noise {
+            app_state.mine_shaft.noise_octaves = octaves;
+            app_state.mine_shaft.noise_lacunarity = lacunarity;
+            app_state.mine_shaft.noise_persistence = persistence;
+        }
+        app_state.post_process.set_border(self.border);
+        app_state.post_process.set_motion_blur(self.motion_blur);
+        app_state
+            .post_process
+            .set_dim_color(self.menu_backdrop.truncate());
+        app_state
+            .post_process
+            .set_internal_resolution(self.internal_resolution);
+        app_state.post_process.set_upscale(self.upscale);
+        app_state.post_process.set_supersample(self.supersample);
+        app_state.post_process.set_bloom_scale(self.bloom_scale);
+        app_state.post_process.set_grain(self.grain);
+        app_state
+            .post_process
+            .set_bloom_threshold(bloom_threshold(app_state.game_state));
+        app_state.apply_settings();
+
+        Ok(app_state)
+    }
 }
 
-const FONT: &[u8] = include_bytes!("../assets/KarmaticArcade-6Yrp1.ttf");
+impl AppState {
+    /// Health at the start of a run
+    fn starting_health(&self) -> usize {
+        self.starting_health
+            .unwrap_or(self.difficulty().starting_health())
+    }
+
+    fn apply_settings(&mut self) {
+        self.post_process.set_bloom(self.settings.bloom);
+        self.post_process.set_crt(self.settings.crt);
+        let mut crt_params = CrtParams::default();
+        if self.settings.flat_screen {
+            crt_params.curvature = 0.0;
+        }
+        self.post_process.set_crt_params(crt_params);
+        self.post_process.set_fxaa(self.settings.fxaa);
+        if let Some(audio) = &self.audio {
+            audio.set_muted(!self.settings.sound);
+        }
+    }
+
+    pub fn current_state(&self) -> u8 {
+        self.game_state as u8
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn last_run_thumbnail(&self) -> Option<String> {
+        self.last_run_thumbnail.clone()
+    }
+
+    /// Moves the ship from outside the game, e.g. for a scripted tutorial. Only allowed during a
+    /// run, and returns whether the ship was moved
+    pub fn set_ship_pos(&mut self, pos: Vec2) -> bool {
+        if self.game_state != GameState::InGame {
+            return false;
+        }
+
+        self.player_ship.set_pos(pos);
+        self.snap_camera();
+        true
+    }
+
+    pub fn set_ship_rotation(&mut self, rotation: f32) -> bool {
+        if self.game_state != GameState::InGame {
+            return false;
+        }
+
+        self.player_ship.set_rotation(rotation);
+        true
+    }
+
+    pub fn set_ship_vel(&mut self, vel: Vec2) -> bool {
+        if self.game_state != GameState::InGame {
+            return false;
+        }
+
+        self.player_ship.vel = vel;
+        true
+    }
+
+    pub fn entity(&self, id: EntityId) -> &Entity {
+        match id {
+            EntityId::PlayerShip => &self.player_ship,
+        }
+    }
+
+    /// Points the camera at something other than the player, e.g. to show the crash site
+    pub fn set_camera_target(&mut self, target: CameraTarget) {
+        self.camera_target = target;
+    }
+
+    /// Roughly how many seconds the camera takes to catch up with what it follows, so it doesn't
+    /// jerk about with the ship. 0 locks it in place, as does turning smooth camera off in the
+    /// settings
+    pub fn set_camera_smoothing(&mut self, seconds: f32) {
+        self.camera_smoothing = seconds.max(0.0);
+    }
+
+    fn smooths_camera(&self) -> bool {
+        self.settings.smooth_camera && self.camera_smoothing > 0.0
+    }
+
+    /// Moves the camera straight to its focus, for when the ship jumps somewhere new
+    fn snap_camera(&mut self) {
+        self.camera_pos = self.camera_focus();
+    }
+
+    /// The world position the camera is following
+    fn camera_focus(&self) -> Vec2 {
+        match self.camera_target {
+            CameraTarget::Point(p) => p,
+            CameraTarget::Entity(id) => self.entity(id).pos(),
+        }
+    }
+
+    /// Where the view is centred, given half its size: the camera's focus, kept far enough inside
+    /// the camera bounds that the view doesn't cross them. Bounds smaller than the view are
+    /// centred on instead
+    fn view_center(&self, half_extents: Vec2) -> Vec2 {
+        let focus = if self.smooths_camera() {
+            self.camera_pos
+        } else {
+            self.camera_focus()
+        };
+        let Some((min, max)) = self.camera_bounds else {
+            return focus;
+        };
+
+        let lo = min + half_extents;
+        let hi = max - half_extents;
+        let mid = (min + max) * 0.5;
+        vec2(
+            if lo.x <= hi.x {
+                focus.x.clamp(lo.x, hi.x)
+            } else {
+                mid.x
+            },
+            if lo.y <= hi.y {
+                focus.y.clamp(lo.y, hi.y)
+            } else {
+                mid.y
+            },
+        )
+    }
+
+    /// Remaps the keys that fly the ship and pause. Bindings that use any of `RESERVED_KEYS` are
+    /// rejected, returning false and keeping the current ones
+    pub fn set_key_bindings(&mut self, bindings: KeyBindings) -> bool {
+        if let Some(code) = bindings.reserved_clash() {
+            log::warn!("{code} is reserved, keeping the current key bindings");
+            return false;
+        }
+
+        self.key_bindings = bindings;
+        // whatever was held may no longer be bound, and its release would go unnoticed
+        self.release_controls();
+        true
+    }
+
+    /// Tunes the anti-aliasing, while the player has it turned on
+    pub fn set_fxaa_params(&mut self, params: FxaaParams) {
+        self.post_process.set_fxaa_params(params);
+    }
+
+    /// Changes how bright colours are brought into range, or with `None` skips tone mapping
+    /// altogether
+    pub fn set_tonemap(&mut self, tonemap: Option<ToneMap>) {
+        self.post_process.set_tonemap_enabled(tonemap.is_some());
+        if let Some(tonemap) = tonemap {
+            self.post_process.set_tonemap(tonemap);
+        }
+    }
+
+    /// Changes what fills the screen behind the cave
+    pub fn set_backdrop(&mut self, backdrop: Backdrop) {
+        self.background.backdrop = backdrop;
+    }
+
+    /// Changes whether the rock is filled, outlined or both
+    pub fn set_cave_style(&mut self, style: CaveRenderStyle) {
+        self.cave_style = style;
+    }
+
+    /// Changes how the cave walls are coloured
+    pub fn set_cave_tint(&mut self, tint: CaveTint) {
+        self.cave_tint = tint;
+    }
+
+    /// Moves the parts of the screen that fly the ship when touched
+    pub fn set_touch_layout(&mut self, layout: TouchLayout) {
+        self.touch_layout = layout;
+    }
+
+    /// Limits the view to the rectangle between two corners, or `None` to follow the camera's
+    /// target anywhere
+    pub fn set_camera_bounds(&mut self, bounds: Option<(Vec2, Vec2)>) {
+        self.camera_bounds = bounds.map(|(a, b)| (a.min(b), a.max(b)));
+    }
+
+    pub fn on_resize(&mut self, canvas: &HtmlCanvasElement, context: &WebGl2RenderingContext) {
+        let device_pixel_ratio = window().unwrap().device_pixel_ratio();
+        let document = document();
+        let w = document.body().unwrap().client_width() as f64 * device_pixel_ratio;
+        let h = document.body().unwrap().client_height() as f64 * device_pixel_ratio;
+
+        canvas.set_width(w as u32);
+        canvas.set_height(h as u32);
+        context.viewport(0, 0, w as i32, h as i32);
+
+        self.post_process.on_resize(w as i32, h as i32);
+
+        // the title only needs re-rendering when the screen's pixel density changes
+        let (_, h) = self.post_process.render_size();
+        let pixels_per_unit = h as f32 / (VIEW_HALF_HEIGHT * 2.0);
+        self.text.set_pixels_per_unit(pixels_per_unit);
+        self.scribe.set_pixels_per_unit(pixels_per_unit);
+        if self
+            .title_label
+            .as_ref()
+            .is_none_or(|label| label.pixels_per_unit() != pixels_per_unit)
+        {
+            self.title_label = self
+                .text
+                .bake(self.font, TITLE_FONT_SIZE, "FATHOM", Vec4::ONE)
+                .inspect_err(|e| log::warn!("failed to bake title: {:?}", e))
+                .ok();
+        }
+    }
+
+    pub fn on_keydown(&mut self, key: KeyboardEvent) {
+        // browsers hold sound back until the player interacts with the page
+        if let Some(audio) = &self.audio {
+            audio.resume();
+        }
+
+        match key.code().as_str() {
+            "Backquote" => {
+                self.debug = !self.debug;
+                self.frame_stepping = false;
+                return;
+            }
+            "KeyF" if self.debug => {
+                self.frame_stepping = !self.frame_stepping;
+                return;
+            }
+            "Period" if self.frame_stepping => {
+                self.step_once = true;
+                return;
+            }
+            // compare the vertex counts with and without straightening out polylines
+            "KeyM" if self.debug => {
+                let merge = !self.scribe.merge_collinear();
+                self.scribe.set_merge_collinear(merge);
+                return;
+            }
+            _ => {}
+        }
+
+        if self.game_state == GameState::Settings {
+            self.on_settings_keydown(&key.code());
+            return;
+        }
+
+        let action = self.key_bindings.action(&key.code());
+
+        if self.game_state == GameState::Paused {
+            if action == Some(Action::Pause) {
+                self.set_state(GameState::InGame);
+            } else if key.code() == "KeyO" {
+                self.open_settings();
+            } else if key.code() == "KeyQ" {
+                self.return_to_splash();
+            }
+            return;
+        }
+
+        match action {
+            Some(Action::Thrust) => self.thrust = 1.0,
+            Some(Action::TurnLeft) => {
+                self.left_held = true;
+                self.steer_from_keys();
+            }
+            Some(Action::TurnRight) => {
+                self.right_held = true;
+                self.steer_from_keys();
+            }
+            _ => log::info!("key down {:?}", key.code()),
+        }
+
+        match self.game_state {
+            GameState::SplashScreen if key.code() == "KeyO" => self.open_settings(),
+            GameState::InGame if key.code() == "KeyQ" => self.return_to_splash(),
+            GameState::InGame if action == Some(Action::Pause) => self.pause(),
+            GameState::InGame if self.practice && key.code() == "KeyR" => {
+                self.player_ship.transform = Mat3::IDENTITY;
+                self.player_ship.vel = Vec2::ZERO;
+                self.player_ship.angular_vel = 0.0;
+            }
+            GameState::SplashScreen if key.code() == "KeyT" => self.start_run(daily_seed(), false),
+            GameState::SplashScreen => self.start_run(random_seed(), key.code() == "KeyP"),
+            GameState::GameOver | GameState::Victory => {
+                self.start_run(random_seed(), self.practice)
+            }
+            _ => {}
+        }
+    }
+
+    /// Opens the settings menu over the current screen, which it returns to when closed
+    fn open_settings(&mut self) {
+        self.settings_return = self.game_state;
+        self.set_state(GameState::Settings);
+        self.settings_selection = 0;
+    }
+
+    /// Whether a run is on hold, either paused or with the settings menu opened over it
+    fn run_on_hold(&self) -> bool {
+        match self.game_state {
+            GameState::Paused => true,
+            GameState::Settings => self.settings_return == GameState::Paused,
+            _ => false,
+        }
+    }
+
+    /// Puts the run on hold. Held keys are let go, since their release may happen while paused
+    fn pause(&mut self) {
+        self.set_state(GameState::Paused);
+        self.release_controls();
+        self.shake = 0.0;
+    }
+
+    /// Lets go of thrust and turning, whichever input was holding them
+    fn release_controls(&mut self) {
+        self.thrust = 0.0;
+        self.turn_input = 0.0;
+        self.left_held = false;
+        self.right_held = false;
+    }
+
+    /// Turns by whichever turning keys are held
+    fn steer_from_keys(&mut self) {
+        self.turn_input = self.right_held as i32 as f32 - self.left_held as i32 as f32;
+    }
+
+    /// Starts a fresh run. Practice runs can't take damage, and don't count towards best times
+    fn start_run(&mut self, seed: u32, practice: bool) {
+        self.practice = practice;
+        self.run_ticks = 0;
+        self.seed = seed;
+        self.mine_shaft.set_seed(seed);
+        self.recording =
+            (!practice && self.playback.is_none()).then(|| Replay::new(seed, self.difficulty()));
+        self.rng = Rng::new(self.seed);
+        self.player_ship.transform = Mat3::IDENTITY;
+        self.player_ship.vel = Vec2::ZERO;
+        self.player_ship.angular_vel = 0.0;
+        self.max_depth = 0;
+        self.gems.clear();
+        self.fuel_cans.clear();
+        // the first band holds the starting zone, where pickups would be grabbed straight away
+        self.pickup_bands = 1;
+        self.gems_collected = 0;
+        self.fuel = MAX_FUEL;
+        self.health = self.starting_health();
+        self.gravity_dir = self.gravity_target(self.player_ship.pos());
+        self.effects.clear();
+        self.camera_target = CameraTarget::Entity(EntityId::PlayerShip);
+        self.snap_camera();
+        self.set_state(GameState::InGame);
+    }
+
+    /// Scatters gems and fuel over the next band of the shaft down. Each band's pickups come from
+    /// the run's seed, so every run with the same seed has the same pickups
+    fn spawn_pickup_band(&mut self) {
+        let top = -(self.pickup_bands as f32) * PICKUP_BAND_HEIGHT;
+        let min = vec2(-PICKUP_SPREAD, top - PICKUP_BAND_HEIGHT);
+        let max = vec2(PICKUP_SPREAD, top);
+        let seed = self.seed ^ self.pickup_bands.wrapping_mul(PICKUP_BAND_HASH);
+        self.gems.extend(self.mine_shaft.sample_open_points(
+            min,
+            max,
+            PICKUP_CLEARANCE,
+            GEMS_PER_BAND,
+            seed,
+        ));
+        self.fuel_cans.extend(self.mine_shaft.sample_open_points(
+            min,
+            max,
+            PICKUP_CLEARANCE,
+            FUEL_CANS_PER_BAND,
+            seed ^ FUEL_SEED_SALT,
+        ));
+        self.pickup_bands += 1;
+    }
+
+    /// Picks up the gems and fuel the ship is touching, with a burst of sparkles for each gem
+    fn collect_pickups(&mut self) {
+        let ship = self.player_ship.pos();
+
+        let refuelled = take_within(&mut self.fuel_cans, ship, PICKUP_RADIUS).len();
+        self.fuel = (self.fuel + refuelled as f32 * FUEL_CAN_AMOUNT).min(MAX_FUEL);
+
+        for gem in take_within(&mut self.gems, ship, PICKUP_RADIUS) {
+            self.gems_collected += 1;
+            for i in 0..GEM_SPARKLES {
+                let angle = i as f32 / GEM_SPARKLES as f32 * std::f32::consts::TAU
+                    + self.rng.range(-0.3, 0.3);
+                let speed = self.rng.range(20.0, 40.0);
+                self.effects.spawn(
+                    gem,
+                    Vec2::from_angle(angle) * speed,
+                    self.rng.range(0.2, 0.4),
+                    self.gem_color,
+                );
+            }
+        }
+    }
+
+    /// Blows exhaust particles out of the back of the ship, more of them the longer the engine
+    /// has been running. They carry on drifting and fading once the engine stops
+    fn emit_exhaust(&mut self, dt: f32) {
+        let ramp = (self.thrust_held / EXHAUST_RAMP_TIME).min(1.0);
+        let rate = MIN_EXHAUST_RATE + (MAX_EXHAUST_RATE - MIN_EXHAUST_RATE) * ramp;
+        self.exhaust_backlog += rate * dt;
+
+        let nozzle = self.player_ship.transform.transform_point2(vec2(0.0, -9.0));
+        let backward = -self.player_ship.forward();
+        let gravity = self.gravity_dir * self.physics.gravity;
+        while self.exhaust_backlog >= 1.0 {
+            self.exhaust_backlog -= 1.0;
+
+            let spread = self.visual_rng.range(-0.3, 0.3);
+            let speed = self.visual_rng.range(20.0, 40.0);
+            let vel = self.player_ship.vel + Vec2::from_angle(spread).rotate(backward) * speed;
+            let life = self.visual_rng.range(0.4, 0.6);
+            self.effects
+                .spawn_accelerated(nozzle, vel, gravity, life, Color::YELLOW);
+        }
+    }
+
+    /// Which way gravity pulls at a point: the direction of the first zone containing it, or down
+    fn gravity_target(&self, p: Vec2) -> Vec2 {
+        self.gravity_zones
+            .iter()
+            .find(|zone| zone.contains(p))
+            .and_then(|zone| zone.direction.try_normalize())
+            .unwrap_or(Vec2::NEG_Y)
+    }
+
+    /// Abandons whatever is going on and starts a run in the cave grown from `seed`, such as
+    /// `daily_seed` for a layout everyone shares
+    pub fn start_seeded_run(&mut self, seed: u32) {
+        self.playback = None;
+        self.start_run(seed, false);
+    }
+
+    /// Plays a recorded run back from the start, in place of the player's input
+    pub fn start_playback(&mut self, replay: Replay) {
+        let seed = replay.seed;
+        self.playback = Some(replay);
+        self.start_run(seed, false);
+    }
+
+    /// The recording of the last run to finish, other than practice runs and replays
+    pub fn last_replay(&self) -> Option<&Replay> {
+        self.last_replay.as_ref()
+    }
+
+    /// The difficulty of the current run, which for replays is the one they were recorded at
+    fn difficulty(&self) -> Difficulty {
+        self.playback
+            .as_ref()
+            .map_or(self.settings.difficulty, |replay| replay.difficulty)
+    }
+
+    /// Traces the cave walls between `min` and `max` into an SVG document, at the same resolution
+    /// they're drawn at
+    pub fn export_cave_svg(&self, min: Vec2, max: Vec2) -> String {
+        let contours = self.mine_shaft.contours(CAVE_RESOLUTION, min, max);
+        svg::cave_svg(&contours, self.mine_shaft.seed(), min, max)
+    }
+
+    /// Whether the simulation is frozen for debugging, only advancing when `take_step` says so
+    pub fn frame_stepping(&self) -> bool {
+        self.frame_stepping
+    }
+
+    /// Whether a single update has been requested since the last call
+    pub fn take_step(&mut self) -> bool {
+        std::mem::take(&mut self.step_once)
+    }
+
+    pub fn pause_when_hidden(&self) -> bool {
+        self.pause_when_hidden
+    }
+
+    /// Opts out of suspending the game while the canvas is offscreen or the tab is hidden
+    pub fn set_pause_when_hidden(&mut self, enabled: bool) {
+        self.pause_when_hidden = enabled;
+    }
+
+    pub fn pause_on_blur(&self) -> bool {
+        self.pause_on_blur
+    }
+
+    /// Opts in to suspending the game while the page doesn't have focus
+    pub fn set_pause_on_blur(&mut self, enabled: bool) {
+        self.pause_on_blur = enabled;
+    }
+
+    /// Moves to a new state, queueing the transition to be reported to the page
+    fn set_state(&mut self, state: GameState) {
+        if state == self.game_state {
+            return;
+        }
+
+        // the run is over, so the recording is complete. Pausing only puts it on hold, as does
+        // opening the settings while paused
+        let in_run = |state| {
+            matches!(state, GameState::InGame | GameState::Paused)
+                || state == GameState::Settings && self.settings_return == GameState::Paused
+        };
+        if in_run(self.game_state) && !in_run(state) {
+            if let Some(mut replay) = self.recording.take() {
+                replay.finish(self.run_ticks);
+                self.last_replay = Some(replay);
+            }
+            self.playback = None;
+        }
+
+        if let Some(audio) = &mut self.audio {
+            if state != GameState::InGame {
+                audio.set_engine(0.0);
+            }
+            if state == GameState::GameOver {
+                audio.game_over();
+            }
+        }
+
+        self.game_state = state;
+        self.post_process
+            .set_bloom_threshold(bloom_threshold(state));
+        self.state_changes.push(StateChange {
+            state: state as u8,
+            seed: self.seed,
+            depth: self.max_depth,
+            health: self.health,
+            gems: self.gems_collected,
+            score: self.score(),
+            practice: self.practice,
+        });
+    }
+
+    /// Points for the run so far, from how deep it went and the gems it picked up
+    fn score(&self) -> usize {
+        self.max_depth + self.gems_collected * GEM_SCORE
+    }
+
+    /// Takes the state transitions since the last call
+    pub fn take_state_changes(&mut self) -> Vec<StateChange> {
+        std::mem::take(&mut self.state_changes)
+    }
+
+    /// Sets a depth at which the run is won, or `None` to play endlessly
+    pub fn set_goal_depth(&mut self, goal_depth: Option<usize>) {
+        self.goal_depth = goal_depth;
+    }
 
-impl AppState {
-    pub fn new(context: &WebGl2RenderingContext) -> Result<Self, JsValue> {
-        Ok(Self {
-            scribe: Scribe::new(context),
-            post_process: PostProcessor::new(context)?,
-            text: Text::new(context, Font::from_slice(&FONT, 0)),
-            thrust: false,
-            turn_left: false,
-            turn_right: false,
-            player_ship: Entity {
-                transform: Mat3::IDENTITY,
-                vel: Vec2::ZERO,
-            },
-            mine_shaft: MineShaft::new(760.0, 340.0),
-            max_depth: 0,
-            health: 5,
-            invulnerability_ticks: 0,
-            game_state: GameState::SplashScreen,
-        })
+    /// Ends the run in victory, recording the time taken if it beats the best for this goal
+    fn win(&mut self, dt: f32) {
+        let Some(goal) = self.goal_depth else {
+            return;
+        };
+
+        self.set_state(GameState::Victory);
+        self.pending_thumbnail = Some(self.player_ship.transform);
+
+        if self.practice {
+            self.victory_times = None;
+            return;
+        }
+
+        let time = self.run_ticks as f32 * dt;
+        let key = format!("fathom.best_time.{goal}");
+        let best = storage::load(&key)
+            .and_then(|best| best.parse::<f32>().ok())
+            .filter(|&best| best <= time);
+
+        let best = match best {
+            Some(best) => best,
+            None => {
+                storage::save(&key, &time.to_string());
+                time
+            }
+        };
+
+        self.victory_times = Some((time, best));
     }
 
-    pub fn on_resize(&mut self, canvas: &HtmlCanvasElement, context: &WebGl2RenderingContext) {
-        let device_pixel_ratio = window().unwrap().device_pixel_ratio();
-        let document = document();
-        let w = document.body().unwrap().client_width() as f64 * device_pixel_ratio;
-        let h = document.body().unwrap().client_height() as f64 * device_pixel_ratio;
+    /// Abandons the current run and goes back to the splash screen. Unlike dying this doesn't end
+    /// the run as a game over, so no thumbnail is taken
+    pub fn return_to_splash(&mut self) {
+        self.set_state(GameState::SplashScreen);
+        self.release_controls();
+        self.player_ship.transform = Mat3::IDENTITY;
+        self.player_ship.vel = Vec2::ZERO;
+        self.player_ship.angular_vel = 0.0;
+        self.camera_target = CameraTarget::Entity(EntityId::PlayerShip);
+        self.snap_camera();
+        self.health = self.starting_health();
+        self.invulnerability_ticks = 0;
+        self.effects.clear();
+        self.gems.clear();
+        self.fuel_cans.clear();
+        self.fuel = MAX_FUEL;
+    }
 
-        canvas.set_width(w as u32);
-        canvas.set_height(h as u32);
-        context.viewport(0, 0, w as i32, h as i32);
+    fn on_settings_keydown(&mut self, code: &str) {
+        let count = SettingsItem::ALL.len();
 
-        self.post_process.on_resize(w as i32, h as i32);
+        match code {
+            "KeyW" | "ArrowUp" => {
+                self.settings_selection = (self.settings_selection + count - 1) % count;
+            }
+            "KeyS" | "ArrowDown" => {
+                self.settings_selection = (self.settings_selection + 1) % count;
+            }
+            "Enter" | "Space" | "KeyA" | "ArrowLeft" | "KeyD" | "ArrowRight" => {
+                self.settings
+                    .toggle(SettingsItem::ALL[self.settings_selection]);
+                self.settings.save();
+                self.apply_settings();
+            }
+            "Escape" | "Backspace" | "KeyO" => self.set_state(self.settings_return),
+            _ => {}
+        }
     }
 
-    pub fn on_keydown(&mut self, key: KeyboardEvent) {
-        match key.code().as_str() {
-            "KeyW" | "ArrowUp" => self.thrust = true,
-            "KeyA" | "ArrowLeft" => self.turn_left = true,
-            "KeyD" | "ArrowRight" => self.turn_right = true,
-            _ => log::info!("key down {:?}", key.code()),
+    /// A finger touched the screen, which starts a run from the menus much as a key press does.
+    /// `touches` holds every finger now down, from 0 to 1 across the canvas with y down
+    pub fn on_touchstart(&mut self, touches: &[Vec2]) {
+        if let Some(audio) = &self.audio {
+            audio.resume();
         }
 
+        self.start_or_resume();
+        self.on_touchmove(touches);
+    }
+
+    /// Starts a run from the menus, or carries on a paused one, for inputs with no keys to choose
+    /// between practice and daily runs
+    fn start_or_resume(&mut self) {
         match self.game_state {
-            GameState::GameOver | GameState::SplashScreen => {
-                self.game_state = GameState::InGame;
-                self.player_ship.transform = Mat3::IDENTITY;
-                self.player_ship.vel = Vec2::ZERO;
-                self.max_depth = 0;
-                self.health = 5;
+            GameState::SplashScreen => self.start_run(random_seed(), false),
+            GameState::GameOver | GameState::Victory => {
+                self.start_run(random_seed(), self.practice)
             }
+            GameState::Paused => self.set_state(GameState::InGame),
             _ => {}
         }
     }
 
+    /// Fingers moved or lifted. `touches` holds every finger still down, from 0 to 1 across the
+    /// canvas with y down
+    pub fn on_touchmove(&mut self, touches: &[Vec2]) {
+        let actions: Vec<Action> = touches
+            .iter()
+            .filter_map(|&pos| self.touch_layout.action(pos))
+            .collect();
+
+        if actions.is_empty() && !self.touch_steering {
+            return;
+        }
+        self.touch_steering = !actions.is_empty();
+
+        let left = actions.contains(&Action::TurnLeft);
+        let right = actions.contains(&Action::TurnRight);
+        self.thrust = if actions.contains(&Action::Thrust) {
+            1.0
+        } else {
+            0.0
+        };
+        self.turn_input = match (left, right) {
+            (true, false) => -1.0,
+            (false, true) => 1.0,
+            _ => 0.0,
+        };
+    }
+
+    /// Takes which gamepad buttons are down, acting as each is pressed. The face button starts
+    /// runs and resumes as a tap does, and start does the same, or pauses during a run
+    pub fn set_pad_buttons(&mut self, confirm: bool, start: bool) {
+        let (was_confirm, was_start) = std::mem::replace(&mut self.pad_buttons, (confirm, start));
+
+        if start && !was_start && self.game_state == GameState::InGame {
+            self.pause();
+        } else if (start && !was_start) || (confirm && !was_confirm) {
+            self.start_or_resume();
+        }
+    }
+
+    /// Flies the ship from a gamepad, with thrust in [0, 1] and turn in [-1, 1], negative turning
+    /// left. The keyboard takes over again while both are zero
+    pub fn set_analog_input(&mut self, thrust: f32, turn: f32) {
+        let active = thrust > 0.0 || turn != 0.0;
+        if !active && !self.pad_steering {
+            return;
+        }
+        self.pad_steering = active;
+
+        self.thrust = thrust.clamp(0.0, 1.0);
+        self.turn_input = turn.clamp(-1.0, 1.0);
+    }
+
     pub fn on_keyup(&mut self, key: KeyboardEvent) {
-        match key.code().as_str() {
-            "KeyW" | "ArrowUp" => self.thrust = false,
-            "KeyA" | "ArrowLeft" => self.turn_left = false,
-            "KeyD" | "ArrowRight" => self.turn_right = false,
+        match self.key_bindings.action(&key.code()) {
+            Some(Action::Thrust) => self.thrust = 0.0,
+            Some(Action::TurnLeft) => {
+                self.left_held = false;
+                self.steer_from_keys();
+            }
+            Some(Action::TurnRight) => {
+                self.right_held = false;
+                self.steer_from_keys();
+            }
             _ => {}
         }
     }
 
     pub fn fixed_update(&mut self, dt: f32) {
-        self.player_ship.transform =
-            Mat3::from_translation(self.player_ship.vel * dt) * self.player_ship.transform;
+        if self.run_on_hold() {
+            return;
+        }
+
+        // hold everything still for a moment after a hard impact
+        if self.hitstop_ticks > 0 {
+            self.hitstop_ticks -= 1;
+            return;
+        }
+
+        // the ship only flies during a run, and stays put once it's won or lost
+        if self.game_state == GameState::InGame {
+            self.update_ship(dt);
+        }
+
+        self.damage_flash = (self.damage_flash - dt / DAMAGE_FLASH_DURATION).max(0.0);
+        self.shake = (self.shake - dt / SHAKE_DURATION).max(0.0);
+        self.post_process.update(dt);
+
+        self.effects.update(dt);
+
+        // ease the camera after its focus, independent of the tick rate
+        if self.smooths_camera() {
+            let t = 1.0 - (-dt / self.camera_smoothing).exp();
+            self.camera_pos = self.camera_pos.lerp(self.camera_focus(), t);
+        } else {
+            self.snap_camera();
+        }
+    }
+
+    /// Flies the ship one fixed update through a run: input, collisions and damage, and the
+    /// pickups and goal along the way
+    fn update_ship(&mut self, dt: f32) {
+        self.player_ship.drift(dt);
 
         // handle collision
         let distance = self.mine_shaft.distance(self.player_ship.pos());
-        if distance < 7.0 {
-            if let Some(n) = self.mine_shaft.normal(self.player_ship.pos()) {
-                self.player_ship.transform =
-                    Mat3::from_translation(n * (7.0 - distance)) * self.player_ship.transform;
+        let radius = self.player_ship.radius;
+        if distance < radius
+            && let Some(n) = self.mine_shaft.normal(self.player_ship.pos())
+        {
+            self.player_ship.transform =
+                Mat3::from_translation(n * (radius - distance)) * self.player_ship.transform;
+
+            // log::info!("penetration {}", distance + radius);
 
-                // log::info!("penetration {}", distance + 7.0);
+            let vn = self.player_ship.vel.dot(n) * n;
+            let vt = self.player_ship.vel - vn;
+
+            // the harder we hit, the longer the freeze, up to a limit
+            let impact_speed = vn.length();
+            if impact_speed > HITSTOP_MIN_SPEED && !self.settings.reduced_motion {
+                let t = (impact_speed - HITSTOP_MIN_SPEED)
+                    / (self.physics.max_speed - HITSTOP_MIN_SPEED);
+                self.hitstop_ticks = (t.min(1.0) * MAX_HITSTOP_TICKS as f32).ceil() as usize;
+            }
+            let strength = (impact_speed / self.physics.max_speed).min(1.0);
+            if !self.settings.reduced_motion {
+                self.shake = self.shake.max(strength);
+            }
+            if let Some(audio) = &self.audio
+                && impact_speed > IMPACT_SOUND_MIN_SPEED
+                && self.game_state == GameState::InGame
+            {
+                audio.impact(strength);
+            }
 
-                let vn = self.player_ship.vel.dot(n) * n;
-                let vt = self.player_ship.vel - vn;
+            let material = self.mine_shaft.material_at(self.player_ship.pos());
 
-                const RESTITUTION: f32 = 0.5;
-                const FRICTION: f32 = 0.125;
+            // Reflect the normal part with restitution (bounce factor)
+            let reflected_vn = -vn * material.restitution;
 
-                // Reflect the normal part with restitution (bounce factor)
-                let reflected_vn = -vn * RESTITUTION;
+            // Apply friction to the tangential (sliding) part
+            let friction_vt = vt * (1.0 - material.friction);
 
-                // Apply friction to the tangential (sliding) part
-                let friction_vt = vt * (1.0 - FRICTION);
+            self.player_ship.vel = reflected_vn + friction_vt;
 
-                self.player_ship.vel = reflected_vn + friction_vt;
+            // if we aren't invulnerable, apply damage
+            if !self.practice && self.invulnerability_ticks == 0 && self.health > 0 {
+                self.health -= 1;
+                self.damage_flash = 1.0;
 
-                // if we aren't invulnerable, apply damage
-                if self.invulnerability_ticks == 0 && self.health > 0 {
-                    self.health -= 1;
+                // keep the flash gentle for players who asked for less motion
+                let flash = if self.settings.reduced_motion {
+                    SCREEN_FLASH_COLOR * vec4(1.0, 1.0, 1.0, 0.4)
+                } else {
+                    SCREEN_FLASH_COLOR
+                };
+                self.post_process
+                    .trigger_flash(flash, SCREEN_FLASH_DURATION);
 
-                    // if we run out of health, game over. Otherwise give us 2 seconds of invulnerability
-                    if self.health < 1 {
-                        self.game_state = GameState::GameOver;
-                        self.player_ship.transform = Mat3::from_translation(Vec2::ZERO);
-                        self.health = 5;
-                    } else {
-                        self.invulnerability_ticks = 2 * 120;
-                    }
+                // if we run out of health, game over. Otherwise give us 2 seconds of invulnerability
+                if self.health < 1 {
+                    // burst into debris, and keep watching where the ship went down
+                    let crash_site = self.player_ship.pos();
+                    self.effects
+                        .explode(crash_site, self.player_ship.vel, &mut self.rng);
+                    self.camera_target = CameraTarget::Point(crash_site);
+                    self.set_state(GameState::GameOver);
+                    self.pending_thumbnail = Some(self.player_ship.transform);
+                    self.player_ship.transform = Mat3::from_translation(Vec2::ZERO);
+                    self.health = self.starting_health();
+                } else {
+                    self.invulnerability_ticks = 2 * 120;
                 }
             }
         }
@@ -160,103 +1721,260 @@ impl AppState {
             self.invulnerability_ticks -= 1;
         }
 
-        // handle player input
-        if self.thrust {
-            self.player_ship.vel += self.player_ship.forward() * 30.0 * dt;
+        // replays drive the ship in place of the player
+        if let Some(replay) = &self.playback {
+            if self.run_ticks > replay.length() {
+                self.return_to_splash();
+                return;
+            }
+            let input = replay.input_at(self.run_ticks);
+            self.thrust = input.thrust;
+            self.turn_input = input.turn;
+        } else {
+            // analog input is rounded to what a replay holds, so the run replays exactly
+            let input = Input {
+                thrust: self.thrust,
+                turn: self.turn_input,
+            }
+            .quantized();
+            self.thrust = input.thrust;
+            self.turn_input = input.turn;
+            if let Some(recording) = &mut self.recording {
+                recording.record(self.run_ticks, input);
+            }
         }
-        if self.turn_left {
-            self.player_ship.transform *= Mat3::from_angle(dt);
+
+        // the engine burns fuel during a run, except in practice
+        let thrusting = self.thrust > 0.0 && self.fuel > 0.0;
+        if thrusting && self.game_state == GameState::InGame && !self.practice {
+            self.fuel = (self.fuel - self.thrust * dt).max(0.0);
         }
-        if self.turn_right {
-            self.player_ship.transform *= Mat3::from_angle(-dt);
+
+        // the engine is only heard during a run, since keys do other things in the menus
+        if let Some(audio) = &mut self.audio {
+            let in_game = self.game_state == GameState::InGame;
+            let level = if thrusting && in_game {
+                self.thrust
+            } else {
+                0.0
+            };
+            audio.set_engine(level);
         }
 
-        // gravity
-        self.player_ship.vel += vec2(0.0, -10.0) * dt;
+        // gravity, which swings round gradually when the ship crosses into another zone
+        let target = self.gravity_target(self.player_ship.pos());
+        let angle = self.gravity_dir.angle_to(target);
+        let max_turn = GRAVITY_TURN_RATE * dt;
+        self.gravity_dir =
+            Vec2::from_angle(angle.clamp(-max_turn, max_turn)).rotate(self.gravity_dir);
 
-        // clamp speed
-        self.player_ship.vel = self.player_ship.vel.clamp_length_max(40.0);
+        // handle player input
+        let thrust = if thrusting {
+            self.physics.thrust_at(self.thrust_held) * self.thrust
+        } else {
+            0.0
+        };
+        self.player_ship.fly(
+            &self.physics,
+            thrust,
+            self.turn_input,
+            self.gravity_dir * self.physics.gravity,
+            dt,
+        );
+        if thrusting {
+            self.thrust_held += dt;
+            self.emit_exhaust(dt);
+        } else {
+            self.thrust_held = 0.0;
+            self.exhaust_backlog = 0.0;
+        }
 
         // calculate score
         self.max_depth = self.max_depth.max(-self.player_ship.pos().y as usize);
-    }
 
-    pub fn draw(&mut self, context: &WebGl2RenderingContext) {
-        let document = document();
-        let aspect = document.body().unwrap().client_width() as f32
-            / document.body().unwrap().client_height() as f32;
+        if self.game_state == GameState::InGame {
+            self.run_ticks += 1;
 
-        let transform =
-            Mat4::orthographic_rh_gl(-100.0 * aspect, 100.0 * aspect, -100.0, 100.0, -10.0, 10.0)
-                * Mat4::from_translation(-self.player_ship.pos().extend(0.0));
+            // pickups are laid out a band at a time, a little ahead of the ship
+            while (self.pickup_bands as f32) * PICKUP_BAND_HEIGHT
+                < self.max_depth as f32 + PICKUP_LOOKAHEAD
+            {
+                self.spawn_pickup_band();
+            }
+            self.collect_pickups();
 
-        self.post_process.start_capture();
+            if self.goal_depth.is_some_and(|goal| self.max_depth >= goal) {
+                self.win(dt);
+            }
+        }
+    }
 
-        context.clear_color(0.0, 0.0, 0.5, 1.0);
-        context.clear(
-            WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT,
-        );
+    /// Renders a frame. `alpha` is the fraction of a fixed update tick that has elapsed since the
+    /// last call to `fixed_update`, for interpolating effects between ticks
+    pub fn draw(&mut self, context: &WebGl2RenderingContext, alpha: f32) {
+        let (w, h) = self.post_process.render_size();
+        let aspect = w as f32 / h as f32;
 
-        let pos = self.player_ship.pos();
-        let grid_locked_pos = (pos / 40.0).floor() * 40.0;
+        // ease the flash out, and keep it gentle for players who asked for less motion
+        let intensity = if self.settings.reduced_motion {
+            DAMAGE_FLASH_INTENSITY * 0.4
+        } else {
+            DAMAGE_FLASH_INTENSITY
+        };
+        self.post_process
+            .set_damage_flash(self.damage_flash * self.damage_flash * intensity);
 
-        // draw background crosses
-        {
-            for i in 0..20 {
-                for j in 0..20 {
-                    let p = grid_locked_pos + vec2(i as f32 - 9.0, j as f32 - 9.0) * 40.0;
-                    if self.mine_shaft.distance(p) > 0.0 {
-                        self.scribe.draw_poly_line(
-                            &[p + vec2(-1.0, 0.0), p + vec2(1.0, 0.0)],
-                            1.0,
-                            false,
-                            Color::PaleBlue,
-                        );
-                        self.scribe.draw_poly_line(
-                            &[p + vec2(0.0, -1.0), p + vec2(0.0, 1.0)],
-                            1.0,
-                            false,
-                            Color::PaleBlue,
-                        );
-                    }
+        // blur along the camera's movement, unless the player asked for less motion
+        let focus = self.view_center(vec2(VIEW_HALF_HEIGHT * aspect, VIEW_HALF_HEIGHT));
+        let motion = match self.last_camera_focus.replace(focus) {
+            Some(last) if !self.settings.reduced_motion => {
+                (focus - last) / vec2(2.0 * VIEW_HALF_HEIGHT * aspect, 2.0 * VIEW_HALF_HEIGHT)
+            }
+            _ => Vec2::ZERO,
+        };
+        // a jump across much of the screen is the camera being reset, not movement
+        let motion = if motion.length() < 0.25 {
+            motion
+        } else {
+            Vec2::ZERO
+        };
+        self.post_process.set_motion(motion);
+
+        self.post_process.start_capture();
+        self.draw_scene(aspect, alpha);
+        self.post_process.finish();
+        self.scribe_stats = self.scribe.take_stats();
+
+        if let Some(final_transform) = self.pending_thumbnail.take() {
+            // the ship has already been reset for the next run, so put it back where it crashed
+            let transform = std::mem::replace(&mut self.player_ship.transform, final_transform);
+            match self.render_thumbnail(context, aspect, alpha) {
+                Ok(thumbnail) => {
+                    storage::save(THUMBNAIL_KEY, &thumbnail);
+                    self.last_run_thumbnail = Some(thumbnail);
                 }
+                Err(err) => log::warn!("failed to capture thumbnail: {err:?}"),
             }
-            self.scribe.render(transform);
+            self.player_ship.transform = transform;
         }
+    }
 
-        // draw mine shaft
-        {
-            let vertices = self.mine_shaft.marching_squares(5.0, grid_locked_pos);
-            self.scribe.draw_lines(&vertices, 1.0, Color::White);
-        }
+    /// Renders the world at a reduced resolution into an offscreen target, and encodes it as a
+    /// PNG `data:` URL. The HUD and menus are left out, and the canvas is left untouched.
+    fn render_thumbnail(
+        &mut self,
+        context: &WebGl2RenderingContext,
+        aspect: f32,
+        alpha: f32,
+    ) -> Result<String, JsValue> {
+        let h = THUMBNAIL_HEIGHT;
+        let w = (h as f32 * aspect).round().max(1.0) as i32;
 
-        // draw player ship
-        {
-            let p = |v| self.player_ship.transform.transform_point2(v);
+        let target = match self.thumbnail_target.take() {
+            Some(target) if target.w == w && target.h == h => target,
+            _ => RenderTarget::new(context, w, h)?,
+        };
+        target.bind();
+        self.draw_world(aspect, alpha);
+        self.text.render();
+        let thumbnail = target.to_png_data_url();
+        self.thumbnail_target = Some(target);
 
-            if self.invulnerability_ticks % 30 < 15 {
-                let ship = [p(vec2(-7.0, -7.0)), p(vec2(7.0, -7.0)), p(vec2(0.0, 7.0))];
-                self.scribe.draw_poly_line(&ship, 1.0, true, Color::White);
-            }
+        context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+        context.viewport(
+            0,
+            0,
+            context.drawing_buffer_width(),
+            context.drawing_buffer_height(),
+        );
 
-            // draw engine exhaust
-            if self.thrust {
-                let exhaust = [p(vec2(-3.0, -8.0)), p(vec2(3.0, -8.0)), p(vec2(0.0, -12.0))];
-                self.scribe
-                    .draw_poly_line(&exhaust, 1.0, true, Color::Yellow);
-            }
+        thumbnail
+    }
+
+    /// Draws the world and HUD into the currently bound framebuffer
+    fn draw_scene(&mut self, aspect: f32, alpha: f32) {
+        let pos = self.draw_world(aspect, alpha);
+
+        // menus sit on a darkened backdrop, so they stay readable over the cave
+        if self.game_state != GameState::InGame {
+            self.post_process.draw_dim_overlay(self.menu_dim);
+        }
+        // highlight the selected settings entry
+        if self.game_state == GameState::Settings {
+            let label = self
+                .settings
+                .label(SettingsItem::ALL[self.settings_selection]);
+            let size = self.text.measure(self.font, SETTINGS_FONT_SIZE, &label);
+            let center = vec2(pos.x, settings_row_y(pos.y, self.settings_selection));
+            let half = size * 0.5 + vec2(4.0, 1.0);
+
+            self.scribe.draw_poly_line(
+                &[
+                    center + vec2(-half.x, -half.y),
+                    center + vec2(half.x, -half.y),
+                    center + vec2(half.x, half.y),
+                    center + vec2(-half.x, half.y),
+                ],
+                0.5,
+                true,
+                Color::YELLOW,
+            );
+        }
+
+        // a gauge beside the fuel readout, square ended so it reads as a crisp bar, not a pill
+        let gauge_start = vec2(
+            pos.x - 120.0 + self.text.measure(self.font, 4.0, "Fuel 100%").x + 4.0,
+            pos.y - 72.0
+                + self.text.baseline_offset(self.font, 4.0)
+                + self.text.cap_height(self.font, 4.0) * 0.5,
+        );
+        let gauge_length = FUEL_GAUGE_LENGTH * (self.fuel / MAX_FUEL).clamp(0.0, 1.0);
+        if gauge_length > 0.0 {
+            self.scribe.draw_poly_line_capped(
+                &[gauge_start, gauge_start + vec2(gauge_length, 0.0)],
+                2.0,
+                CapStyle::Square,
+                self.fuel_color,
+            );
         }
-        self.scribe.render(transform);
+        self.scribe.render();
 
+        // monospaced digits, so the counter doesn't jitter as it ticks up
+        let digit_advance = self.text.measure(self.font, 1.0, "0").x;
+        self.text.set_monospace_advance(Some(digit_advance));
+        let depth = self.max_depth.to_string();
+        let depth_width = self.text.measure(self.font, 6.0, &depth).x;
         self.text.draw(
+            self.font,
             pos.x - 120.0,
             pos.y + 80.0,
             6.0,
             Align::Left,
-            &format!("{} meters", self.max_depth),
+            &depth,
+        );
+        self.text.set_monospace_advance(None);
+        self.text.draw(
+            self.font,
+            pos.x - 120.0 + depth_width,
+            pos.y + 80.0,
+            6.0,
+            Align::Left,
+            " meters",
         );
 
+        if self.gems_collected > 0 {
+            self.text.draw(
+                self.font,
+                pos.x - 120.0,
+                pos.y + 72.0,
+                4.0,
+                Align::Left,
+                &format!("Gems {}", self.gems_collected),
+            );
+        }
+
         self.text.draw(
+            self.font,
             pos.x - 120.0,
             pos.y - 80.0,
             6.0,
@@ -264,24 +1982,108 @@ impl AppState {
             &format!("Health {}", "I".repeat(self.health)),
         );
 
+        self.text.draw(
+            self.font,
+            pos.x - 120.0,
+            pos.y - 72.0,
+            4.0,
+            Align::Left,
+            &format!("Fuel {:.0}%", self.fuel / MAX_FUEL * 100.0),
+        );
+
+        if self.game_state == GameState::InGame {
+            let mode = if self.playback.is_some() {
+                Some("Replay - Q to stop")
+            } else if self.practice {
+                Some("Practice - R to reset")
+            } else {
+                None
+            };
+            if let Some(mode) = mode {
+                self.text.draw(
+                    self.font,
+                    pos.x + 120.0,
+                    pos.y - 80.0,
+                    4.0,
+                    Align::Right,
+                    mode,
+                );
+            }
+        }
+
         match self.game_state {
             GameState::SplashScreen => {
-                self.text
-                    .draw(pos.x, pos.y + 20.0, 18.0, Align::Center, "FATHOM");
+                match &self.title_label {
+                    Some(label) => self
+                        .text
+                        .draw_baked(label, pos.x, pos.y + 20.0, Align::Center),
+                    None => self.text.draw(
+                        self.font,
+                        pos.x,
+                        pos.y + 20.0,
+                        TITLE_FONT_SIZE,
+                        Align::Center,
+                        "FATHOM",
+                    ),
+                }
 
                 self.text.draw(
+                    self.font,
                     pos.x,
                     pos.y - 30.0,
                     4.0,
                     Align::Center,
                     "Press any key to start",
                 );
+
+                self.text.draw_block(
+                    self.font,
+                    pos.x,
+                    pos.y - 36.0,
+                    HINT_LAYOUT,
+                    "Press O for settings\nPress P to practice\nPress T for today's cave",
+                );
+            }
+            GameState::Paused => {
+                self.text.draw(
+                    self.font,
+                    pos.x,
+                    pos.y + 20.0,
+                    18.0,
+                    Align::Center,
+                    "Paused",
+                );
+
+                self.text.draw(
+                    self.font,
+                    pos.x,
+                    pos.y - 30.0,
+                    4.0,
+                    Align::Center,
+                    &pause_hint(&self.key_bindings),
+                );
             }
             GameState::GameOver => {
-                self.text
-                    .draw(pos.x, pos.y + 20.0, 18.0, Align::Center, "Game Over :(");
+                self.text.draw(
+                    self.font,
+                    pos.x,
+                    pos.y + 20.0,
+                    18.0,
+                    Align::Center,
+                    "Game Over :(",
+                );
 
                 self.text.draw(
+                    self.font,
+                    pos.x,
+                    pos.y - 10.0,
+                    4.0,
+                    Align::Center,
+                    &format!("Score {}", self.score()),
+                );
+
+                self.text.draw(
+                    self.font,
                     pos.x,
                     pos.y - 30.0,
                     4.0,
@@ -289,11 +2091,389 @@ impl AppState {
                     "Press any key to restart",
                 );
             }
+            GameState::Victory => {
+                self.text.draw(
+                    self.font,
+                    pos.x,
+                    pos.y + 20.0,
+                    18.0,
+                    Align::Center,
+                    "You made it!",
+                );
+
+                if let Some((time, best)) = self.victory_times {
+                    self.text.draw(
+                        self.font,
+                        pos.x,
+                        pos.y - 10.0,
+                        4.0,
+                        Align::Center,
+                        &format!("Time {:.1}s   Best {:.1}s", time, best),
+                    );
+                }
+
+                self.text.draw(
+                    self.font,
+                    pos.x,
+                    pos.y - 18.0,
+                    4.0,
+                    Align::Center,
+                    &format!("Score {}", self.score()),
+                );
+
+                self.text.draw(
+                    self.font,
+                    pos.x,
+                    pos.y - 30.0,
+                    4.0,
+                    Align::Center,
+                    "Press any key to play again",
+                );
+            }
+            GameState::Settings => {
+                self.text.draw(
+                    self.font,
+                    pos.x,
+                    pos.y + 50.0,
+                    12.0,
+                    Align::Center,
+                    "Settings",
+                );
+
+                for (i, item) in SettingsItem::ALL.iter().enumerate() {
+                    let y = settings_row_y(pos.y, i);
+                    let label = self.settings.label(*item);
+                    // text extends upwards from y, so shift it down to center it on the row
+                    let h = self.text.measure(self.font, SETTINGS_FONT_SIZE, &label).y;
+                    self.text.draw(
+                        self.font,
+                        pos.x,
+                        y - h * 0.5,
+                        SETTINGS_FONT_SIZE,
+                        Align::Center,
+                        &label,
+                    );
+                }
+
+                self.text.draw(
+                    self.font,
+                    pos.x,
+                    pos.y - 60.0,
+                    3.0,
+                    Align::Center,
+                    "Up/Down to select, Enter to change, Esc to go back",
+                );
+            }
             _ => {}
         }
 
-        self.text.render(transform);
+        self.text.render();
+    }
 
-        self.post_process.finish();
+    /// Draws the cave and everything in it, without the HUD or menus, and returns the centre of
+    /// the view
+    fn draw_world(&mut self, aspect: f32, alpha: f32) -> Vec2 {
+        let half_extents = vec2(VIEW_HALF_HEIGHT * aspect, VIEW_HALF_HEIGHT);
+        let pos = self.view_center(half_extents);
+
+        // only the view shakes, so what's drawn and culled around `pos` stays put
+        let shake = if self.shake > 0.0 {
+            let direction = vec2(
+                self.visual_rng.range(-1.0, 1.0),
+                self.visual_rng.range(-1.0, 1.0),
+            );
+            direction * self.shake * self.shake * MAX_SHAKE_OFFSET
+        } else {
+            Vec2::ZERO
+        };
+
+        let transform = Mat4::orthographic_rh_gl(
+            -half_extents.x,
+            half_extents.x,
+            -half_extents.y,
+            half_extents.y,
+            -10.0,
+            10.0,
+        ) * Mat4::from_translation(-(pos + shake).extend(0.0));
+
+        // upload the camera once, for every shader in the frame
+        let time = self.start_time.elapsed().as_secs_f32();
+        self.camera.write(&camera_block(transform, time));
+        self.camera.bind_base(CAMERA_BINDING);
+
+        self.backdrop.draw(
+            self.background.backdrop,
+            vec2(pos.y - half_extents.y, pos.y + half_extents.y),
+        );
+
+        let grid_locked_pos = (pos / 40.0).floor() * 40.0;
+
+        // draw background crosses
+        if self.background.enabled && self.background.spacing > 0.0 {
+            let spacing = self.background.spacing;
+            let size = self.background.cross_size;
+            let color = self.background.color;
+            // the grid breathes in and out behind the title, and holds still once a run starts
+            let grid_alpha =
+                if self.game_state == GameState::SplashScreen && !self.settings.reduced_motion {
+                    0.6 + 0.4 * (time * SPLASH_GRID_PULSE).sin()
+                } else {
+                    1.0
+                };
+
+            // only visit the grid points inside the view
+            let min = ((pos - half_extents) / spacing).floor();
+            let max = ((pos + half_extents) / spacing).ceil();
+
+            for i in min.x as i32..=max.x as i32 {
+                for j in min.y as i32..=max.y as i32 {
+                    let p = vec2(i as f32, j as f32) * spacing;
+                    // square ends keep the crosses crisp, where round ones would blob
+                    if self.mine_shaft.is_open(p) {
+                        self.scribe.draw_poly_line_faded(
+                            &[p + vec2(-size, 0.0), p + vec2(size, 0.0)],
+                            1.0,
+                            CapStyle::Butt,
+                            color,
+                            grid_alpha,
+                        );
+                        self.scribe.draw_poly_line_faded(
+                            &[p + vec2(0.0, -size), p + vec2(0.0, size)],
+                            1.0,
+                            CapStyle::Butt,
+                            color,
+                            grid_alpha,
+                        );
+                    }
+                }
+            }
+            self.scribe.render();
+        }
+
+        // draw contours around the walls, fading out towards the middle of the cave
+        let levels = self.background.contour_levels;
+        if levels > 0 && self.background.contour_spacing > 0.0 {
+            let base = self.scribe.color_value(self.background.color);
+            for level in 1..=levels {
+                let iso = level as f32 * self.background.contour_spacing;
+                let fade = 1.0 - level as f32 / (levels + 1) as f32;
+                let vertices = self.mine_shaft.marching_squares_at_level(
+                    CAVE_RESOLUTION,
+                    grid_locked_pos,
+                    iso,
+                );
+                let colors = vec![base * fade; vertices.len()];
+                self.scribe.draw_lines_colored(&vertices, &colors, 0.5);
+            }
+        }
+
+        // draw mine shaft
+        if self.cave_style != CaveRenderStyle::Outline {
+            let triangles = self
+                .mine_shaft
+                .marching_squares_filled(CAVE_RESOLUTION, grid_locked_pos);
+            self.scribe.fill_triangles(&triangles, ROCK_COLOR);
+        }
+        if self.cave_style != CaveRenderStyle::Filled {
+            // stroked as polylines rather than loose segments, so the scribe can merge the
+            // straight runs along the walls
+            let outline = match self.cave_tint {
+                CaveTint::Lit => self
+                    .mine_shaft
+                    .outline_with_normals(CAVE_RESOLUTION, grid_locked_pos),
+                _ => self
+                    .mine_shaft
+                    .outline(CAVE_RESOLUTION, grid_locked_pos)
+                    .into_iter()
+                    .map(|contour| contour.into_iter().map(|p| (p, Vec2::ZERO)).collect())
+                    .collect(),
+            };
+            for contour in outline {
+                // closed loops repeat their first point
+                let closed = contour.len() > 2 && contour.first() == contour.last();
+                let contour = &contour[..contour.len() - closed as usize];
+                let points: Vec<_> = contour.iter().map(|&(p, _)| p).collect();
+                match self.cave_tint {
+                    CaveTint::Flat => {
+                        self.scribe
+                            .draw_poly_line(&points, 1.0, closed, Color::WHITE)
+                    }
+                    CaveTint::Depth => {
+                        let colors: Vec<_> = points.iter().map(|p| depth_tint(p.y)).collect();
+                        self.scribe
+                            .draw_poly_line_gradient(&points, 1.0, closed, &colors);
+                    }
+                    CaveTint::Lit => {
+                        let colors: Vec<_> = contour.iter().map(|&(_, n)| wall_light(n)).collect();
+                        self.scribe
+                            .draw_poly_line_gradient(&points, 1.0, closed, &colors);
+                    }
+                }
+            }
+        }
+
+        // mark the finish, dashed so it doesn't read as a wall
+        if let Some(goal) = self.goal_depth {
+            let y = -(goal as f32);
+            if (y - pos.y).abs() < half_extents.y {
+                self.scribe.draw_dashed_line(
+                    &[
+                        vec2(pos.x - half_extents.x, y),
+                        vec2(pos.x + half_extents.x, y),
+                    ],
+                    0.5,
+                    false,
+                    LineStyle::Dashed { on: 6.0, off: 4.0 },
+                    Color::YELLOW,
+                );
+            }
+        }
+
+        for &gem in &self.gems {
+            self.scribe.draw_poly_line(
+                &[
+                    gem + vec2(0.0, GEM_SIZE),
+                    gem + vec2(GEM_SIZE * 0.7, 0.0),
+                    gem + vec2(0.0, -GEM_SIZE),
+                    gem + vec2(-GEM_SIZE * 0.7, 0.0),
+                ],
+                0.5,
+                true,
+                self.gem_color,
+            );
+
+            // a four pointed glint inside, pinched in between the points
+            let glint: Vec<Vec2> = (0..8)
+                .map(|i| {
+                    let radius = if i % 2 == 0 { 0.6 } else { 0.15 } * GEM_SIZE;
+                    gem + Vec2::from_angle(i as f32 * std::f32::consts::FRAC_PI_4) * radius
+                })
+                .collect();
+            self.scribe.draw_polygon_filled(&glint, self.gem_color);
+        }
+
+        for &can in &self.fuel_cans {
+            let half = vec2(FUEL_CAN_SIZE * 0.7, FUEL_CAN_SIZE);
+            self.scribe.draw_poly_line(
+                &[
+                    can - half,
+                    can + vec2(half.x, -half.y),
+                    can + half,
+                    can + vec2(-half.x, half.y),
+                ],
+                0.5,
+                true,
+                self.fuel_color,
+            );
+        }
+
+        let ship = Ship {
+            entity: &self.player_ship,
+            thrust: self.thrust > 0.0 && self.fuel > 0.0,
+            visible: self.invulnerability_ticks % 30 < 15,
+        };
+        let drawables: [&dyn Drawable; 2] = [&ship, &self.effects];
+        for drawable in drawables {
+            drawable.draw(&mut self.scribe, &mut self.text, alpha);
+        }
+
+        if self.debug {
+            self.draw_debug_overlay(pos);
+        }
+
+        // a compass for gravity, in levels where it doesn't always pull down
+        if self.game_state == GameState::InGame && !self.gravity_zones.is_empty() {
+            let center = pos + vec2(110.0, 70.0);
+            self.scribe.draw_arrow(
+                center - self.gravity_dir * 5.0,
+                center + self.gravity_dir * 5.0,
+                0.5,
+                2.5,
+                Color::PALE_BLUE,
+            );
+        }
+        self.scribe.render();
+
+        pos
+    }
+
+    /// Outlines the ship's collision circle, its velocity and the nearest wall's normal, and
+    /// prints the numbers behind them
+    fn draw_debug_overlay(&mut self, camera: Vec2) {
+        let ship = &self.player_ship;
+        let pos = ship.pos();
+
+        let circle: Vec<Vec2> = (0..24)
+            .map(|i| pos + Vec2::from_angle(i as f32 * std::f32::consts::TAU / 24.0) * ship.radius)
+            .collect();
+        self.scribe
+            .draw_poly_line(&circle, 0.5, true, Color::YELLOW);
+        self.scribe
+            .draw_arrow(pos, pos + ship.vel * 0.5, 0.5, 2.0, Color::PALE_BLUE);
+
+        let distance = self.mine_shaft.distance(pos);
+        if let Some(normal) = self.mine_shaft.normal(pos) {
+            let wall = pos - normal * distance;
+            self.scribe
+                .draw_arrow(wall, wall + normal * 10.0, 0.5, 2.0, Color::WHITE);
+            self.scribe.draw_dashed_line(
+                &[pos, wall],
+                0.5,
+                false,
+                LineStyle::Dotted { spacing: 2.0 },
+                Color::WHITE,
+            );
+        }
+
+        let text_stats = self.text.stats();
+        let status = if self.frame_stepping {
+            "frozen - . to step, F to resume"
+        } else {
+            "F to freeze"
+        };
+        let merging = if self.scribe.merge_collinear() {
+            "M to stop merging lines"
+        } else {
+            "M to merge lines"
+        };
+        let lines = [
+            format!("pos {:.2} {:.2}", pos.x, pos.y),
+            format!("vel {:.2} {:.2}", ship.vel.x, ship.vel.y),
+            format!("wall {:.2}", distance),
+            format!(
+                "verts {} of {}",
+                self.scribe_stats.unique_vertices, self.scribe_stats.queued_vertices
+            ),
+            format!("glyphs {}", self.text.loaded_chars(self.font).count()),
+            format!(
+                "text peak {} segs {} verts",
+                text_stats.peak_segments, text_stats.peak_vertices
+            ),
+            merging.to_string(),
+            status.to_string(),
+        ];
+        let layout = BlockLayout {
+            align: Align::Right,
+            ..HINT_LAYOUT
+        };
+        let text = lines.join("\n");
+        let top_right = camera + vec2(120.0, 84.0);
+
+        // frame the numbers, so they stand apart from the HUD
+        let size = self.text.measure_block(self.font, layout, &text) + Vec2::splat(2.0);
+        let corner = top_right + vec2(1.0, 1.0);
+        self.scribe.draw_poly_line(
+            &[
+                corner,
+                corner - vec2(size.x, 0.0),
+                corner - size,
+                corner - vec2(0.0, size.y),
+            ],
+            0.25,
+            true,
+            Color::PALE_BLUE,
+        );
+        self.text
+            .draw_block(self.font, top_right.x, top_right.y, layout, &text);
     }
 }
@@ -0,0 +1,156 @@
+use wasm_bindgen::JsValue;
+use web_sys::{
+    AudioContext, AudioContextState, BiquadFilterType, GainNode, OscillatorNode, OscillatorType,
+};
+
+/// Volume of everything together
+const MASTER_VOLUME: f32 = 0.3;
+
+/// Pitch of the engine's drone, in hertz
+const ENGINE_FREQUENCY: f32 = 55.0;
+
+/// Cutoff of the filter muffling the engine, in hertz
+const ENGINE_CUTOFF: f32 = 400.0;
+
+/// Volume of the engine at full thrust
+const ENGINE_VOLUME: f32 = 0.6;
+
+/// Roughly how long the engine takes to spool up or die away, in seconds
+const ENGINE_FADE: f64 = 0.05;
+
+/// Quietest level a tone decays to before it stops. Exponential ramps can't reach zero
+const SILENCE: f32 = 0.001;
+
+/// Synthesized sound effects, played through the Web Audio API
+pub struct Audio {
+    context: AudioContext,
+    output: GainNode,
+    /// The engine drones continuously, and is faded in and out by this gain
+    engine: GainNode,
+    /// The engine level last asked for, so its fades are only scheduled on changes
+    engine_level: f32,
+}
+
+impl Audio {
+    /// Browsers start the context suspended until the player interacts with the page, so call
+    /// `resume` from an input event
+    pub fn new() -> Result<Self, JsValue> {
+        let context = AudioContext::new()?;
+
+        let output = context.create_gain()?;
+        output.gain().set_value(MASTER_VOLUME);
+        output.connect_with_audio_node(&context.destination())?;
+
+        let drone = context.create_oscillator()?;
+        drone.set_type(OscillatorType::Sawtooth);
+        drone.frequency().set_value(ENGINE_FREQUENCY);
+
+        let filter = context.create_biquad_filter()?;
+        filter.set_type(BiquadFilterType::Lowpass);
+        filter.frequency().set_value(ENGINE_CUTOFF);
+
+        let engine = context.create_gain()?;
+        engine.gain().set_value(0.0);
+
+        drone
+            .connect_with_audio_node(&filter)?
+            .connect_with_audio_node(&engine)?
+            .connect_with_audio_node(&output)?;
+        drone.start()?;
+
+        Ok(Self {
+            context,
+            output,
+            engine,
+            engine_level: 0.0,
+        })
+    }
+
+    /// Lets sound play, once the page has had a key press or touch
+    pub fn resume(&self) {
+        if self.context.state() == AudioContextState::Suspended {
+            let _ = self
+                .context
+                .resume()
+                .inspect_err(|e| log::warn!("failed to resume audio: {:?}", e));
+        }
+    }
+
+    /// Silences everything, or brings it back at the usual volume
+    pub fn set_muted(&self, muted: bool) {
+        let volume = if muted { 0.0 } else { MASTER_VOLUME };
+        self.output.gain().set_value(volume);
+    }
+
+    /// Sets the engine's volume from the thrust amount, in [0, 1]
+    pub fn set_engine(&mut self, thrust: f32) {
+        if thrust == self.engine_level {
+            return;
+        }
+        self.engine_level = thrust;
+
+        let _ = self
+            .engine
+            .gain()
+            .set_target_at_time(
+                thrust * ENGINE_VOLUME,
+                self.context.current_time(),
+                ENGINE_FADE,
+            )
+            .inspect_err(|e| log::warn!("failed to fade engine: {:?}", e));
+    }
+
+    /// A dull thud against the cave wall, louder for `strength` nearer 1
+    pub fn impact(&self, strength: f32) {
+        self.play(OscillatorType::Triangle, (120.0, 40.0), strength, 0.15);
+    }
+
+    /// A long falling whine
+    pub fn game_over(&self) {
+        self.play(OscillatorType::Square, (440.0, 55.0), 0.4, 1.2);
+    }
+
+    /// Plays a tone sweeping between two frequencies in hertz, decaying from `volume` to silence
+    /// over `duration` seconds
+    fn play(&self, wave: OscillatorType, (from, to): (f32, f32), volume: f32, duration: f64) {
+        let _ = self
+            .tone(wave, from, to, volume, duration)
+            .inspect_err(|e| log::warn!("failed to play sound: {:?}", e));
+    }
+
+    fn tone(
+        &self,
+        wave: OscillatorType,
+        from: f32,
+        to: f32,
+        volume: f32,
+        duration: f64,
+    ) -> Result<(), JsValue> {
+        let now = self.context.current_time();
+        let end = now + duration;
+
+        let oscillator: OscillatorNode = self.context.create_oscillator()?;
+        oscillator.set_type(wave);
+        oscillator.frequency().set_value_at_time(from, now)?;
+        oscillator
+            .frequency()
+            .exponential_ramp_to_value_at_time(to, end)?;
+
+        let envelope = self.context.create_gain()?;
+        envelope
+            .gain()
+            .set_value_at_time(volume.max(SILENCE), now)?;
+        envelope
+            .gain()
+            .exponential_ramp_to_value_at_time(SILENCE, end)?;
+
+        oscillator
+            .connect_with_audio_node(&envelope)?
+            .connect_with_audio_node(&self.output)?;
+        oscillator.start_with_when(now)?;
+        // stopped oscillators are disconnected and collected by the browser
+        oscillator.stop_with_when(end)?;
+
+        Ok(())
+    }
+}
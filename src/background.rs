@@ -0,0 +1,127 @@
+use glam::{Vec2, Vec4, vec4};
+use web_sys::WebGl2RenderingContext;
+
+use crate::{scribe::Color, shader::Shader};
+
+/// What fills the screen behind everything else
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Backdrop {
+    /// A single colour
+    Flat(Vec4),
+    /// A vertical gradient through the world, from `shallow` at the surface to `deep` once
+    /// `depth` world units down
+    Gradient {
+        shallow: Vec4,
+        deep: Vec4,
+        depth: f32,
+    },
+}
+
+/// Appearance of the grid of crosses drawn behind the cave
+pub struct BackgroundConfig {
+    pub enabled: bool,
+    /// Distance between neighbouring crosses, in world units
+    pub spacing: f32,
+    /// Half the length of each arm of a cross
+    pub cross_size: f32,
+    pub color: Color,
+    pub backdrop: Backdrop,
+    /// Number of contours traced through the open cave parallel to the walls, each fainter than
+    /// the last, for a topographic map look. 0 disables them
+    pub contour_levels: usize,
+    /// Distance between neighbouring contours, and from the walls to the first, in world units
+    pub contour_spacing: f32,
+}
+
+impl Default for BackgroundConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            spacing: 40.0,
+            cross_size: 1.0,
+            color: Color::PALE_BLUE,
+            backdrop: Backdrop::Flat(vec4(0.0, 0.0, 0.5, 1.0)),
+            contour_levels: 0,
+            contour_spacing: 10.0,
+        }
+    }
+}
+
+/// Clears the frame to a `Backdrop`
+pub struct Background {
+    context: WebGl2RenderingContext,
+    gradient_shader: Shader,
+}
+
+impl Background {
+    /// Sources of the gradient program, for `precompile_shaders`
+    pub const SHADERS: &[(&str, &str)] = &[(GRADIENT_VS, GRADIENT_FS)];
+
+    pub fn new(context: &WebGl2RenderingContext) -> Result<Self, String> {
+        Ok(Self {
+            context: context.clone(),
+            gradient_shader: Shader::new(context, GRADIENT_VS, GRADIENT_FS)?,
+        })
+    }
+
+    /// Clears the bound target's colour and depth, and fills it with the backdrop. `view_y` holds
+    /// the world heights at the bottom and top of the view
+    pub fn draw(&self, backdrop: Backdrop, view_y: Vec2) {
+        let clear_color = match backdrop {
+            Backdrop::Flat(color) => color,
+            Backdrop::Gradient { deep, .. } => deep,
+        };
+        self.context
+            .clear_color(clear_color.x, clear_color.y, clear_color.z, clear_color.w);
+        self.context.clear(
+            WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT,
+        );
+
+        if let Backdrop::Gradient {
+            shallow,
+            deep,
+            depth,
+        } = backdrop
+        {
+            self.gradient_shader.uniform4f("u_shallow", shallow);
+            self.gradient_shader.uniform4f("u_deep", deep);
+            self.gradient_shader
+                .uniform4f("u_view", view_y.extend(depth).extend(0.0));
+
+            self.context.bind_vertex_array(None);
+            self.context
+                .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+        }
+    }
+}
+
+const GRADIENT_VS: &str = r##"#version 300 es
+
+out float v_t;
+
+void main() {
+    vec2 pos = vec2(gl_VertexID & 1, gl_VertexID >> 1) * 2.0 - 1.0;
+    v_t = pos.y * 0.5 + 0.5;
+    gl_Position = vec4(pos, 0.0, 1.0);
+}
+"##;
+
+const GRADIENT_FS: &str = r##"#version 300 es
+
+precision highp float;
+
+uniform vec4 u_shallow;
+uniform vec4 u_deep;
+// bottom and top of the view in world space, then the depth at which `u_deep` is reached
+uniform vec4 u_view;
+
+in float v_t;
+
+out vec4 outColor;
+
+void main() {
+    float world_y = mix(u_view.x, u_view.y, v_t);
+    float depth = clamp(-world_y / u_view.z, 0.0, 1.0);
+    outColor = mix(u_shallow, u_deep, depth);
+}
+"##;
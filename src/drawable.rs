@@ -0,0 +1,9 @@
+use crate::{scribe::Scribe, text::Text};
+
+/// Anything in the world that knows how to draw itself. The scene queues each drawable in turn,
+/// and flushes the scribe and text afterwards, so implementations only need to queue geometry
+pub trait Drawable {
+    /// Queues this object's geometry, `alpha` being the fraction of a tick elapsed since the last
+    /// fixed update
+    fn draw(&self, scribe: &mut Scribe, text: &mut Text, alpha: f32);
+}
@@ -0,0 +1,176 @@
+use glam::Vec2;
+
+use crate::{
+    drawable::Drawable,
+    polyline::CapStyle,
+    rng::Rng,
+    scribe::{Color, Scribe},
+    text::Text,
+};
+
+// Effects are simulated in `AppState::fixed_update`, at the fixed tick rate, and only rendered in
+// `AppState::draw`, interpolating between the previous and current tick. Advancing them from
+// `draw` instead would make them move at the display's refresh rate and stutter, so every new
+// effect (particles, screen shake, starfields...) should follow the same split.
+
+const MAX_PARTICLES: usize = 1024;
+
+/// Length of the streak drawn behind each particle, in seconds of travel
+const STREAK_DURATION: f32 = 0.05;
+
+/// Pieces of debris the ship bursts into
+const EXPLOSION_PARTICLES: usize = 64;
+
+pub struct Particle {
+    pos: Vec2,
+    prev_pos: Vec2,
+    vel: Vec2,
+    /// Constant acceleration, such as gravity
+    accel: Vec2,
+    /// Remaining lifetime, in seconds
+    life: f32,
+    /// Lifetime at spawn, so the particle can fade out over it
+    lifetime: f32,
+    color: Color,
+}
+
+pub struct Effects {
+    particles: Vec<Particle>,
+}
+
+impl Effects {
+    pub fn new() -> Self {
+        Self { particles: vec![] }
+    }
+
+    /// Spawns a particle, unless the particle budget is exhausted
+    pub fn spawn(&mut self, pos: Vec2, vel: Vec2, life: f32, color: Color) {
+        self.spawn_accelerated(pos, vel, Vec2::ZERO, life, color);
+    }
+
+    /// Spawns a particle that speeds up by `accel` every second, such as one falling under
+    /// gravity
+    pub fn spawn_accelerated(
+        &mut self,
+        pos: Vec2,
+        vel: Vec2,
+        accel: Vec2,
+        life: f32,
+        color: Color,
+    ) {
+        if self.particles.len() < MAX_PARTICLES {
+            self.particles.push(Particle {
+                pos,
+                prev_pos: pos,
+                vel,
+                accel,
+                life,
+                lifetime: life,
+                color,
+            });
+        }
+    }
+
+    /// Bursts into debris at `pos`, carrying some of the momentum `vel` of whatever exploded
+    pub fn explode(&mut self, pos: Vec2, vel: Vec2, rng: &mut Rng) {
+        for _ in 0..EXPLOSION_PARTICLES {
+            let direction = Vec2::from_angle(rng.range(0.0, std::f32::consts::TAU));
+            let speed = rng.range(10.0, 60.0);
+            let color = if rng.next_f32() < 0.3 {
+                Color::YELLOW
+            } else {
+                Color::WHITE
+            };
+            self.spawn(
+                pos,
+                vel * 0.5 + direction * speed,
+                rng.range(0.5, 1.5),
+                color,
+            );
+        }
+    }
+
+    /// Removes every live particle
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    /// Advances all effects by one fixed tick
+    pub fn update(&mut self, dt: f32) {
+        for particle in &mut self.particles {
+            particle.prev_pos = particle.pos;
+            particle.vel += particle.accel * dt;
+            particle.pos += particle.vel * dt;
+            particle.life -= dt;
+        }
+
+        self.particles.retain(|particle| particle.life > 0.0);
+    }
+}
+
+impl Drawable for Effects {
+    fn draw(&self, scribe: &mut Scribe, _text: &mut Text, alpha: f32) {
+        for particle in &self.particles {
+            let pos = particle.prev_pos.lerp(particle.pos, alpha);
+            let tail = pos - particle.vel * STREAK_DURATION;
+            let fade = (particle.life / particle.lifetime).clamp(0.0, 1.0);
+            scribe.draw_poly_line_faded(&[tail, pos], 0.5, CapStyle::Round, particle.color, fade);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec2;
+    use web_time::{Duration, Instant};
+
+    use super::*;
+    use crate::{UPDATE_DURATION, catch_up};
+
+    /// Spawns a few falling particles and advances them for `seconds`, rendering at `fps`
+    fn particles_at_frame_rate(fps: f32, seconds: f32) -> Vec<Vec2> {
+        let mut effects = Effects::new();
+        for i in 0..8 {
+            let angle = i as f32 * 0.8;
+            effects.spawn_accelerated(
+                vec2(i as f32, 0.0),
+                Vec2::from_angle(angle) * 40.0,
+                vec2(0.0, -30.0),
+                10.0,
+                Color::WHITE,
+            );
+        }
+
+        let start = Instant::now();
+        let end = start + Duration::from_secs_f32(seconds);
+        let frame = Duration::from_secs_f32(1.0 / fps);
+
+        let mut last = start;
+        let mut now = start;
+        while now < end {
+            now = (now + frame).min(end);
+            last = catch_up(last, now, |dt| effects.update(dt));
+        }
+        effects
+            .particles
+            .iter()
+            .map(|particle| particle.pos)
+            .collect()
+    }
+
+    #[test]
+    fn particles_move_the_same_at_any_frame_rate() {
+        // end partway between ticks, so rounding can't tip the last one either way
+        let seconds = 1.5 + 0.4 * UPDATE_DURATION;
+        let reference = particles_at_frame_rate(60.0, seconds);
+        assert_eq!(reference.len(), 8);
+
+        for fps in [24.0, 30.0, 75.0, 144.0, 240.0] {
+            assert_eq!(
+                particles_at_frame_rate(fps, seconds),
+                reference,
+                "positions at {fps} fps"
+            );
+        }
+    }
+}
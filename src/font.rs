@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use glam::{Vec2, vec2};
 use ttf_parser::{Face, OutlineBuilder};
@@ -130,7 +130,16 @@ pub struct Font {
     pub height: f32,
     pub descender: f32,
     pub ascender: f32,
+    /// Height of capital letters above the baseline
+    pub cap_height: f32,
+    /// Height of lowercase letters like 'x' above the baseline
+    pub x_height: f32,
     pub units_per_em: f32,
+    /// The font file, kept so more glyphs can be loaded later
+    data: Vec<u8>,
+    index: u32,
+    /// Characters the font has no glyph for, so they aren't looked up again
+    missing: HashSet<char>,
 }
 
 impl Font {
@@ -141,34 +150,72 @@ impl Font {
         const ALPHABET: &str =
             "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789.,!?:()-+*/\\ ";
 
-        let mut chars = HashMap::new();
-
-        for c in ALPHABET.chars() {
-            let mut path = face
-                .glyph_index(c)
-                .map(|glyph_id| {
-                    let mut path = Path::new();
-                    let _ = face.outline_glyph(glyph_id, &mut path);
-                    path
-                })
-                .unwrap_or_else(|| Path::new());
+        // not every font carries these in its OS/2 table, so estimate them from the ascender
+        let ascender = face.ascender() as f32;
+        let cap_height = face
+            .capital_height()
+            .map(|height| height as f32)
+            .unwrap_or(ascender * 0.7);
+        let x_height = face
+            .x_height()
+            .map(|height| height as f32)
+            .unwrap_or(ascender * 0.5);
+
+        let mut font = Self {
+            chars: HashMap::new(),
+            height: face.height() as f32,
+            descender: face.descender() as f32,
+            ascender,
+            cap_height,
+            x_height,
+            units_per_em: face.units_per_em() as f32,
+            data: data.to_vec(),
+            index,
+            missing: HashSet::new(),
+        };
+        font.ensure_glyphs(ALPHABET);
+        font
+    }
+
+    /// Loads the glyphs for any characters in `text` that haven't been loaded yet. Returns whether
+    /// every character is now available; those the font has no glyph for are left out, and are
+    /// skipped when drawing
+    pub fn ensure_glyphs(&mut self, text: &str) -> bool {
+        let pending: Vec<char> = text
+            .chars()
+            .filter(|c| !self.chars.contains_key(c) && !self.missing.contains(c))
+            .collect();
+        if pending.is_empty() {
+            return text.chars().all(|c| self.chars.contains_key(&c));
+        }
+
+        let Ok(face) = Face::parse(&self.data, self.index) else {
+            return false;
+        };
+
+        for c in pending {
+            let Some(glyph_id) = face.glyph_index(c) else {
+                self.missing.insert(c);
+                continue;
+            };
+
+            // whitespace has an advance but no outline
+            let mut path = Path::new();
+            let _ = face.outline_glyph(glyph_id, &mut path);
             path.normalize();
             let advance = face
-                .glyph_index(c)
-                .map(|glyph_id| face.glyph_hor_advance(glyph_id))
-                .flatten()
+                .glyph_hor_advance(glyph_id)
                 .map(|advance| advance as f32)
                 .unwrap_or(0.0);
 
-            chars.insert(c, Character { path, advance });
+            self.chars.insert(c, Character { path, advance });
         }
 
-        Self {
-            chars,
-            height: face.height() as f32,
-            descender: face.descender() as f32,
-            ascender: face.ascender() as f32,
-            units_per_em: face.units_per_em() as f32,
-        }
+        text.chars().all(|c| self.chars.contains_key(&c))
+    }
+
+    /// Every character with a glyph loaded, in no particular order
+    pub fn loaded_chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.chars.keys().copied()
     }
 }
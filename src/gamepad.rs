@@ -0,0 +1,60 @@
+use wasm_bindgen::JsCast;
+use web_sys::{Gamepad, GamepadButton};
+
+use crate::window;
+
+/// The left stick's horizontal axis, in the standard mapping
+const TURN_AXIS: u32 = 0;
+
+/// The right trigger, in the standard mapping
+const THRUST_TRIGGER: u32 = 7;
+
+/// The bottom face button, for pads without analog triggers. It also starts runs, like a tap
+const THRUST_BUTTON: u32 = 0;
+
+/// The start button, which pauses and resumes
+const START_BUTTON: u32 = 9;
+
+/// Stick travel ignored around the centre, where worn sticks come to rest slightly off it
+const STICK_DEAD_ZONE: f32 = 0.15;
+
+/// What the first connected gamepad is doing
+pub struct PadInput {
+    /// In [0, 1]
+    pub thrust: f32,
+    /// In [-1, 1], negative turning left
+    pub turn: f32,
+    /// Whether the bottom face button is down
+    pub confirm: bool,
+    /// Whether the start button is down
+    pub start: bool,
+}
+
+/// Reads the first connected gamepad, or `None` if there isn't one
+pub fn poll() -> Option<PadInput> {
+    let pads = window().navigator().get_gamepads().ok()?;
+    // slots of disconnected pads hold null
+    let pad = pads
+        .iter()
+        .filter_map(|pad| pad.dyn_into::<Gamepad>().ok())
+        .find(Gamepad::connected)?;
+
+    let x = pad.axes().get(TURN_AXIS).as_f64().unwrap_or(0.0) as f32;
+    let turn = x.signum() * ((x.abs() - STICK_DEAD_ZONE) / (1.0 - STICK_DEAD_ZONE)).max(0.0);
+
+    let buttons = pad.buttons();
+    let button = |index| {
+        buttons
+            .get(index)
+            .dyn_into::<GamepadButton>()
+            .map_or(0.0, |button| button.value() as f32)
+    };
+    let thrust = button(THRUST_TRIGGER).max(button(THRUST_BUTTON));
+
+    Some(PadInput {
+        thrust,
+        turn,
+        confirm: button(THRUST_BUTTON) > 0.5,
+        start: button(START_BUTTON) > 0.5,
+    })
+}
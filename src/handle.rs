@@ -0,0 +1,342 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use glam::{vec2, vec4};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    REPLAY_FRAGMENT_PREFIX,
+    app::{AppState, CaveRenderStyle, CaveTint, EntityId, StateChange},
+    background::Backdrop,
+    key_bindings::KeyBindings,
+    post_processor::{FxaaParams, ToneMap},
+    replay::Replay,
+    rng::daily_seed,
+    touch_controls::TouchLayout,
+};
+
+thread_local! {
+    static CURRENT: RefCell<Option<AppHandle>> = const { RefCell::new(None) };
+}
+
+/// A handle to the running game, exposed to page code for embedding
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct AppHandle {
+    app_state: Rc<RefCell<AppState>>,
+    on_state_change: Rc<RefCell<Option<js_sys::Function>>>,
+    /// Set while state changes are being reported, so callbacks can't start a nested dispatch
+    dispatching: Rc<Cell<bool>>,
+}
+
+impl AppHandle {
+    pub fn new(app_state: Rc<RefCell<AppState>>) -> Self {
+        Self {
+            app_state,
+            on_state_change: Rc::new(RefCell::new(None)),
+            dispatching: Rc::new(Cell::new(false)),
+        }
+    }
+
+    /// Makes this the handle returned by `app_handle()`
+    pub fn register(&self) {
+        CURRENT.with(|current| *current.borrow_mut() = Some(self.clone()));
+    }
+
+    /// Reports state transitions to the page's callback, if it registered one. The app state must
+    /// not be borrowed, as the callback is free to call back into the handle
+    pub fn dispatch_state_changes(&self) {
+        // changes queued by a callback are picked up by the loop below, rather than recursing
+        if self.dispatching.replace(true) {
+            return;
+        }
+
+        loop {
+            let changes = self.app_state.borrow_mut().take_state_changes();
+            if changes.is_empty() {
+                break;
+            }
+
+            // cloned so the callback can replace itself
+            let Some(callback) = self.on_state_change.borrow().clone() else {
+                continue;
+            };
+            for change in changes {
+                let result = callback.call2(
+                    &JsValue::NULL,
+                    &JsValue::from(change.state),
+                    &state_change_stats(&change),
+                );
+                if let Err(e) = result {
+                    log::warn!("state change callback failed: {:?}", e);
+                }
+            }
+        }
+
+        self.dispatching.set(false);
+    }
+}
+
+#[wasm_bindgen]
+impl AppHandle {
+    /// The current state of the game: 0 = splash screen, 1 = in game, 2 = game over,
+    /// 3 = settings menu, 4 = victory, 5 = paused
+    pub fn current_state(&self) -> u8 {
+        self.app_state.borrow().current_state()
+    }
+
+    /// The seed of the current run
+    pub fn seed(&self) -> u32 {
+        self.app_state.borrow().seed()
+    }
+
+    /// A small PNG `data:` URL of the final frame of the last run, once one has ended
+    pub fn last_run_thumbnail(&self) -> Option<String> {
+        self.app_state.borrow().last_run_thumbnail()
+    }
+
+    /// The ship's position as `[x, y]`, in world units
+    pub fn ship_position(&self) -> Vec<f32> {
+        self.app_state
+            .borrow()
+            .entity(EntityId::PlayerShip)
+            .pos()
+            .to_array()
+            .to_vec()
+    }
+
+    /// The ship's anticlockwise rotation in radians, where 0 faces straight up
+    pub fn ship_rotation(&self) -> f32 {
+        self.app_state
+            .borrow()
+            .entity(EntityId::PlayerShip)
+            .rotation()
+    }
+
+    /// The ship's velocity as `[x, y]`, in world units per second
+    pub fn ship_velocity(&self) -> Vec<f32> {
+        self.app_state
+            .borrow()
+            .entity(EntityId::PlayerShip)
+            .vel
+            .to_array()
+            .to_vec()
+    }
+
+    /// Moves the ship. Only applies during a run, and returns whether it did
+    pub fn set_ship_position(&self, x: f32, y: f32) -> bool {
+        self.app_state.borrow_mut().set_ship_pos(vec2(x, y))
+    }
+
+    /// Turns the ship. Only applies during a run, and returns whether it did
+    pub fn set_ship_rotation(&self, rotation: f32) -> bool {
+        self.app_state.borrow_mut().set_ship_rotation(rotation)
+    }
+
+    /// Sets the ship's velocity. Only applies during a run, and returns whether it did
+    pub fn set_ship_velocity(&self, x: f32, y: f32) -> bool {
+        self.app_state.borrow_mut().set_ship_vel(vec2(x, y))
+    }
+
+    /// Sets the depth that wins a run. Zero or less plays endlessly
+    pub fn set_goal_depth(&self, depth: i32) {
+        let goal = (depth > 0).then_some(depth as usize);
+        self.app_state.borrow_mut().set_goal_depth(goal);
+    }
+
+    /// Whether to suspend the game while its canvas is offscreen or the tab is hidden. On by
+    /// default; takes effect the next time visibility changes
+    pub fn set_pause_when_hidden(&self, enabled: bool) {
+        self.app_state.borrow_mut().set_pause_when_hidden(enabled);
+    }
+
+    /// Whether to suspend the game while the page doesn't have focus. Off by default; takes
+    /// effect the next time focus changes
+    pub fn set_pause_on_blur(&self, enabled: bool) {
+        self.app_state.borrow_mut().set_pause_on_blur(enabled);
+    }
+
+    /// Fills the screen behind the cave with a single colour, given as red, green and blue
+    /// between 0 and 1
+    pub fn set_backdrop_flat(&self, r: f32, g: f32, b: f32) {
+        self.app_state
+            .borrow_mut()
+            .set_backdrop(Backdrop::Flat(vec4(r, g, b, 1.0)));
+    }
+
+    /// Fills the screen behind the cave with a vertical gradient, from `shallow` at the surface
+    /// to `deep` once `depth` world units down. Colours are `[r, g, b]` between 0 and 1. Returns
+    /// false, leaving the backdrop alone, unless both colours have three parts and `depth` is
+    /// positive
+    pub fn set_backdrop_gradient(&self, shallow: Vec<f32>, deep: Vec<f32>, depth: f32) -> bool {
+        let (&[r0, g0, b0], &[r1, g1, b1]) = (&shallow[..], &deep[..]) else {
+            return false;
+        };
+        if depth <= 0.0 {
+            return false;
+        }
+        self.app_state
+            .borrow_mut()
+            .set_backdrop(Backdrop::Gradient {
+                shallow: vec4(r0, g0, b0, 1.0),
+                deep: vec4(r1, g1, b1, 1.0),
+                depth,
+            });
+        true
+    }
+
+    /// Draws the cave as an `"outline"` of the walls, the default, `"filled"` solid rock, or
+    /// `"filled-outlined"` for both. Returns false for any other name
+    pub fn set_cave_style(&self, style: &str) -> bool {
+        let Some(style) = CaveRenderStyle::from_name(style) else {
+            return false;
+        };
+        self.app_state.borrow_mut().set_cave_style(style);
+        true
+    }
+
+    /// Colours the cave walls by `"depth"`, the default, by `"lit"` to shade them by which way
+    /// they face, or plain with `"flat"`. Returns false for any other name
+    pub fn set_cave_tint(&self, tint: &str) -> bool {
+        let Some(tint) = CaveTint::from_name(tint) else {
+            return false;
+        };
+        self.app_state.borrow_mut().set_cave_tint(tint);
+        true
+    }
+
+    /// Tunes the anti-aliasing the player can turn on in the settings: `subpix` is how much to
+    /// soften detail smaller than a pixel, from 0 to 1, and an edge is smoothed once its contrast
+    /// reaches `edge_threshold` of its brightest side, and at least `edge_threshold_min`. 0.75,
+    /// 0.166 and 0.0833 by default
+    pub fn set_fxaa_params(&self, subpix: f32, edge_threshold: f32, edge_threshold_min: f32) {
+        self.app_state.borrow_mut().set_fxaa_params(FxaaParams {
+            subpix: subpix.clamp(0.0, 1.0),
+            edge_threshold,
+            edge_threshold_min,
+        });
+    }
+
+    /// Brings bright colours into range with extended `"reinhard"`, the default, a filmic
+    /// `"aces"` curve, or `"linear"` to clip them at white. `"none"` skips tone mapping, saving
+    /// its pass. Returns false for any other name
+    pub fn set_tonemap(&self, tonemap: &str) -> bool {
+        let tonemap = match tonemap {
+            "none" => None,
+            name => match ToneMap::from_name(name) {
+                Some(tonemap) => Some(tonemap),
+                None => return false,
+            },
+        };
+        self.app_state.borrow_mut().set_tonemap(tonemap);
+        true
+    }
+
+    /// Registers a function called as `callback(state, stats)` whenever the game changes state,
+    /// where `state` is numbered as in `current_state` and `stats` is an object holding the
+    /// run's `seed`, `depth`, `health`, `gems` collected and `score`, and whether it's a
+    /// `practice` run. Pass `undefined` to unregister
+    pub fn set_on_state_change(&self, callback: Option<js_sys::Function>) {
+        *self.on_state_change.borrow_mut() = callback;
+    }
+
+    /// The cave walls between `(min_x, min_y)` and `(max_x, max_y)`, in world units, as an SVG
+    /// document. The ship starts at the origin, and the shaft descends towards negative y
+    pub fn export_cave_svg(&self, min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> String {
+        self.app_state
+            .borrow()
+            .export_cave_svg(vec2(min_x, min_y), vec2(max_x, max_y))
+    }
+
+    /// The last finished run as a URL fragment, starting `#replay=`, which plays the run back
+    /// when added to a link to the game
+    pub fn last_replay_fragment(&self) -> Option<String> {
+        self.app_state
+            .borrow()
+            .last_replay()
+            .map(|replay| format!("{REPLAY_FRAGMENT_PREFIX}{}", replay.to_url_fragment()))
+    }
+
+    /// Remaps the controls. Each argument lists the `KeyboardEvent.code` values that do that
+    /// action, such as `["Space"]` to thrust with the space bar. Q, R, O, F, M, backquote and
+    /// full stop are reserved; bindings using them are rejected, returning false
+    pub fn set_key_bindings(
+        &self,
+        thrust: Vec<String>,
+        left: Vec<String>,
+        right: Vec<String>,
+        pause: Vec<String>,
+    ) -> bool {
+        self.app_state.borrow_mut().set_key_bindings(KeyBindings {
+            thrust,
+            left,
+            right,
+            pause,
+        })
+    }
+
+    /// Resizes the touch controls: the turning strips down each side take `side_width` of the
+    /// screen's width, and thrust takes `thrust_height` of its height between them at the bottom
+    pub fn set_touch_layout(&self, side_width: f32, thrust_height: f32) {
+        self.app_state.borrow_mut().set_touch_layout(TouchLayout {
+            side_width,
+            thrust_height,
+        });
+    }
+
+    /// Starts a run in the cave grown from `seed`, abandoning any run in progress
+    pub fn start_run(&self, seed: u32) {
+        self.app_state.borrow_mut().start_seeded_run(seed);
+        self.dispatch_state_changes();
+    }
+
+    /// The seed everyone playing today shares, to pass to `start_run`
+    pub fn daily_seed(&self) -> u32 {
+        daily_seed()
+    }
+
+    /// Plays back a run from a fragment made by `last_replay_fragment`, with or without the
+    /// leading `#replay=`. Returns false, and leaves the game alone, if it can't be read
+    pub fn play_replay(&self, fragment: &str) -> bool {
+        let fragment = fragment
+            .strip_prefix(REPLAY_FRAGMENT_PREFIX)
+            .unwrap_or(fragment);
+        let Some(replay) = Replay::from_url_fragment(fragment) else {
+            return false;
+        };
+
+        self.app_state.borrow_mut().start_playback(replay);
+        self.dispatch_state_changes();
+        true
+    }
+
+    /// Abandons the current run, if any, and goes back to the splash screen
+    pub fn return_to_splash(&self) {
+        self.app_state.borrow_mut().return_to_splash();
+        self.dispatch_state_changes();
+    }
+}
+
+fn state_change_stats(change: &StateChange) -> JsValue {
+    let stats = js_sys::Object::new();
+    let fields = [
+        ("seed", change.seed as f64),
+        ("depth", change.depth as f64),
+        ("health", change.health as f64),
+        ("gems", change.gems as f64),
+        ("score", change.score as f64),
+    ];
+    for (key, value) in fields {
+        let _ = js_sys::Reflect::set(&stats, &key.into(), &value.into());
+    }
+    let _ = js_sys::Reflect::set(&stats, &"practice".into(), &change.practice.into());
+    stats.into()
+}
+
+/// Returns the handle of the most recently started game, if any
+#[wasm_bindgen]
+pub fn app_handle() -> Option<AppHandle> {
+    CURRENT.with(|current| current.borrow().clone())
+}
@@ -0,0 +1,105 @@
+/// What a bound key does during a run
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Thrust,
+    TurnLeft,
+    TurnRight,
+    Pause,
+}
+
+/// Keys with fixed jobs during a run: Q quits, R resets a practice run, O opens the settings
+/// while paused, and backquote, F, full stop and M drive the debug overlay. Binding an action to one
+/// would do both at once, so they can't be bound. Keys that only do something on the splash
+/// screen or in the settings menu, like T or Enter, are free, since bindings only act in a run
+pub const RESERVED_KEYS: [&str; 7] = [
+    "KeyQ",
+    "KeyR",
+    "KeyO",
+    "Backquote",
+    "KeyF",
+    "Period",
+    "KeyM",
+];
+
+/// Which keys do what, as `KeyboardEvent.code` values such as `"KeyW"` or `"Space"`. Codes name
+/// physical keys, so bindings stay in the same place on any keyboard layout
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KeyBindings {
+    pub thrust: Vec<String>,
+    pub left: Vec<String>,
+    pub right: Vec<String>,
+    pub pause: Vec<String>,
+}
+
+impl Default for KeyBindings {
+    /// WASD and the arrow keys, with P or Escape to pause
+    fn default() -> Self {
+        let codes = |codes: &[&str]| codes.iter().map(|code| code.to_string()).collect();
+        Self {
+            thrust: codes(&["KeyW", "ArrowUp"]),
+            left: codes(&["KeyA", "ArrowLeft"]),
+            right: codes(&["KeyD", "ArrowRight"]),
+            pause: codes(&["KeyP", "Escape"]),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// The action bound to a key, if any. A key bound to several actions does the first of
+    /// thrust, left, right and pause
+    pub fn action(&self, code: &str) -> Option<Action> {
+        [
+            (&self.thrust, Action::Thrust),
+            (&self.left, Action::TurnLeft),
+            (&self.right, Action::TurnRight),
+            (&self.pause, Action::Pause),
+        ]
+        .into_iter()
+        .find(|(codes, _)| codes.iter().any(|bound| bound == code))
+        .map(|(_, action)| action)
+    }
+
+    /// The first bound key that is one of `RESERVED_KEYS`, if any
+    pub fn reserved_clash(&self) -> Option<&str> {
+        [&self.thrust, &self.left, &self.right, &self.pause]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .find(|code| RESERVED_KEYS.contains(code))
+    }
+}
+
+/// A key's name as players know it, such as `"P"` for `"KeyP"` or `"Up"` for `"ArrowUp"`
+pub fn key_name(code: &str) -> &str {
+    ["Key", "Digit", "Arrow"]
+        .into_iter()
+        .find_map(|prefix| code.strip_prefix(prefix))
+        .unwrap_or(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_avoid_reserved_keys() {
+        assert_eq!(KeyBindings::default().reserved_clash(), None);
+    }
+
+    #[test]
+    fn reserved_keys_clash() {
+        let bindings = KeyBindings {
+            pause: vec!["Escape".to_string(), "KeyQ".to_string()],
+            ..KeyBindings::default()
+        };
+        assert_eq!(bindings.reserved_clash(), Some("KeyQ"));
+    }
+
+    #[test]
+    fn key_names_drop_their_prefix() {
+        assert_eq!(key_name("KeyP"), "P");
+        assert_eq!(key_name("Digit1"), "1");
+        assert_eq!(key_name("ArrowUp"), "Up");
+        assert_eq!(key_name("Escape"), "Escape");
+    }
+}
@@ -1,32 +1,88 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
-use app::AppState;
+use background::Background;
+use glam::{Vec2, vec2};
+use handle::AppHandle;
+use post_processor::PostProcessor;
+use replay::Replay;
+use scribe::Scribe;
+use text::Text;
 use wasm_bindgen::prelude::*;
 use web_sys::{
-    Event, HtmlCanvasElement, KeyboardEvent, WebGl2RenderingContext, WebGlProgram, WebGlShader,
+    Event, HtmlCanvasElement, IntersectionObserver, IntersectionObserverEntry, KeyboardEvent,
+    TouchEvent, WebGl2RenderingContext, WebGlProgram, WebGlShader,
 };
 use web_time::{Duration, Instant};
 
 mod app;
+mod audio;
+mod background;
+mod drawable;
+mod effects;
 mod font;
+mod gamepad;
+mod handle;
+mod key_bindings;
 mod mine_shaft;
+mod options;
+mod physics;
 mod polyline;
 mod post_processor;
+mod render_target;
+mod replay;
+mod rng;
 mod scribe;
+mod settings;
 mod shader;
+mod storage;
+mod svg;
 mod text;
 mod texture;
+mod touch_controls;
+mod uniform_block;
 
 const UPDATE_RATE: usize = 120;
 const UPDATE_DURATION: f32 = 1.0 / UPDATE_RATE as f32;
 
+/// The canvas the game starts in automatically, if the page has one
+const DEFAULT_CANVAS_ID: &str = "canvas";
+
+/// Marks a replay in the page URL's fragment, as in `#replay=...`
+pub const REPLAY_FRAGMENT_PREFIX: &str = "#replay=";
+
 fn main() -> Result<(), JsValue> {
     workflow_panic_hook::set_once(workflow_panic_hook::Type::Console);
     wasm_log::init(wasm_log::Config::default());
 
     log::info!("Hello, world!");
 
-    let canvas = document().get_element_by_id("canvas").unwrap();
+    // pages without the default canvas start the game themselves, with `fathom_start`
+    if document().get_element_by_id(DEFAULT_CANVAS_ID).is_some() {
+        start(DEFAULT_CANVAS_ID, &JsValue::UNDEFINED)?;
+    }
+
+    Ok(())
+}
+
+/// Starts a game in the canvas with the given id, or the default canvas if the id is empty, and
+/// returns a handle to it. `options` is an optional object configuring the game, as described by
+/// `options::builder_from_options`
+#[wasm_bindgen]
+pub fn fathom_start(canvas_id: &str, options: JsValue) -> Result<AppHandle, JsValue> {
+    if canvas_id.is_empty() {
+        start(DEFAULT_CANVAS_ID, &options)
+    } else {
+        start(canvas_id, &options)
+    }
+}
+
+fn start(canvas_id: &str, options: &JsValue) -> Result<AppHandle, JsValue> {
+    let canvas = document()
+        .get_element_by_id(canvas_id)
+        .ok_or_else(|| format!("no element with id {canvas_id:?}"))?;
     let canvas: HtmlCanvasElement = canvas.dyn_into::<HtmlCanvasElement>()?;
 
     let context = canvas
@@ -34,7 +90,19 @@ fn main() -> Result<(), JsValue> {
         .unwrap()
         .dyn_into::<WebGl2RenderingContext>()?;
 
-    let app_state = Rc::new(RefCell::new(AppState::new(&context)?));
+    let app_state = options::builder_from_options(options).build(&context)?;
+    let app_state = Rc::new(RefCell::new(app_state));
+    let handle = AppHandle::new(app_state.clone());
+    handle.register();
+
+    // links to shared runs start straight into playing them back
+    let hash = window().location().hash().unwrap_or_default();
+    if let Some(fragment) = hash.strip_prefix(REPLAY_FRAGMENT_PREFIX) {
+        match Replay::from_url_fragment(fragment) {
+            Some(replay) => app_state.borrow_mut().start_playback(replay),
+            None => log::warn!("ignoring malformed replay link"),
+        }
+    }
 
     let onresize = {
         let canvas = canvas.clone();
@@ -51,9 +119,11 @@ fn main() -> Result<(), JsValue> {
 
     let keydown = {
         let app_state = app_state.clone();
+        let handle = handle.clone();
 
         Closure::<dyn FnMut(_)>::new(move |event: KeyboardEvent| {
             app_state.borrow_mut().on_keydown(event);
+            handle.dispatch_state_changes();
         })
     };
     document().add_event_listener_with_callback("keydown", keydown.as_ref().unchecked_ref())?;
@@ -69,38 +139,205 @@ fn main() -> Result<(), JsValue> {
     document().add_event_listener_with_callback("keyup", keyup.as_ref().unchecked_ref())?;
     keyup.forget();
 
-    let f = Rc::new(RefCell::<Option<Closure<dyn FnMut()>>>::new(None));
-    let g = f.clone();
+    for event in ["touchstart", "touchmove", "touchend", "touchcancel"] {
+        let canvas_ref = canvas.clone();
+        let app_state = app_state.clone();
+        let handle = handle.clone();
+
+        let listener = Closure::<dyn FnMut(_)>::new(move |event: TouchEvent| {
+            // keep the page from scrolling or zooming under the player's fingers
+            event.prevent_default();
+
+            let rect = canvas_ref.get_bounding_client_rect();
+            let list = event.touches();
+            let touches: Vec<Vec2> = (0..list.length())
+                .filter_map(|i| list.get(i))
+                .map(|touch| {
+                    vec2(
+                        ((touch.client_x() as f64 - rect.left()) / rect.width()) as f32,
+                        ((touch.client_y() as f64 - rect.top()) / rect.height()) as f32,
+                    )
+                })
+                .collect();
+
+            let mut app_state = app_state.borrow_mut();
+            if event.type_() == "touchstart" {
+                app_state.on_touchstart(&touches);
+            } else {
+                app_state.on_touchmove(&touches);
+            }
+            drop(app_state);
+            handle.dispatch_state_changes();
+        });
+        canvas.add_event_listener_with_callback(event, listener.as_ref().unchecked_ref())?;
+        listener.forget();
+    }
+
+    // browsers only report gamepads once one is used, so don't poll until then
+    let gamepads = Rc::new(Cell::new(0));
+    for (event, change) in [("gamepadconnected", 1), ("gamepaddisconnected", -1)] {
+        let app_state = app_state.clone();
+        let gamepads = gamepads.clone();
+        let listener = Closure::<dyn FnMut(_)>::new(move |_event: Event| {
+            gamepads.set((gamepads.get() + change).max(0));
+            log::info!("{event}, {} connected", gamepads.get());
+            // let go of anything the pad was holding, handing back to the keyboard
+            app_state.borrow_mut().set_analog_input(0.0, 0.0);
+        });
+        window().add_event_listener_with_callback(event, listener.as_ref().unchecked_ref())?;
+        listener.forget();
+    }
+
+    let frame_loop = Rc::new(RefCell::new(FrameLoop {
+        callback: None,
+        running: false,
+        frame: 0,
+        last: Instant::now(),
+    }));
 
     {
-        let mut last = Instant::now();
+        let frame_loop_ref = frame_loop.clone();
         let context = context.clone();
-        *g.borrow_mut() = Some(Closure::<dyn FnMut()>::new(move || {
+        let app_state = app_state.clone();
+        let handle = handle.clone();
+        frame_loop.borrow_mut().callback = Some(Closure::<dyn FnMut()>::new(move || {
+            if gamepads.get() > 0
+                && let Some(pad) = gamepad::poll()
+            {
+                let mut app_state = app_state.borrow_mut();
+                app_state.set_pad_buttons(pad.confirm, pad.start);
+                app_state.set_analog_input(pad.thrust, pad.turn);
+            }
+
             let now = Instant::now();
-            while now.duration_since(last).as_secs_f32() > UPDATE_DURATION {
-                app_state.borrow_mut().fixed_update(UPDATE_DURATION);
-                last += Duration::from_secs_f32(UPDATE_DURATION);
+            let mut last = frame_loop_ref.borrow().last;
+            if app_state.borrow().frame_stepping() {
+                // time stands still while stepping, apart from one update per request
+                if app_state.borrow_mut().take_step() {
+                    app_state.borrow_mut().fixed_update(UPDATE_DURATION);
+                }
+                last = now;
+            } else {
+                last = catch_up(last, now, |dt| app_state.borrow_mut().fixed_update(dt));
             }
 
-            app_state.borrow_mut().draw(&context);
+            let alpha = now.duration_since(last).as_secs_f32() / UPDATE_DURATION;
+            app_state.borrow_mut().draw(&context, alpha);
+            handle.dispatch_state_changes();
 
-            request_animation_frame(f.borrow().as_ref().unwrap());
+            let mut frame_loop = frame_loop_ref.borrow_mut();
+            frame_loop.last = last;
+            frame_loop.frame = request_animation_frame(frame_loop.callback.as_ref().unwrap());
         }));
     }
 
-    request_animation_frame(g.borrow().as_ref().unwrap());
+    frame_loop.borrow_mut().resume();
 
-    Ok(())
+    // sleep while the canvas is scrolled out of view or the tab is hidden, and optionally while
+    // the page doesn't have focus
+    let on_screen = Rc::new(Cell::new(true));
+    let focused = Rc::new(Cell::new(document().has_focus().unwrap_or(true)));
+    let update_visibility = {
+        let frame_loop = frame_loop.clone();
+        let app_state = app_state.clone();
+        let on_screen = on_screen.clone();
+        let focused = focused.clone();
+        Rc::new(move || {
+            let app_state = app_state.borrow();
+            let hidden = !on_screen.get() || document().hidden();
+            if (hidden && app_state.pause_when_hidden())
+                || (!focused.get() && app_state.pause_on_blur())
+            {
+                frame_loop.borrow_mut().pause();
+            } else {
+                frame_loop.borrow_mut().resume();
+            }
+        })
+    };
+
+    let intersection = {
+        let update_visibility = update_visibility.clone();
+        Closure::<dyn FnMut(_)>::new(move |entries: js_sys::Array| {
+            if let Some(entry) = entries.iter().last() {
+                let entry: IntersectionObserverEntry = entry.unchecked_into();
+                on_screen.set(entry.is_intersecting());
+            }
+            update_visibility();
+        })
+    };
+    IntersectionObserver::new(intersection.as_ref().unchecked_ref())?.observe(&canvas);
+    intersection.forget();
+
+    let visibilitychange = {
+        let update_visibility = update_visibility.clone();
+        Closure::<dyn FnMut(_)>::new(move |_event: Event| update_visibility())
+    };
+    document().add_event_listener_with_callback(
+        "visibilitychange",
+        visibilitychange.as_ref().unchecked_ref(),
+    )?;
+    visibilitychange.forget();
+
+    for (event, has_focus) in [("focus", true), ("blur", false)] {
+        let update_visibility = update_visibility.clone();
+        let focused = focused.clone();
+        let listener = Closure::<dyn FnMut(_)>::new(move |_event: Event| {
+            focused.set(has_focus);
+            update_visibility();
+        });
+        window().add_event_listener_with_callback(event, listener.as_ref().unchecked_ref())?;
+        listener.forget();
+    }
+
+    Ok(handle)
+}
+
+/// Runs as many fixed updates as fit between `last` and `now`, and returns the time simulated up
+/// to. However often frames come, the simulation advances in the same steps
+fn catch_up(mut last: Instant, now: Instant, mut fixed_update: impl FnMut(f32)) -> Instant {
+    while now.duration_since(last).as_secs_f32() > UPDATE_DURATION {
+        fixed_update(UPDATE_DURATION);
+        last += Duration::from_secs_f32(UPDATE_DURATION);
+    }
+    last
+}
+
+/// The render loop, which can be suspended while nobody can see the canvas
+struct FrameLoop {
+    callback: Option<Closure<dyn FnMut()>>,
+    running: bool,
+    /// The pending animation frame request, so it can be cancelled
+    frame: i32,
+    /// The time simulated up to
+    last: Instant,
+}
+
+impl FrameLoop {
+    fn pause(&mut self) {
+        if self.running {
+            self.running = false;
+            let _ = window().cancel_animation_frame(self.frame);
+        }
+    }
+
+    fn resume(&mut self) {
+        if !self.running {
+            self.running = true;
+            // skip over the time spent suspended, rather than simulating it all at once
+            self.last = Instant::now();
+            self.frame = request_animation_frame(self.callback.as_ref().unwrap());
+        }
+    }
 }
 
 pub fn window() -> web_sys::Window {
     web_sys::window().expect("no global `window` exists")
 }
 
-pub fn request_animation_frame(f: &Closure<dyn FnMut()>) {
+pub fn request_animation_frame(f: &Closure<dyn FnMut()>) -> i32 {
     window()
         .request_animation_frame(f.as_ref().unchecked_ref())
-        .expect("should register `requestAnimationFrame` OK");
+        .expect("should register `requestAnimationFrame` OK")
 }
 
 pub fn document() -> web_sys::Document {
@@ -135,11 +372,77 @@ pub fn compile_shader(
         let log = context
             .get_shader_info_log(&shader)
             .unwrap_or_else(|| String::from("Unknown error creating shader"));
-        log::error!("failed to compile shader: {log}");
-        Err(log)
+        let message = annotate_shader_log(source, &log);
+        log::error!("failed to compile shader: {message}");
+        Err(message)
     }
 }
 
+/// Appends the source lines reported in a shader info log, with a little surrounding context, so
+/// errors in the inline GLSL can be located at a glance
+fn annotate_shader_log(source: &str, log: &str) -> String {
+    const CONTEXT_LINES: usize = 2;
+
+    let lines: Vec<&str> = source.lines().collect();
+    let mut message = log.trim_end().to_string();
+
+    // drivers often report several errors against the same line
+    let mut reported: Vec<usize> = log.lines().filter_map(reported_line).collect();
+    reported.dedup();
+
+    for number in reported {
+        if number == 0 || number > lines.len() {
+            continue;
+        }
+
+        message.push_str(&format!("\n\nat line {number}:"));
+
+        let first = number.saturating_sub(CONTEXT_LINES).max(1);
+        let last = (number + CONTEXT_LINES).min(lines.len());
+        for i in first..=last {
+            let marker = if i == number { '>' } else { ' ' };
+            message.push_str(&format!("\n{marker}{i:4} | {}", lines[i - 1]));
+        }
+    }
+
+    message
+}
+
+/// Extracts the line number from an info log entry like `ERROR: 0:12: 'foo' : undeclared identifier`
+fn reported_line(entry: &str) -> Option<usize> {
+    let (_, rest) = entry
+        .split_once("ERROR:")
+        .or_else(|| entry.split_once("WARNING:"))?;
+
+    // skip the source string index, which is always 0 for us
+    let mut parts = rest.split(':');
+    parts.next()?;
+    parts.next()?.trim().parse().ok()
+}
+
+/// Compiles every shader program the renderers use in one batch, so constructing them doesn't
+/// stall on each program in turn. Called by `AppStateBuilder::build` before any renderer is made
+fn precompile_shaders(context: &WebGl2RenderingContext) {
+    let start = Instant::now();
+
+    let sources = [
+        Background::SHADERS,
+        Scribe::SHADERS,
+        Text::SHADERS,
+        PostProcessor::SHADERS,
+    ]
+    .concat();
+    shader::precompile(context, &sources);
+
+    log::info!(
+        "precompiled {} shader programs in {:?}",
+        sources.len(),
+        start.elapsed()
+    );
+}
+
+/// Links a program from two compiled shaders, deleting the shaders afterwards, since the program
+/// no longer needs them
 pub fn link_program(
     context: &WebGl2RenderingContext,
     vert_shader: &WebGlShader,
@@ -153,17 +456,25 @@ pub fn link_program(
     context.attach_shader(&program, frag_shader);
     context.link_program(&program);
 
-    if context
+    let linked = context
         .get_program_parameter(&program, WebGl2RenderingContext::LINK_STATUS)
         .as_bool()
-        .unwrap_or(false)
-    {
+        .unwrap_or(false);
+
+    // a linked program keeps its own copy of the code, so the shaders can go either way
+    for shader in [vert_shader, frag_shader] {
+        context.detach_shader(&program, shader);
+        context.delete_shader(Some(shader));
+    }
+
+    if linked {
         Ok(program)
     } else {
         let log = context
             .get_program_info_log(&program)
             .unwrap_or_else(|| String::from("Unknown error creating program object"));
         log::error!("failed to link program: {log}");
+        context.delete_program(Some(&program));
         Err(log)
     }
 }
@@ -174,3 +485,57 @@ pub fn reinterpret_cast_slice<S, T>(input: &[S]) -> &[T] {
     // log::info!("size_of T {}, total size {}", std::mem::size_of::<T>(), length_in_bytes);
     unsafe { std::slice::from_raw_parts(input.as_ptr() as *const T, desired_length) }
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::Mat3;
+
+    use super::*;
+    use crate::{app::Entity, physics::PhysicsConfig};
+
+    /// Flies a ship with the same input for `seconds`, rendering at `fps`
+    fn fly_at_frame_rate(fps: f32, seconds: f32) -> Entity {
+        let physics = PhysicsConfig::default();
+        let mut ship = Entity {
+            transform: Mat3::IDENTITY,
+            vel: Vec2::ZERO,
+            angular_vel: 0.0,
+            radius: 7.0,
+        };
+
+        let start = Instant::now();
+        let end = start + Duration::from_secs_f32(seconds);
+        let frame = Duration::from_secs_f32(1.0 / fps);
+
+        let mut last = start;
+        let mut now = start;
+        while now < end {
+            now = (now + frame).min(end);
+            last = catch_up(last, now, |dt| {
+                ship.drift(dt);
+                ship.fly(
+                    &physics,
+                    physics.thrust,
+                    -0.5,
+                    vec2(0.0, -physics.gravity),
+                    dt,
+                );
+            });
+        }
+        ship
+    }
+
+    #[test]
+    fn ship_moves_the_same_at_any_frame_rate() {
+        // end partway between ticks, so rounding can't tip the last one either way
+        let seconds = 1.5 + 0.4 * UPDATE_DURATION;
+        let reference = fly_at_frame_rate(60.0, seconds);
+
+        for fps in [24.0, 30.0, 75.0, 144.0, 240.0] {
+            let ship = fly_at_frame_rate(fps, seconds);
+            assert_eq!(ship.transform, reference.transform, "position at {fps} fps");
+            assert_eq!(ship.vel, reference.vel, "velocity at {fps} fps");
+            assert_eq!(ship.angular_vel, reference.angular_vel, "spin at {fps} fps");
+        }
+    }
+}
@@ -1,30 +1,145 @@
+use std::{
+    cell::{RefCell, RefMut},
+    collections::{HashMap, HashSet},
+};
+
 use glam::{Vec2, vec2};
-use noise::{NoiseFn, Perlin};
+use noise::{NoiseFn, Perlin, Seedable};
+
+use crate::rng::Rng;
+
+/// How a surface responds to being hit
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Material {
+    /// Fraction of the velocity into the wall that is kept, as a bounce
+    pub restitution: f32,
+    /// Fraction of the velocity along the wall that is lost on contact
+    pub friction: f32,
+}
+
+impl Material {
+    pub const ROCK: Material = Material {
+        restitution: 0.5,
+        friction: 0.125,
+    };
+    pub const ICE: Material = Material {
+        restitution: 0.3,
+        friction: 0.02,
+    };
+    pub const RUBBLE: Material = Material {
+        restitution: 0.2,
+        friction: 0.4,
+    };
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self::ROCK
+    }
+}
 
 pub struct MineShaft {
     pub width: f32,
     pub height: f32,
+    /// Half the width of the shaft at the surface
     pub shaft_radius: f32,
+    /// Half the width of the shaft from `narrowing_depth` down
+    pub deep_shaft_radius: f32,
     pub noise_scale: f32,
+    /// How far the noise pushes the walls in or out at the surface
     pub noise_amplitude: f32,
+    /// How far the noise pushes the walls in or out from `narrowing_depth` down
+    pub deep_noise_amplitude: f32,
+    /// Depth over which the shaft narrows and its walls grow more jagged, going from the surface
+    /// values to the deep ones
+    pub narrowing_depth: f32,
+    /// Number of layers of noise summed into the walls. Each layer adds finer detail, and 1 gives
+    /// the smooth walls of plain Perlin noise
+    pub noise_octaves: u32,
+    /// How much the frequency increases with each octave
+    pub noise_lacunarity: f32,
+    /// How much the amplitude falls off with each octave
+    pub noise_persistence: f32,
+    /// Vertical distance between side tunnels branching off the shaft. 0 digs no side tunnels
+    pub tunnel_spacing: f32,
+    /// Longest a side tunnel reaches out from the middle of the shaft. Each is somewhere between
+    /// half this and all of it
+    pub tunnel_length: f32,
+    /// Half the height of a side tunnel
+    pub tunnel_radius: f32,
+    /// Whether some sections of wall are ice or rubble, as they are by default. Otherwise every
+    /// wall is `Material::ROCK`
+    pub varied_materials: bool,
     pub noise: Perlin,
+    /// Meshes from the last grid position, which the camera usually stays at for many frames
+    marching_cache: RefCell<Option<MarchingCache>>,
 }
 
 impl MineShaft {
+    /// A shaft grown from seed 0, the same cave every time. Runs are each seeded separately, so
+    /// only tests dig this one
+    #[cfg_attr(not(test), expect(dead_code))]
     pub fn new(width: f32, height: f32) -> Self {
+        Self::with_seed(width, height, 0)
+    }
+
+    /// A shaft whose walls are generated from `seed`, so the same seed always digs the same cave
+    pub fn with_seed(width: f32, height: f32, seed: u32) -> Self {
         Self {
             width,
             height,
             shaft_radius: 60.0,
+            deep_shaft_radius: 40.0,
             noise_scale: 1.0 / 80.0,
             noise_amplitude: 60.0,
-            noise: Perlin::new(3),
+            deep_noise_amplitude: 80.0,
+            narrowing_depth: 3000.0,
+            noise_octaves: 4,
+            noise_lacunarity: 2.0,
+            noise_persistence: 0.5,
+            tunnel_spacing: 500.0,
+            tunnel_length: 260.0,
+            tunnel_radius: 22.0,
+            varied_materials: true,
+            noise: Perlin::new(seed),
+            marching_cache: RefCell::new(None),
         }
     }
 
+    /// Horizontal position of the middle of the shaft at height `y`
+    fn shaft_center(&self, y: f32) -> f32 {
+        (y * 0.01).sin() * 35.0
+    }
+
     /// Signed distance from vertical shaft wall
     fn shaft_distance(&self, p: Vec2, radius: f32) -> f32 {
-        radius - (p.x - (p.y * 0.01).sin() * 35.0).abs()
+        radius - (p.x - self.shaft_center(p.y)).abs()
+    }
+
+    /// Signed distance from the nearest side tunnel. Each slot of `tunnel_spacing` below the
+    /// surface has one tunnel, whose height, side, length and slope are picked from the seed
+    fn tunnel_distance(&self, p: Vec2) -> f32 {
+        if self.tunnel_spacing <= 0.0 {
+            return f32::NEG_INFINITY;
+        }
+
+        // tunnels stay near their own slot, so only the neighbouring slots can be the nearest
+        let slot = (-p.y / self.tunnel_spacing).floor() as i32;
+        (slot - 1..=slot + 1)
+            // the first slot would cut into the starting zone
+            .filter(|&slot| slot >= 1)
+            .map(|slot| {
+                let mut rng = Rng::new(self.seed() ^ (slot as u32).wrapping_mul(TUNNEL_SLOT_HASH));
+                let y = -(slot as f32 + rng.range(0.25, 0.75)) * self.tunnel_spacing;
+                let side = if rng.next_f32() < 0.5 { -1.0 } else { 1.0 };
+                let length = self.tunnel_length * rng.range(0.5, 1.0);
+                let slope = rng.range(-MAX_TUNNEL_SLOPE, MAX_TUNNEL_SLOPE);
+
+                let start = vec2(self.shaft_center(y), y);
+                let end = start + vec2(side, slope).normalize() * length;
+                self.tunnel_radius - distance_to_segment(p, start, end)
+            })
+            .fold(f32::NEG_INFINITY, f32::max)
     }
 
     fn secondary_shaft_distance(&self, p: Vec2) -> f32 {
@@ -33,103 +148,576 @@ impl MineShaft {
 
     /// Signed distance from a circular starting zone
     fn starting_zone_distance(&self, p: Vec2) -> f32 {
-        100.0 - p.length()
+        STARTING_ZONE_RADIUS - p.length()
     }
 
-    fn noise(&self, mut p: Vec2) -> f32 {
+    /// Fractal noise, summing `noise_octaves` layers of Perlin noise. The sum is normalised so
+    /// `amplitude` bounds the result however many octaves there are
+    fn noise(&self, mut p: Vec2, amplitude: f32) -> f32 {
         p *= self.noise_scale;
-        self.noise.get([p.x as f64, p.y as f64]) as f32 * self.noise_amplitude
+
+        let mut sum = 0.0;
+        let mut total_amplitude = 0.0;
+        let mut octave_amplitude = 1.0;
+        for _ in 0..self.noise_octaves.max(1) {
+            sum += self.noise.get([p.x as f64, p.y as f64]) as f32 * octave_amplitude;
+            total_amplitude += octave_amplitude;
+            octave_amplitude *= self.noise_persistence;
+            p *= self.noise_lacunarity;
+        }
+
+        sum / total_amplitude * amplitude
     }
 
     /// Final combined distance field at a point
     pub fn distance(&self, p: Vec2) -> f32 {
-        let shaft = self.shaft_distance(p, self.shaft_radius);
-        let noise = self.noise(p);
-        let starting_zone = self.starting_zone_distance(p);
+        // the walls depend on the depth of the point itself, so the cave stays put however deep
+        // the ship is, and the surface, starting zone included, is unchanged
+        let t = if self.narrowing_depth > 0.0 {
+            (-p.y / self.narrowing_depth).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let radius = self.shaft_radius + (self.deep_shaft_radius - self.shaft_radius) * t;
+        let amplitude =
+            self.noise_amplitude + (self.deep_noise_amplitude - self.noise_amplitude) * t;
+
+        let shaft = self.shaft_distance(p, radius);
+        let noise = self.noise(p, amplitude);
+        // the starting zone only opens up space within its radius. Any further down it can only
+        // win the max deep inside the rock, where the exact distance doesn't matter
+        let starting_zone = if p.y < -STARTING_ZONE_CUTOFF {
+            f32::NEG_INFINITY
+        } else {
+            self.starting_zone_distance(p)
+        };
         let shaft_clear_zone = self.secondary_shaft_distance(p);
+        // tunnels are only roughened a little, or the noise would often pinch them shut
+        let tunnels = self.tunnel_distance(p) - noise * TUNNEL_ROUGHNESS;
+
+        f32::max(
+            shaft_clear_zone,
+            f32::max(starting_zone, f32::max(shaft - noise, tunnels)),
+        )
+    }
+
+    /// The material of the wall nearest to `p`. Materials come in large patches, sampled from a
+    /// separate slice of the noise so they don't line up with the shape of the walls
+    pub fn material_at(&self, p: Vec2) -> Material {
+        if !self.varied_materials {
+            return Material::default();
+        }
 
-        f32::max(shaft_clear_zone, f32::max(starting_zone, shaft - noise))
+        const MATERIAL_SCALE: f32 = 1.0 / 400.0;
+        const MATERIAL_SLICE: f64 = 17.5;
+
+        let q = p * MATERIAL_SCALE;
+        let n = self.noise.get([q.x as f64, q.y as f64, MATERIAL_SLICE]);
+        if n > 0.3 {
+            Material::ICE
+        } else if n < -0.3 {
+            Material::RUBBLE
+        } else {
+            Material::ROCK
+        }
+    }
+
+    /// The seed the walls were generated from
+    pub fn seed(&self) -> u32 {
+        self.noise.seed()
+    }
+
+    /// Regenerates the walls from a different seed, keeping the rest of the shape
+    pub fn set_seed(&mut self, seed: u32) {
+        self.noise = Perlin::new(seed);
+    }
+
+    /// Scatters up to `count` points over the rectangle from `min` to `max`, each at least
+    /// `min_clearance` out into the open cave. The same seed always gives the same points, and
+    /// fewer come back where open space is too scarce to find them all
+    pub fn sample_open_points(
+        &self,
+        min: Vec2,
+        max: Vec2,
+        min_clearance: f32,
+        count: usize,
+        seed: u32,
+    ) -> Vec<Vec2> {
+        let mut rng = Rng::new(seed);
+        let mut points = Vec::with_capacity(count);
+
+        for _ in 0..count * MAX_SAMPLE_ATTEMPTS {
+            if points.len() == count {
+                break;
+            }
+
+            let p = vec2(rng.range(min.x, max.x), rng.range(min.y, max.y));
+            if self.distance(p) > min_clearance {
+                points.push(p);
+            }
+        }
+
+        points
+    }
+
+    /// Whether a point lies in the open cave, i.e. on the positive side of the distance field
+    pub fn is_open(&self, p: Vec2) -> bool {
+        self.distance(p) > 0.0
     }
 
+    /// Direction out of the nearest wall at `p`, as the normalised gradient of the distance
+    /// field. `None` where the field is flat, such as deep inside the rock or where two walls
+    /// cancel out, since there's no sensible way out there
     pub fn normal(&self, p: Vec2) -> Option<Vec2> {
-        let eps = 0.05;
-        let dx = self.distance(p + vec2(eps, 0.0)) - self.distance(p - vec2(eps, 0.0));
-        let dy = self.distance(p + vec2(0.0, eps)) - self.distance(p - vec2(0.0, eps));
-        vec2(dx, dy).try_normalize()
+        let dx = self.distance(p + vec2(NORMAL_EPSILON, 0.0))
+            - self.distance(p - vec2(NORMAL_EPSILON, 0.0));
+        let dy = self.distance(p + vec2(0.0, NORMAL_EPSILON))
+            - self.distance(p - vec2(0.0, NORMAL_EPSILON));
+        let gradient = vec2(dx, dy) / (2.0 * NORMAL_EPSILON);
+
+        (gradient.length_squared() > MIN_NORMAL_GRADIENT * MIN_NORMAL_GRADIENT)
+            .then(|| gradient.normalize())
     }
 
-    pub fn marching_squares(&self, resolution: f32, center: Vec2) -> Vec<Vec2> {
+    /// Line segments, in pairs of points, tracing where the distance field equals `iso`. Level 0
+    /// is the walls themselves, and positive levels run through the open cave, parallel to them
+    pub fn marching_squares_at_level(&self, resolution: f32, center: Vec2, iso: f32) -> Vec<Vec2> {
+        self.cached(MeshKind::Outline, resolution, center, iso, || {
+            self.trace_level(resolution, center, iso)
+        })
+    }
+
+    fn trace_level(&self, resolution: f32, center: Vec2, iso: f32) -> Vec<Vec2> {
         let mut segments = Vec::new();
 
-        let offset = center - vec2(self.width, self.height) * 0.5;
+        self.for_each_cell(resolution, center, iso, |cell| {
+            let center_solid = self.distance(cell.center()) < iso;
+
+            for &(a, b) in resolve_case(cell.index(), center_solid) {
+                segments.push(cell.edge(a as usize));
+                segments.push(cell.edge(b as usize));
+            }
+        });
+
+        segments
+    }
+
+    /// Traces the walls within the rectangle from `min` to `max` into polylines, each running with
+    /// solid rock on its right. Closed loops end by repeating their first point; the rest are cut
+    /// off by the edge of the rectangle
+    pub fn contours(&self, resolution: f32, min: Vec2, max: Vec2) -> Vec<Vec<Vec2>> {
+        // segments are keyed by the grid edges they join, so neighbouring cells can be chained
+        // exactly, rather than by comparing interpolated positions
+        let mut next = HashMap::new();
+        let mut points = HashMap::new();
+
+        self.for_each_cell_in(resolution, min, max - min, 0.0, |cell| {
+            let center_solid = self.distance(cell.center()) < 0.0;
 
-        let cols = (self.width / resolution) as i32;
-        let rows = (self.height / resolution) as i32;
+            for &(a, b) in resolve_case(cell.index(), center_solid) {
+                let (a, b) = (a as usize, b as usize);
+                next.insert(cell.edge_key(a), cell.edge_key(b));
+                points
+                    .entry(cell.edge_key(a))
+                    .or_insert_with(|| cell.edge(a));
+                points
+                    .entry(cell.edge_key(b))
+                    .or_insert_with(|| cell.edge(b));
+            }
+        });
+
+        // contours cut off by the rectangle start where no segment ends, and have to be followed
+        // from there. Anything left over after those is a closed loop. Starts are sorted so the
+        // output doesn't depend on hash order
+        let ends: HashSet<EdgeKey> = next.values().copied().collect();
+        let mut open: Vec<EdgeKey> = next.keys().filter(|k| !ends.contains(k)).copied().collect();
+        open.sort();
+        let mut closed: Vec<EdgeKey> = next.keys().filter(|k| ends.contains(k)).copied().collect();
+        closed.sort();
+
+        let mut contours = Vec::new();
+        for start in open.into_iter().chain(closed) {
+            if !next.contains_key(&start) {
+                continue;
+            }
+
+            let mut contour = vec![points[&start]];
+            let mut key = start;
+            while let Some(end) = next.remove(&key) {
+                contour.push(points[&end]);
+                key = end;
+            }
+            contours.push(contour);
+        }
+
+        contours
+    }
+
+    /// The walls in the same grid as `marching_squares_at_level`, chained into polylines by `contours`, so
+    /// they can be stroked with proper joins and have their straight runs merged
+    pub fn outline(&self, resolution: f32, center: Vec2) -> Vec<Vec<Vec2>> {
+        let mut cache = self.grid_cache(resolution, center);
+        if let Some(outline) = &cache.outline {
+            return outline.clone();
+        }
+
+        let size = vec2(self.width, self.height);
+        let outline = self.contours(resolution, center - size * 0.5, center + size * 0.5);
+        cache.outline = Some(outline.clone());
+        outline
+    }
+
+    /// Like `outline`, but pairs each point with the normal of the wall there, pointing out into
+    /// the open cave. Where the distance field is too flat for `normal`, the perpendicular of the
+    /// polyline stands in. The normals are cached along with the outline
+    pub fn outline_with_normals(&self, resolution: f32, center: Vec2) -> Vec<Vec<(Vec2, Vec2)>> {
+        let outline = self.outline(resolution, center);
+        let mut cache = self.grid_cache(resolution, center);
+        let normals = cache.outline_normals.get_or_insert_with(|| {
+            outline
+                .iter()
+                .map(|contour| {
+                    (0..contour.len())
+                        .map(|i| {
+                            // rock is on the right of the polyline, so the open side is on its
+                            // left. The last point has no segment after it, so uses the one before
+                            let segment = i.min(contour.len().saturating_sub(2));
+                            let fallback = contour
+                                .get(segment + 1)
+                                .map_or(Vec2::ZERO, |&next| next - contour[segment])
+                                .perp()
+                                .normalize_or_zero();
+                            self.normal(contour[i]).unwrap_or(fallback)
+                        })
+                        .collect()
+                })
+                .collect()
+        });
+
+        outline
+            .iter()
+            .zip(normals.iter())
+            .map(|(contour, normals)| {
+                contour
+                    .iter()
+                    .copied()
+                    .zip(normals.iter().copied())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Triangulates the solid rock in the same grid as `marching_squares_at_level`, returning a
+    /// list of triangles that exactly meets the outline
+    pub fn marching_squares_filled(&self, resolution: f32, center: Vec2) -> Vec<Vec2> {
+        self.cached(MeshKind::Filled, resolution, center, 0.0, || {
+            self.triangulate_solid(resolution, center)
+        })
+    }
+
+    fn triangulate_solid(&self, resolution: f32, center: Vec2) -> Vec<Vec2> {
+        let mut triangles = Vec::new();
+
+        self.for_each_cell(resolution, center, 0.0, |cell| {
+            let index = cell.index();
+            if index == 0 {
+                return;
+            }
+
+            // saddles with an open centre are two separate corners of rock, matching the outline
+            if (index == 5 || index == 10) && self.distance(cell.center()) >= 0.0 {
+                for i in (0..4).filter(|&i| cell.is_solid(i)) {
+                    triangles.extend([cell.corners[i], cell.edge(i), cell.edge((i + 3) % 4)]);
+                }
+                return;
+            }
+
+            // otherwise the rock is a single polygon, convex since every point lies on the
+            // cell boundary, so it can be fanned
+            let mut polygon = [Vec2::ZERO; 8];
+            let mut len = 0;
+            for i in 0..4 {
+                if cell.is_solid(i) {
+                    polygon[len] = cell.corners[i];
+                    len += 1;
+                }
+                if cell.is_solid(i) != cell.is_solid((i + 1) % 4) {
+                    polygon[len] = cell.edge(i);
+                    len += 1;
+                }
+            }
+
+            for k in 1..len - 1 {
+                triangles.extend([polygon[0], polygon[k], polygon[k + 1]]);
+            }
+        });
+
+        triangles
+    }
+
+    /// Returns the mesh built for the same grid last time, or builds and keeps it. Everything
+    /// cached is thrown away once the grid moves or the shape of the walls changes
+    fn cached(
+        &self,
+        kind: MeshKind,
+        resolution: f32,
+        center: Vec2,
+        iso: f32,
+        build: impl FnOnce() -> Vec<Vec2>,
+    ) -> Vec<Vec2> {
+        let mut cache = self.grid_cache(resolution, center);
+        if let Some((_, _, mesh)) = cache
+            .meshes
+            .iter()
+            .find(|&&(k, level, _)| k == kind && level == iso)
+        {
+            return mesh.clone();
+        }
+
+        let mesh = build();
+        cache.meshes.push((kind, iso, mesh.clone()));
+        mesh
+    }
+
+    /// The cache for the grid around `center`, emptied first if it was built for another grid or
+    /// the walls have changed shape since
+    fn grid_cache(&self, resolution: f32, center: Vec2) -> RefMut<'_, MarchingCache> {
+        let shape = self.shape();
+        RefMut::map(self.marching_cache.borrow_mut(), |cache| {
+            let cache = cache.get_or_insert_with(|| MarchingCache::new(shape, resolution, center));
+            if cache.shape != shape || cache.resolution != resolution || cache.center != center {
+                *cache = MarchingCache::new(shape, resolution, center);
+            }
+            cache
+        })
+    }
+
+    /// Everything the distance field depends on
+    fn shape(&self) -> ShapeKey {
+        ShapeKey {
+            width: self.width,
+            height: self.height,
+            shaft_radius: self.shaft_radius,
+            deep_shaft_radius: self.deep_shaft_radius,
+            noise_scale: self.noise_scale,
+            noise_amplitude: self.noise_amplitude,
+            deep_noise_amplitude: self.deep_noise_amplitude,
+            narrowing_depth: self.narrowing_depth,
+            noise_octaves: self.noise_octaves,
+            noise_lacunarity: self.noise_lacunarity,
+            noise_persistence: self.noise_persistence,
+            tunnel_spacing: self.tunnel_spacing,
+            tunnel_length: self.tunnel_length,
+            tunnel_radius: self.tunnel_radius,
+            seed: self.seed(),
+        }
+    }
+
+    /// Visits every cell of a `resolution` sized grid covering the shaft's area around `center`,
+    /// treating points with a distance below `iso` as solid
+    fn for_each_cell(&self, resolution: f32, center: Vec2, iso: f32, visit: impl FnMut(&Cell)) {
+        let size = vec2(self.width, self.height);
+        self.for_each_cell_in(resolution, center - size * 0.5, size, iso, visit);
+    }
+
+    /// Visits every cell of a `resolution` sized grid covering `size` from `offset`
+    fn for_each_cell_in(
+        &self,
+        resolution: f32,
+        offset: Vec2,
+        size: Vec2,
+        iso: f32,
+        mut visit: impl FnMut(&Cell),
+    ) {
+        if !(resolution > 0.0 && resolution.is_finite()) {
+            log::warn!("invalid marching squares resolution {}", resolution);
+            return;
+        }
+
+        // work out the grid size in floating point, so huge or negative grids can't overflow
+        let cols = (size.x / resolution).floor();
+        let rows = (size.y / resolution).floor();
+        if !(cols >= 0.0 && rows >= 0.0 && cols * rows <= MAX_MARCHING_CELLS) {
+            log::warn!(
+                "marching squares grid of {}x{} cells is too large, skipping",
+                cols,
+                rows
+            );
+            return;
+        }
+        let cols = cols as i32;
+        let rows = rows as i32;
 
         for y in 0..rows {
             for x in 0..cols {
                 let p0 = Vec2::new(x as f32 * resolution, y as f32 * resolution) + offset;
-                let p1 = p0 + Vec2::new(resolution, 0.0);
-                let p2 = p0 + Vec2::new(resolution, resolution);
-                let p3 = p0 + Vec2::new(0.0, resolution);
-
-                let d0 = self.distance(p0);
-                let d1 = self.distance(p1);
-                let d2 = self.distance(p2);
-                let d3 = self.distance(p3);
-
-                let cell = [d0 < 0.0, d1 < 0.0, d2 < 0.0, d3 < 0.0];
-                let index = (cell[0] as u8) << 0
-                    | (cell[1] as u8) << 1
-                    | (cell[2] as u8) << 2
-                    | (cell[3] as u8) << 3;
-
-                // Interpolate edge intersections
-                let interp = |a: Vec2, da: f32, b: Vec2, db: f32| {
-                    if (da - db).abs() < 0.0001 {
-                        (a + b) * 0.5
-                    } else {
-                        let t = (0.0 - da) / (db - da);
-                        a + (b - a) * t
-                    }
-                };
-
-                let e0 = interp(p0, d0, p1, d1);
-                let e1 = interp(p1, d1, p2, d2);
-                let e2 = interp(p2, d2, p3, d3);
-                let e3 = interp(p3, d3, p0, d0);
-
-                let center = (p0 + p2) * 0.5;
-                let dc = self.distance(center);
-                let center_sign = dc < 0.0;
-
-                let edges = resolve_case(index, center_sign);
-                for (a, b) in edges {
-                    let pa = match a {
-                        0 => e0,
-                        1 => e1,
-                        2 => e2,
-                        3 => e3,
-                        _ => continue,
-                    };
-                    let pb = match b {
-                        0 => e0,
-                        1 => e1,
-                        2 => e2,
-                        3 => e3,
-                        _ => continue,
-                    };
-                    segments.push(pa);
-                    segments.push(pb);
-                }
+                let corners = [
+                    p0,
+                    p0 + Vec2::new(resolution, 0.0),
+                    p0 + Vec2::new(resolution, resolution),
+                    p0 + Vec2::new(0.0, resolution),
+                ];
+
+                visit(&Cell {
+                    x,
+                    y,
+                    corners,
+                    distances: corners.map(|p| self.distance(p) - iso),
+                });
             }
         }
+    }
+}
 
-        segments
+/// The meshes `MineShaft` builds from the grid
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MeshKind {
+    /// Line segments from `marching_squares_at_level`
+    Outline,
+    /// Triangles from `marching_squares_filled`
+    Filled,
+}
+
+/// A snapshot of the `MineShaft` fields that shape its walls. They're all public, so they're
+/// compared on each lookup rather than invalidating the cache when set
+#[derive(Clone, Copy, PartialEq)]
+struct ShapeKey {
+    width: f32,
+    height: f32,
+    shaft_radius: f32,
+    deep_shaft_radius: f32,
+    noise_scale: f32,
+    noise_amplitude: f32,
+    deep_noise_amplitude: f32,
+    narrowing_depth: f32,
+    noise_octaves: u32,
+    noise_lacunarity: f32,
+    noise_persistence: f32,
+    tunnel_spacing: f32,
+    tunnel_length: f32,
+    tunnel_radius: f32,
+    seed: u32,
+}
+
+/// Meshes built for one grid, with the level each was traced at
+struct MarchingCache {
+    shape: ShapeKey,
+    resolution: f32,
+    center: Vec2,
+    meshes: Vec<(MeshKind, f32, Vec<Vec2>)>,
+    /// Polylines from `outline`
+    outline: Option<Vec<Vec<Vec2>>>,
+    /// A normal for each point of `outline`
+    outline_normals: Option<Vec<Vec<Vec2>>>,
+}
+
+impl MarchingCache {
+    fn new(shape: ShapeKey, resolution: f32, center: Vec2) -> Self {
+        Self {
+            shape,
+            resolution,
+            center,
+            meshes: vec![],
+            outline: None,
+            outline_normals: None,
+        }
+    }
+}
+
+/// One cell of the marching squares grid. Corners run anticlockwise from the bottom left, and
+/// edge `i` joins corner `i` to the next corner
+struct Cell {
+    x: i32,
+    y: i32,
+    corners: [Vec2; 4],
+    distances: [f32; 4],
+}
+
+impl Cell {
+    fn is_solid(&self, corner: usize) -> bool {
+        self.distances[corner] < 0.0
+    }
+
+    /// The marching squares case, with a bit set for each solid corner
+    fn index(&self) -> u8 {
+        (0..4).fold(0, |index, i| index | (self.is_solid(i) as u8) << i)
+    }
+
+    fn center(&self) -> Vec2 {
+        (self.corners[0] + self.corners[2]) * 0.5
+    }
+
+    /// Identifies an edge of the grid, which is shared with the neighbouring cell
+    fn edge_key(&self, edge: usize) -> EdgeKey {
+        match edge {
+            0 => (self.x, self.y, true),
+            1 => (self.x + 1, self.y, false),
+            2 => (self.x, self.y + 1, true),
+            _ => (self.x, self.y, false),
+        }
+    }
+
+    /// Where the surface crosses an edge, interpolated from the distances at its ends
+    fn edge(&self, edge: usize) -> Vec2 {
+        let (a, da) = (self.corners[edge], self.distances[edge]);
+        let (b, db) = (self.corners[(edge + 1) % 4], self.distances[(edge + 1) % 4]);
+
+        if (da - db).abs() < 0.0001 {
+            (a + b) * 0.5
+        } else {
+            let t = (0.0 - da) / (db - da);
+            a + (b - a) * t
+        }
     }
 }
 
-pub fn resolve_case(index: u8, center_sign: bool) -> &'static [(u8, u8)] {
+/// Distance from `p` to the nearest point on the segment from `a` to `b`
+fn distance_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = ((p - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0);
+    p.distance(a + ab * t)
+}
+
+/// A grid edge, as the cell coordinates of its start and whether it's horizontal
+type EdgeKey = (i32, i32, bool);
+
+const STARTING_ZONE_RADIUS: f32 = 100.0;
+
+/// Depth below which `distance` leaves out the starting zone. By then the zone is at least a
+/// radius inside the rock wherever it would still have won, so the walls come out the same
+const STARTING_ZONE_CUTOFF: f32 = 2.0 * STARTING_ZONE_RADIUS;
+
+/// Mixes the tunnel slot into the seed, so neighbouring slots get unrelated tunnels
+const TUNNEL_SLOT_HASH: u32 = 0x9e37_79b9;
+
+/// Steepest a side tunnel climbs or falls, as height gained per unit across
+const MAX_TUNNEL_SLOPE: f32 = 0.3;
+
+/// How much of the noise roughens the side tunnels, compared with the main shaft
+const TUNNEL_ROUGHNESS: f32 = 0.3;
+
+/// Tries `sample_open_points` makes for each point before giving up on it
+const MAX_SAMPLE_ATTEMPTS: usize = 32;
+
+/// Offset of the central differences in `normal`, in world units. Wide enough to smooth over the
+/// finest wiggles of the noise, which would otherwise knock the ship sideways
+const NORMAL_EPSILON: f32 = 0.5;
+
+/// Slope of the distance field below which `normal` gives up. The field has a slope of about 1
+/// near the walls
+const MIN_NORMAL_GRADIENT: f32 = 1e-3;
+
+/// Upper bound on the cells evaluated by a single marching squares pass
+const MAX_MARCHING_CELLS: f32 = 1_000_000.0;
+
+/// The marching squares lookup table. Given a cell's case `index` (bit `i` set when corner `i` is
+/// solid, corners running anticlockwise from the bottom left) returns the edges to join, where
+/// edge `i` runs from corner `i` to the next corner.
+///
+/// Every segment is oriented with solid rock on its right, so segments chain head to tail around
+/// each contour. The saddle cases 5 and 10 are ambiguous, and `center_solid` (whether the centre
+/// of the cell is solid) decides whether the two solid corners are joined through the middle.
+pub fn resolve_case(index: u8, center_solid: bool) -> &'static [(u8, u8)] {
     match index {
         0 | 15 => &[],
         1 => &[(3, 0)],
@@ -137,8 +725,8 @@ pub fn resolve_case(index: u8, center_sign: bool) -> &'static [(u8, u8)] {
         3 => &[(3, 1)],
         4 => &[(1, 2)],
         5 => {
-            if center_sign {
-                &[(0, 1), (2, 3)]
+            if center_solid {
+                &[(1, 0), (3, 2)]
             } else {
                 &[(3, 0), (1, 2)]
             }
@@ -146,18 +734,237 @@ pub fn resolve_case(index: u8, center_sign: bool) -> &'static [(u8, u8)] {
         6 => &[(0, 2)],
         7 => &[(3, 2)],
         8 => &[(2, 3)],
-        9 => &[(0, 2)],
+        9 => &[(2, 0)],
         10 => {
-            if center_sign {
-                &[(1, 2), (3, 0)]
+            if center_solid {
+                &[(0, 3), (2, 1)]
             } else {
                 &[(0, 1), (2, 3)]
             }
         }
-        11 => &[(1, 2)],
+        11 => &[(2, 1)],
         12 => &[(1, 3)],
-        13 => &[(0, 1)],
-        14 => &[(3, 0)],
+        13 => &[(1, 0)],
+        14 => &[(0, 3)],
         _ => &[],
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A unit cell with the corners set in `index` solid, so every crossing is mid-edge
+    fn unit_cell(index: u8) -> Cell {
+        Cell {
+            x: 0,
+            y: 0,
+            corners: [
+                vec2(0.0, 0.0),
+                vec2(1.0, 0.0),
+                vec2(1.0, 1.0),
+                vec2(0.0, 1.0),
+            ],
+            distances: [0, 1, 2, 3].map(|i| if index & 1 << i != 0 { -1.0 } else { 1.0 }),
+        }
+    }
+
+    /// Checks the segments for a case join up every edge the surface crosses, once each, and
+    /// have rock on their right: the corners nearest each segment are solid on its right and
+    /// open on its left
+    fn check_case(index: u8, center_solid: bool) {
+        let cell = unit_cell(index);
+        let segments = resolve_case(index, center_solid);
+
+        let crossing: Vec<u8> = (0..4)
+            .filter(|&e| cell.is_solid(e as usize) != cell.is_solid((e as usize + 1) % 4))
+            .collect();
+        let mut used: Vec<u8> = segments.iter().flat_map(|&(a, b)| [a, b]).collect();
+        used.sort();
+        assert_eq!(used, crossing, "case {index} joins the wrong edges");
+
+        for &(a, b) in segments {
+            let (start, end) = (cell.edge(a as usize), cell.edge(b as usize));
+            let mid = (start + end) * 0.5;
+
+            for right in [true, false] {
+                let side: Vec<usize> = (0..4)
+                    .filter(|&i| ((end - start).perp_dot(cell.corners[i] - start) < 0.0) == right)
+                    .collect();
+                let nearest = side
+                    .iter()
+                    .map(|&i| cell.corners[i].distance(mid))
+                    .fold(f32::INFINITY, f32::min);
+
+                for &i in &side {
+                    if cell.corners[i].distance(mid) - nearest < 1e-4 {
+                        assert_eq!(
+                            cell.is_solid(i),
+                            right,
+                            "case {index} segment {a}->{b} has corner {i} on the wrong side"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn case_0_is_empty() {
+        assert!(resolve_case(0, false).is_empty());
+        check_case(0, false);
+    }
+
+    #[test]
+    fn case_1() {
+        check_case(1, false);
+    }
+
+    #[test]
+    fn case_2() {
+        check_case(2, false);
+    }
+
+    #[test]
+    fn case_3() {
+        check_case(3, false);
+    }
+
+    #[test]
+    fn case_4() {
+        check_case(4, false);
+    }
+
+    #[test]
+    fn case_5_saddle() {
+        check_case(5, false);
+        check_case(5, true);
+        assert_ne!(resolve_case(5, false), resolve_case(5, true));
+    }
+
+    #[test]
+    fn case_6() {
+        check_case(6, false);
+    }
+
+    #[test]
+    fn case_7() {
+        check_case(7, true);
+    }
+
+    #[test]
+    fn case_8() {
+        check_case(8, false);
+    }
+
+    #[test]
+    fn case_9() {
+        check_case(9, false);
+    }
+
+    #[test]
+    fn case_10_saddle() {
+        check_case(10, false);
+        check_case(10, true);
+        assert_ne!(resolve_case(10, false), resolve_case(10, true));
+    }
+
+    #[test]
+    fn case_11() {
+        check_case(11, true);
+    }
+
+    #[test]
+    fn case_12() {
+        check_case(12, false);
+    }
+
+    #[test]
+    fn case_13() {
+        check_case(13, true);
+    }
+
+    #[test]
+    fn case_14() {
+        check_case(14, true);
+    }
+
+    #[test]
+    fn case_15_is_empty() {
+        assert!(resolve_case(15, true).is_empty());
+        check_case(15, true);
+    }
+
+    #[test]
+    fn new_digs_the_seed_zero_cave() {
+        let shaft = MineShaft::new(200.0, 200.0);
+        let seeded = MineShaft::with_seed(200.0, 200.0, 0);
+        assert_eq!(shaft.seed(), 0);
+        for i in 0..50 {
+            let p = vec2(i as f32 * 7.0 - 175.0, -(i as f32) * 40.0);
+            assert_eq!(shaft.distance(p), seeded.distance(p));
+        }
+    }
+
+    #[test]
+    fn materials_vary_down_the_shaft() {
+        let shaft = MineShaft::with_seed(200.0, 200.0, 7);
+        let mut seen = Vec::new();
+        for i in 0..200 {
+            let y = -(i as f32) * 50.0;
+            let material = shaft.material_at(vec2(shaft.shaft_center(y), y));
+            if !seen.contains(&material) {
+                seen.push(material);
+            }
+        }
+        assert!(seen.len() > 1, "{seen:?}");
+
+        let plain = MineShaft {
+            varied_materials: false,
+            ..MineShaft::with_seed(200.0, 200.0, 7)
+        };
+        assert!(
+            (0..200).all(|i| plain.material_at(vec2(0.0, -(i as f32) * 50.0)) == Material::ROCK)
+        );
+    }
+
+    #[test]
+    fn outline_normals_face_the_open_cave() {
+        let shaft = MineShaft::with_seed(200.0, 200.0, 7);
+        let outline = shaft.outline_with_normals(5.0, vec2(0.0, -100.0));
+        assert!(!outline.is_empty());
+
+        for &(p, normal) in outline.iter().flatten() {
+            assert!((normal.length() - 1.0).abs() < 1e-3);
+            // a step out along the normal heads away from the rock
+            assert!(shaft.distance(p + normal) > shaft.distance(p));
+        }
+    }
+
+    #[test]
+    fn merged_outline_needs_fewer_vertices() {
+        use crate::polyline::{CapStyle, LineStyle, merge_collinear, polyline_to_triangles};
+
+        let shaft = MineShaft::with_seed(200.0, 200.0, 7);
+        let outline = shaft.outline(5.0, vec2(0.0, -100.0));
+        assert!(!outline.is_empty());
+
+        // every wall segment ends up in exactly one polyline
+        let segments = shaft
+            .marching_squares_at_level(5.0, vec2(0.0, -100.0), 0.0)
+            .len()
+            / 2;
+        let chained: usize = outline.iter().map(|contour| contour.len() - 1).sum();
+        assert_eq!(chained, segments);
+
+        let stroke = |points: &[Vec2]| {
+            polyline_to_triangles(points, 1.0, 0.05, false, LineStyle::Solid, CapStyle::Round).len()
+        };
+        let unmerged: usize = outline.iter().map(|contour| stroke(contour)).sum();
+        let merged: usize = outline
+            .iter()
+            .map(|contour| stroke(&merge_collinear(contour, 0.01)))
+            .sum();
+        assert!(merged < unmerged, "{merged} vs {unmerged}");
+    }
+}
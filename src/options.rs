@@ -0,0 +1,287 @@
+use glam::{Vec2, Vec4, vec2};
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::{
+    app::{AppStateBuilder, CaveRenderStyle, CaveTint},
+    background::BackgroundConfig,
+    key_bindings::KeyBindings,
+    physics::{GravityZone, PhysicsConfig},
+    post_processor::{Border, Upscale},
+    settings::Difficulty,
+    touch_controls::TouchLayout,
+};
+
+/// Reads the options object a page passes to `fathom_start` into a builder. Every option is
+/// optional, and ones that are missing keep their defaults. Malformed options are logged and
+/// skipped, rather than failing to start. The options are:
+///
+/// - `font`: a `Uint8Array` of TrueType data to draw all text with
+/// - `startingHealth`: health at the start of each run, regardless of difficulty
+/// - `difficulty`: `"easy"`, `"normal"` or `"hard"`, instead of the player's saved setting
+/// - `physics`: an object with any of `gravity`, `thrust`, `thrustRamp`, `thrustBoost`,
+///   `thrustBoostDecay`, `turnRate`, `turnAcceleration`, `angularDamping` and `maxSpeed`
+/// - `shaftSize`: `[width, height]` of the area meshed around the camera
+/// - `caveNoise`: `[octaves, lacunarity, persistence]` of the noise the walls are carved with
+/// - `background`: an object with any of `enabled`, `spacing`, `crossSize`, `contourLevels` and
+///   `contourSpacing`
+/// - `caveStyle`: `"outline"`, `"filled"` or `"filled-outlined"`
+/// - `caveTint`: `"flat"`, `"depth"` or `"lit"`
+/// - `border`: an object with any of `color` as `[r, g, b, a]`, `width` and `curved`
+/// - `textShadows`: whether text has soft shadows beneath it
+/// - `motionBlur`: strength of the blur along the camera's movement
+/// - `cameraSmoothing`: roughly how many seconds the camera takes to catch up with the ship
+/// - `keyBindings`: an object with `thrust`, `left`, `right` and `pause` lists of key codes
+/// - `touchLayout`: `[sideWidth, thrustHeight]` of the touch controls
+/// - `cameraBounds`: `[minX, minY, maxX, maxY]` to keep the view within
+/// - `goalDepth`: the depth that wins a run
+/// - `pauseWhenHidden` and `pauseOnBlur`: when the game suspends itself
+/// - `gravityZones`: a list of objects with `min`, `max` and `direction`, each as `[x, y]`
+/// - `menuBackdrop`: `[r, g, b, a]` of the backdrop behind menus
+/// - `internalResolution`: `[width, height]` to render at, scaled to fit the canvas
+/// - `upscale`: `"nearest"` or `"linear"` scaling of the internal resolution
+/// - `supersample`: how many times the resolution to render the scene at, in each axis
+/// - `bloomScale`: fraction of the resolution to blur the bloom at
+/// - `grain`: strength of the film grain, from 0 for none to 1
+pub fn builder_from_options(options: &JsValue) -> AppStateBuilder {
+    let mut builder = AppStateBuilder::default();
+    if options.is_undefined() || options.is_null() {
+        return builder;
+    }
+
+    if let Some(font) = get(options, "font") {
+        match font.dyn_into::<js_sys::Uint8Array>() {
+            Ok(data) => builder = builder.font(data.to_vec()),
+            Err(_) => malformed("font"),
+        }
+    }
+    if let Some(health) = number(options, "startingHealth") {
+        builder = builder.starting_health(health.max(1.0) as usize);
+    }
+    if let Some(difficulty) = named(options, "difficulty", Difficulty::from_name) {
+        builder = builder.difficulty(difficulty);
+    }
+    if let Some(physics) = get(options, "physics") {
+        builder = builder.physics(read_physics(&physics));
+    }
+    if let Some([width, height]) = numbers(options, "shaftSize") {
+        builder = builder.shaft_size(width, height);
+    }
+    if let Some([octaves, lacunarity, persistence]) = numbers(options, "caveNoise") {
+        builder = builder.cave_noise(octaves.max(1.0) as u32, lacunarity, persistence);
+    }
+    if let Some(background) = get(options, "background") {
+        builder = builder.background(read_background(&background));
+    }
+    if let Some(style) = named(options, "caveStyle", CaveRenderStyle::from_name) {
+        builder = builder.cave_style(style);
+    }
+    if let Some(tint) = named(options, "caveTint", CaveTint::from_name) {
+        builder = builder.cave_tint(tint);
+    }
+    if let Some(border) = get(options, "border") {
+        builder = builder.border(Some(read_border(&border)));
+    }
+    if let Some(enabled) = flag(options, "textShadows") {
+        builder = builder.text_shadows(enabled);
+    }
+    if let Some(strength) = number(options, "motionBlur") {
+        builder = builder.motion_blur(strength);
+    }
+    if let Some(seconds) = number(options, "cameraSmoothing") {
+        builder = builder.camera_smoothing(seconds);
+    }
+    if let Some(bindings) = get(options, "keyBindings") {
+        match read_key_bindings(&bindings) {
+            Some(bindings) => builder = builder.key_bindings(bindings),
+            None => malformed("keyBindings"),
+        }
+    }
+    if let Some([side_width, thrust_height]) = numbers(options, "touchLayout") {
+        builder = builder.touch_layout(TouchLayout {
+            side_width,
+            thrust_height,
+        });
+    }
+    if let Some([min_x, min_y, max_x, max_y]) = numbers(options, "cameraBounds") {
+        builder = builder.camera_bounds(Some((vec2(min_x, min_y), vec2(max_x, max_y))));
+    }
+    if let Some(depth) = number(options, "goalDepth") {
+        builder = builder.goal_depth((depth > 0.0).then_some(depth as usize));
+    }
+    if let Some(enabled) = flag(options, "pauseWhenHidden") {
+        builder = builder.pause_when_hidden(enabled);
+    }
+    if let Some(enabled) = flag(options, "pauseOnBlur") {
+        builder = builder.pause_on_blur(enabled);
+    }
+    if let Some(zones) = get(options, "gravityZones") {
+        match read_gravity_zones(&zones) {
+            Some(zones) => builder = builder.gravity_zones(zones),
+            None => malformed("gravityZones"),
+        }
+    }
+    if let Some(color) = numbers(options, "menuBackdrop") {
+        builder = builder.menu_backdrop(Vec4::from_array(color));
+    }
+    if let Some([width, height]) = numbers(options, "internalResolution") {
+        builder = builder.internal_resolution(Some((width as i32, height as i32)));
+    }
+    if let Some(upscale) = named(options, "upscale", Upscale::from_name) {
+        builder = builder.upscale(upscale);
+    }
+    if let Some(factor) = number(options, "supersample") {
+        builder = builder.supersample(factor as i32);
+    }
+    if let Some(scale) = number(options, "bloomScale") {
+        builder = builder.bloom_scale(scale);
+    }
+    if let Some(intensity) = number(options, "grain") {
+        builder = builder.grain(intensity);
+    }
+
+    builder
+}
+
+fn read_physics(options: &JsValue) -> PhysicsConfig {
+    let mut physics = PhysicsConfig::default();
+    for (key, field) in [
+        ("gravity", &mut physics.gravity),
+        ("thrust", &mut physics.thrust),
+        ("thrustRamp", &mut physics.thrust_ramp),
+        ("thrustBoost", &mut physics.thrust_boost),
+        ("thrustBoostDecay", &mut physics.thrust_boost_decay),
+        ("turnRate", &mut physics.turn_rate),
+        ("turnAcceleration", &mut physics.turn_acceleration),
+        ("angularDamping", &mut physics.angular_damping),
+        ("maxSpeed", &mut physics.max_speed),
+    ] {
+        if let Some(value) = number(options, key) {
+            *field = value;
+        }
+    }
+    physics
+}
+
+fn read_background(options: &JsValue) -> BackgroundConfig {
+    let mut background = BackgroundConfig::default();
+    if let Some(enabled) = flag(options, "enabled") {
+        background.enabled = enabled;
+    }
+    if let Some(spacing) = number(options, "spacing") {
+        background.spacing = spacing;
+    }
+    if let Some(size) = number(options, "crossSize") {
+        background.cross_size = size;
+    }
+    if let Some(levels) = number(options, "contourLevels") {
+        background.contour_levels = levels.max(0.0) as usize;
+    }
+    if let Some(spacing) = number(options, "contourSpacing") {
+        background.contour_spacing = spacing;
+    }
+    background
+}
+
+fn read_border(options: &JsValue) -> Border {
+    let mut border = Border::default();
+    if let Some(color) = numbers(options, "color") {
+        border.color = Vec4::from_array(color);
+    }
+    if let Some(width) = number(options, "width") {
+        border.width = width;
+    }
+    if let Some(curved) = flag(options, "curved") {
+        border.curved = curved;
+    }
+    border
+}
+
+fn read_key_bindings(options: &JsValue) -> Option<KeyBindings> {
+    let codes = |key| {
+        let list = get(options, key).filter(js_sys::Array::is_array)?;
+        js_sys::Array::from(&list)
+            .iter()
+            .map(|code| code.as_string())
+            .collect::<Option<Vec<_>>>()
+    };
+    Some(KeyBindings {
+        thrust: codes("thrust")?,
+        left: codes("left")?,
+        right: codes("right")?,
+        pause: codes("pause")?,
+    })
+}
+
+fn read_gravity_zones(options: &JsValue) -> Option<Vec<GravityZone>> {
+    if !js_sys::Array::is_array(options) {
+        return None;
+    }
+    js_sys::Array::from(options)
+        .iter()
+        .map(|zone| {
+            let point = |key| numbers(&zone, key).map(Vec2::from_array);
+            Some(GravityZone {
+                min: point("min")?,
+                max: point("max")?,
+                direction: point("direction")?,
+            })
+        })
+        .collect()
+}
+
+/// The value of `key`, unless it's missing
+fn get(options: &JsValue, key: &str) -> Option<JsValue> {
+    js_sys::Reflect::get(options, &JsValue::from_str(key))
+        .ok()
+        .filter(|value| !value.is_undefined() && !value.is_null())
+}
+
+fn number(options: &JsValue, key: &str) -> Option<f32> {
+    let value = get(options, key)?;
+    let number = value.as_f64().filter(|n| n.is_finite());
+    if number.is_none() {
+        malformed(key);
+    }
+    number.map(|n| n as f32)
+}
+
+fn flag(options: &JsValue, key: &str) -> Option<bool> {
+    let value = get(options, key)?;
+    let flag = value.as_bool();
+    if flag.is_none() {
+        malformed(key);
+    }
+    flag
+}
+
+/// An array of exactly `N` numbers
+fn numbers<const N: usize>(options: &JsValue, key: &str) -> Option<[f32; N]> {
+    let value = get(options, key)?;
+    let numbers = js_sys::Array::is_array(&value)
+        .then(|| {
+            js_sys::Array::from(&value)
+                .iter()
+                .map(|n| n.as_f64().filter(|n| n.is_finite()).map(|n| n as f32))
+                .collect::<Option<Vec<_>>>()
+        })
+        .flatten()
+        .and_then(|numbers| numbers.try_into().ok());
+    if numbers.is_none() {
+        malformed(key);
+    }
+    numbers
+}
+
+/// A string parsed by `parse`, such as `CaveTint::from_name`
+fn named<T>(options: &JsValue, key: &str, parse: fn(&str) -> Option<T>) -> Option<T> {
+    let value = get(options, key)?;
+    let parsed = value.as_string().and_then(|name| parse(&name));
+    if parsed.is_none() {
+        malformed(key);
+    }
+    parsed
+}
+
+fn malformed(key: &str) {
+    log::warn!("ignoring malformed {key:?} option");
+}
@@ -0,0 +1,82 @@
+use glam::Vec2;
+
+/// Tunables for how the ship moves
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PhysicsConfig {
+    /// Acceleration due to gravity, in world units per second squared. Gravity pulls downwards,
+    /// except within a `GravityZone`
+    pub gravity: f32,
+    /// Acceleration while thrusting, in world units per second squared
+    pub thrust: f32,
+    /// Time for thrust to build up to full strength after it's first pressed, in seconds
+    pub thrust_ramp: f32,
+    /// How far thrust overshoots at the end of the ramp, as a fraction of full strength
+    pub thrust_boost: f32,
+    /// Time for the overshoot to settle back to full strength, in seconds
+    pub thrust_boost_decay: f32,
+    /// Top turning speed, in radians per second
+    pub turn_rate: f32,
+    /// How quickly turning picks up speed, in radians per second squared. 0 turns at `turn_rate`
+    /// straight away, with no momentum
+    pub turn_acceleration: f32,
+    /// How quickly the ship stops spinning without input, as the fraction of its turning speed
+    /// lost per second, roughly
+    pub angular_damping: f32,
+    /// Top speed, in world units per second
+    pub max_speed: f32,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            gravity: 10.0,
+            thrust: 30.0,
+            thrust_ramp: 0.0,
+            thrust_boost: 0.0,
+            thrust_boost_decay: 0.0,
+            turn_rate: 1.0,
+            turn_acceleration: 6.0,
+            angular_damping: 4.0,
+            max_speed: 40.0,
+        }
+    }
+}
+
+impl PhysicsConfig {
+    /// The thrust acceleration after thrust has been held for `held` seconds. Eases up to
+    /// `1 + thrust_boost` times full strength over the ramp, then back down to full strength
+    pub fn thrust_at(&self, held: f32) -> f32 {
+        let peak = 1.0 + self.thrust_boost;
+
+        let scale = if held < self.thrust_ramp {
+            peak * smoothstep(held / self.thrust_ramp)
+        } else if held < self.thrust_ramp + self.thrust_boost_decay {
+            let t = (held - self.thrust_ramp) / self.thrust_boost_decay;
+            peak - self.thrust_boost * smoothstep(t)
+        } else {
+            1.0
+        };
+
+        self.thrust * scale
+    }
+}
+
+/// A rectangle of the world in which gravity pulls in its own direction
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GravityZone {
+    pub min: Vec2,
+    pub max: Vec2,
+    /// Which way gravity pulls in the zone. Only the direction matters
+    pub direction: Vec2,
+}
+
+impl GravityZone {
+    pub fn contains(&self, p: Vec2) -> bool {
+        p.cmpge(self.min).all() && p.cmple(self.max).all()
+    }
+}
+
+fn smoothstep(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -2,7 +2,10 @@ use glam::{Mat2, Vec2, vec2};
 use itertools::Itertools;
 use std::f32::consts::PI;
 
-pub fn lines_to_triangles(points: &[Vec2], width: f32) -> Vec<Vec2> {
+/// Turns disconnected line segments (pairs of points) into quads, pairing each vertex with the
+/// index of the input point it was generated from, so callers can attach per-point attributes
+/// such as colour.
+pub fn lines_to_triangles_with_sources(points: &[Vec2], width: f32) -> Vec<(Vec2, usize)> {
     let mut verts = Vec::new();
     let half_width = width / 2.0;
 
@@ -10,61 +13,176 @@ pub fn lines_to_triangles(points: &[Vec2], width: f32) -> Vec<Vec2> {
         return verts;
     }
 
-    for (p0, p1) in points.iter().tuples() {
+    for (i, (p0, p1)) in points.iter().tuples().enumerate() {
         let dir = (p1 - p0).normalize_or_zero();
         let normal = perpendicular(dir);
 
         // Create two triangles forming a quad
-        let a = p0 + normal * half_width;
-        let b = p0 - normal * half_width;
-        let c = p1 + normal * half_width;
-        let d = p1 - normal * half_width;
+        let a = (p0 + normal * half_width, i * 2);
+        let b = (p0 - normal * half_width, i * 2);
+        let c = (p1 + normal * half_width, i * 2 + 1);
+        let d = (p1 - normal * half_width, i * 2 + 1);
 
         // Triangle 1: a, b, c
-        push_triangle(&mut verts, a, b, c);
+        verts.extend([a, b, c]);
         // Triangle 2: c, b, d
-        push_triangle(&mut verts, c, b, d);
+        verts.extend([c, b, d]);
     }
 
     verts
 }
 
-/// Converts a polyline into triangles with round joins and round end caps.
+/// Drops interior points where the line carries straight on, turning by less than `tolerance`
+/// radians, so runs of short collinear segments come out as one quad with no joins between them.
+/// Repeated points are dropped too. The ends are always kept
+pub fn merge_collinear(points: &[Vec2], tolerance: f32) -> Vec<Vec2> {
+    merge_collinear_indices(points, tolerance)
+        .into_iter()
+        .map(|i| points[i])
+        .collect()
+}
+
+/// Like `merge_collinear`, but returns the indices of the points kept, so anything carried
+/// alongside the points, like colours, can be picked out to match
+pub fn merge_collinear_indices(points: &[Vec2], tolerance: f32) -> Vec<usize> {
+    if points.len() < 2 {
+        return (0..points.len()).collect();
+    }
+    let last = points.len() - 1;
+
+    let mut merged = vec![0];
+    for i in 1..last {
+        // measure from the last point kept, so slight turns can't add up unnoticed
+        let dir_in = (points[i] - points[merged[merged.len() - 1]]).normalize_or_zero();
+        let dir_out = (points[i + 1] - points[i]).normalize_or_zero();
+        if dir_in == Vec2::ZERO {
+            continue;
+        }
+        if dir_out != Vec2::ZERO && signed_angle(dir_in, dir_out).abs() < tolerance {
+            continue;
+        }
+        merged.push(i);
+    }
+    merged.push(last);
+
+    merged
+}
+
+/// How a polyline is stroked along its length
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum LineStyle {
+    #[default]
+    Solid,
+    /// Alternating dashes `on` long and gaps `off` long, measured along the line. Dashes have
+    /// square ends, but bend round the corners they cross
+    Dashed { on: f32, off: f32 },
+    /// Round dots the width of the line, `spacing` apart along it
+    Dotted { spacing: f32 },
+}
+
+/// How the ends of an open polyline are finished
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CapStyle {
+    /// A half circle beyond each end
+    #[default]
+    Round,
+    /// Cut off square at the end points
+    Butt,
+    /// Cut off square half the width beyond the end points, as if the line were that much longer
+    Square,
+}
+
+/// Converts a polyline into triangles with round joins, and `cap` finishing the ends of open
+/// lines. The dashes of dashed lines always end square, and dots are always round.
 /// Triangles are returned as a flat Vec<Vec2> in CW winding order.
+/// Round joins and caps are divided into as few triangles as keep them within `tolerance` of a
+/// true arc, so wide strokes get smooth curves and thin ones stay cheap.
+/// Dashes and dots follow on from one segment to the next, so the pattern is unbroken by corners.
 pub fn polyline_to_triangles(
     points: &[Vec2],
     width: f32,
-    segments_per_arc: usize,
+    tolerance: f32,
     closed: bool,
+    style: LineStyle,
+    cap: CapStyle,
 ) -> Vec<Vec2> {
+    let verts = match style {
+        LineStyle::Dashed { on, off } if on > 0.0 && off > 0.0 => {
+            dashes_to_triangles(points, width / 2.0, tolerance, closed, on, off)
+        }
+        LineStyle::Dotted { spacing } if spacing > 0.0 => {
+            dots_to_triangles(points, width / 2.0, tolerance, closed, spacing)
+        }
+        // a pattern with no gaps, or nothing but gaps, is as good as solid
+        _ => polyline_to_triangles_with_sources(points, width, tolerance, closed, cap),
+    };
+    verts.into_iter().map(|(v, _)| v).collect()
+}
+
+/// Like `polyline_to_triangles`, but pairs each vertex with the index of the input point it was
+/// generated from. The two ends of each segment's quad take the indices of the points at either
+/// end, so per-point attributes interpolate along it, while joins and caps take the index of the
+/// point they are drawn around.
+pub fn polyline_to_triangles_with_sources(
+    points: &[Vec2],
+    width: f32,
+    tolerance: f32,
+    closed: bool,
+    cap: CapStyle,
+) -> Vec<(Vec2, usize)> {
+    let half_widths = vec![width / 2.0; points.len()];
+    stroke_to_triangles(points, &half_widths, tolerance, closed, cap)
+}
+
+/// Like `polyline_to_triangles`, but with a half-width per point, blending linearly along each
+/// segment, for strokes that taper. Joins and caps take the half-width of the point they are
+/// drawn around.
+pub fn polyline_to_triangles_variable(
+    points: &[Vec2],
+    half_widths: &[f32],
+    tolerance: f32,
+    closed: bool,
+) -> Vec<Vec2> {
+    debug_assert_eq!(points.len(), half_widths.len());
+
+    stroke_to_triangles(points, half_widths, tolerance, closed, CapStyle::Round)
+        .into_iter()
+        .map(|(v, _)| v)
+        .collect()
+}
+
+/// Strokes a solid polyline with a half-width per point, pairing each vertex with the index of
+/// the point it was generated from
+fn stroke_to_triangles(
+    points: &[Vec2],
+    half_widths: &[f32],
+    tolerance: f32,
+    closed: bool,
+    cap: CapStyle,
+) -> Vec<(Vec2, usize)> {
     let mut verts = Vec::new();
-    let half_width = width / 2.0;
 
-    if points.len() < 2 {
+    // segments too short to have a direction would only make slivers and bad normals
+    let kept = distinct_points(points, closed);
+    if kept.len() < 2 {
         return verts;
     }
+    let n = kept.len();
+    let point = |k: usize| (points[kept[k]], kept[k]);
 
-    let line_count = if closed {
-        points.len()
-    } else {
-        points.len() - 1
-    };
+    let line_count = if closed { n } else { n - 1 };
 
-    for i in 0..line_count {
-        let p0 = points[i];
-        let p1 = if i < points.len() - 1 {
-            points[i + 1]
-        } else {
-            points[0]
-        };
+    for k in 0..line_count {
+        let (p0, i) = point(k);
+        let (p1, j) = point((k + 1) % n);
         let dir = (p1 - p0).normalize_or_zero();
         let normal = perpendicular(dir);
 
-        // Create two triangles forming a quad
-        let a = p0 + normal * half_width;
-        let b = p0 - normal * half_width;
-        let c = p1 + normal * half_width;
-        let d = p1 - normal * half_width;
+        // Create two triangles forming a quad, as wide at each end as its point
+        let a = (p0 + normal * half_widths[i], i);
+        let b = (p0 - normal * half_widths[i], i);
+        let c = (p1 + normal * half_widths[j], j);
+        let d = (p1 - normal * half_widths[j], j);
 
         // Triangle 1: a, b, c
         push_triangle(&mut verts, a, b, c);
@@ -72,52 +190,279 @@ pub fn polyline_to_triangles(
         push_triangle(&mut verts, c, b, d);
 
         // Handle join (skip if it's the last segment)
-        if i < points.len() - 2 || (closed && i < points.len() - 1) {
-            let p2 = if i < points.len() - 2 {
-                points[i + 2]
-            } else {
-                points[0]
-            };
+        if k < n - 2 || (closed && k < n - 1) {
+            let (p2, _) = point((k + 2) % n);
             let dir_next = (p2 - p1).normalize_or_zero();
-            generate_round_join(&mut verts, p1, dir_next, dir, half_width, segments_per_arc);
+            generate_round_join(
+                &mut verts,
+                (p1, j),
+                dir_next,
+                dir,
+                half_widths[j],
+                tolerance,
+            );
         }
     }
 
-    let last = points.len() - 1;
-    let dir_start = (points[1] - points[0]).normalize_or_zero();
-    let dir_end = (points[last] - points[last - 1]).normalize_or_zero();
+    let first = point(0);
+    let last = point(n - 1);
+    let dir_start = (point(1).0 - first.0).normalize_or_zero();
+    let dir_end = (last.0 - point(n - 2).0).normalize_or_zero();
     if closed {
-        let dir = (points[0] - points[last]).normalize_or_zero();
+        let dir = (first.0 - last.0).normalize_or_zero();
         generate_round_join(
             &mut verts,
-            points[0],
+            first,
             dir_start,
             dir,
-            half_width,
-            segments_per_arc,
+            half_widths[first.1],
+            tolerance,
         );
     } else {
-        generate_round_cap(
-            &mut verts,
-            points[0],
-            dir_start,
-            half_width,
-            segments_per_arc,
-            true,
-        );
-        generate_round_cap(
+        for (end, dir, at_start) in [(first, dir_start, true), (last, dir_end, false)] {
+            let half_width = half_widths[end.1];
+            match cap {
+                CapStyle::Round => {
+                    generate_round_cap(&mut verts, end, dir, half_width, tolerance, at_start)
+                }
+                CapStyle::Butt => {}
+                CapStyle::Square => generate_square_cap(&mut verts, end, dir, half_width, at_start),
+            }
+        }
+    }
+
+    verts
+}
+
+/// Segments shorter than this are dropped before stroking, since their direction is unreliable
+const MIN_SEGMENT_LENGTH: f32 = 1e-4;
+
+/// Indices of the points of a polyline left after collapsing runs of points closer together than
+/// `MIN_SEGMENT_LENGTH` into the first of them. A closed outline's last point is dropped too if it
+/// comes back round onto the first
+fn distinct_points(points: &[Vec2], closed: bool) -> Vec<usize> {
+    let mut kept: Vec<usize> = Vec::with_capacity(points.len());
+    for (i, &p) in points.iter().enumerate() {
+        if kept
+            .last()
+            .is_none_or(|&k| points[k].distance(p) >= MIN_SEGMENT_LENGTH)
+        {
+            kept.push(i);
+        }
+    }
+
+    if closed
+        && kept.len() > 1
+        && points[kept[0]].distance(points[kept[kept.len() - 1]]) < MIN_SEGMENT_LENGTH
+    {
+        kept.pop();
+    }
+
+    kept
+}
+
+/// Splits a simple polygon, given its outline in either winding order, into triangles wound the
+/// same way. Convex polygons are fanned out from their first point, and concave ones have ears
+/// clipped off them. Outlines that cross themselves fill unpredictably, but never panic
+pub fn polygon_to_triangles(points: &[Vec2]) -> Vec<Vec2> {
+    let n = points.len();
+    let mut verts = Vec::with_capacity(n.saturating_sub(2) * 3);
+    if n < 3 {
+        return verts;
+    }
+
+    // twice the signed area, positive when the outline winds counter-clockwise
+    let area = (0..n)
+        .map(|i| points[i].perp_dot(points[(i + 1) % n]))
+        .sum::<f32>();
+    let convex_corner = |a: Vec2, b: Vec2, c: Vec2| (b - a).perp_dot(c - b) * area >= 0.0;
+
+    let mut remaining = (0..n).collect::<Vec<_>>();
+    let corners = |remaining: &[usize], k: usize| {
+        let m = remaining.len();
+        (
+            points[remaining[(k + m - 1) % m]],
+            points[remaining[k]],
+            points[remaining[(k + 1) % m]],
+        )
+    };
+
+    let convex = (0..n).all(|k| {
+        let (a, b, c) = corners(&remaining, k);
+        convex_corner(a, b, c)
+    });
+    if !convex {
+        while remaining.len() > 3 {
+            // an ear is a convex corner whose triangle has no other point of the outline in it
+            let ear = (0..remaining.len()).find(|&k| {
+                let (a, b, c) = corners(&remaining, k);
+                convex_corner(a, b, c)
+                    && !remaining.iter().any(|&r| {
+                        let p = points[r];
+                        p != a && p != b && p != c && point_in_triangle(p, a, b, c)
+                    })
+            });
+
+            // only self-intersecting outlines run out of ears, so fan out whatever is left
+            let Some(k) = ear else {
+                break;
+            };
+            let (a, b, c) = corners(&remaining, k);
+            push_triangle(&mut verts, a, b, c);
+            remaining.remove(k);
+        }
+    }
+
+    for k in 1..remaining.len() - 1 {
+        push_triangle(
             &mut verts,
-            points[last],
-            dir_end,
-            half_width,
-            segments_per_arc,
-            false,
+            points[remaining[0]],
+            points[remaining[k]],
+            points[remaining[k + 1]],
         );
     }
 
     verts
 }
 
+/// Whether `p` is inside or on the edge of the triangle `abc`, in either winding
+fn point_in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    let ab = (b - a).perp_dot(p - a);
+    let bc = (c - b).perp_dot(p - b);
+    let ca = (a - c).perp_dot(p - c);
+    (ab >= 0.0 && bc >= 0.0 && ca >= 0.0) || (ab <= 0.0 && bc <= 0.0 && ca <= 0.0)
+}
+
+/// The segments of a polyline, as the indices of the points at either end
+fn segment_indices(points: &[Vec2], closed: bool) -> impl Iterator<Item = (usize, usize)> {
+    let n = points.len();
+    let line_count = match n {
+        0 | 1 => 0,
+        _ if closed => n,
+        _ => n - 1,
+    };
+    (0..line_count).map(move |i| (i, (i + 1) % n))
+}
+
+/// Strokes only the dashes of a dashed polyline. Vertices are paired with the index of the point
+/// starting the segment they are on
+fn dashes_to_triangles(
+    points: &[Vec2],
+    half_width: f32,
+    tolerance: f32,
+    closed: bool,
+    on: f32,
+    off: f32,
+) -> Vec<(Vec2, usize)> {
+    let mut verts = Vec::new();
+    let period = on + off;
+    let segments = segment_indices(points, closed).collect::<Vec<_>>();
+
+    // how far into the dash pattern we are, carried over from each segment to the next
+    let mut phase = 0.0;
+    for (k, &(i, j)) in segments.iter().enumerate() {
+        let (p0, p1) = (points[i], points[j]);
+        let length = p0.distance(p1);
+        let dir = (p1 - p0).normalize_or_zero();
+        let normal = perpendicular(dir) * half_width;
+
+        let mut s = 0.0;
+        while s < length {
+            let (dash, remaining) = if phase < on {
+                (true, on - phase)
+            } else {
+                (false, period - phase)
+            };
+            let step = remaining.min(length - s);
+
+            if dash {
+                let start = p0 + dir * s;
+                let end = p0 + dir * (s + step);
+                let a = (start + normal, i);
+                let b = (start - normal, i);
+                let c = (end + normal, i);
+                let d = (end - normal, i);
+                push_triangle(&mut verts, a, b, c);
+                push_triangle(&mut verts, c, b, d);
+            }
+
+            s += step;
+            phase += step;
+            if phase >= period {
+                phase -= period;
+            }
+        }
+
+        // a dash carrying on round the corner needs a join, as a solid line would
+        let next = segments.get(k + 1).or(closed.then_some(&segments[0]));
+        if let Some(&(_, after)) = next.filter(|_| phase > 0.0 && phase < on) {
+            let dir_next = (points[after] - p1).normalize_or_zero();
+            generate_round_join(&mut verts, (p1, j), dir_next, dir, half_width, tolerance);
+        }
+    }
+
+    verts
+}
+
+/// Places a round dot every `spacing` along a polyline, starting at its first point. Vertices are
+/// paired with the index of the point starting the segment each dot is on
+fn dots_to_triangles(
+    points: &[Vec2],
+    half_width: f32,
+    tolerance: f32,
+    closed: bool,
+    spacing: f32,
+) -> Vec<(Vec2, usize)> {
+    let mut verts = Vec::new();
+
+    // distance along the current segment to the next dot
+    let mut next = 0.0;
+    for (i, j) in segment_indices(points, closed) {
+        let (p0, p1) = (points[i], points[j]);
+        let length = p0.distance(p1);
+        let Some(dir) = (p1 - p0).try_normalize() else {
+            continue;
+        };
+
+        while next <= length {
+            // a dot is a cap facing each way
+            let center = (p0 + dir * next, i);
+            generate_round_cap(&mut verts, center, dir, half_width, tolerance, true);
+            generate_round_cap(&mut verts, center, dir, half_width, tolerance, false);
+            next += spacing;
+        }
+        next -= length;
+    }
+
+    verts
+}
+
+/// Fewest and most triangles an arc is divided into per half turn, however loose or tight the
+/// tolerance
+const MIN_SEGMENTS_PER_HALF_TURN: f32 = 2.0;
+const MAX_SEGMENTS_PER_HALF_TURN: f32 = 64.0;
+
+/// Number of triangles to divide an arc of `angle` radians into, so that no chord strays more than
+/// `tolerance` inside the true arc. Always at least one
+fn arc_segments(angle: f32, radius: f32, tolerance: f32) -> usize {
+    // the gap between a chord spanning `step` radians and the arc is `radius * (1 - cos(step / 2))`
+    let step = if tolerance < radius {
+        2.0 * (1.0 - tolerance / radius).acos()
+    } else {
+        PI
+    };
+    let turns = angle.abs() / PI;
+    let segments = (angle.abs() / step).ceil().clamp(
+        (MIN_SEGMENTS_PER_HALF_TURN * turns).ceil(),
+        (MAX_SEGMENTS_PER_HALF_TURN * turns).ceil(),
+    );
+    (segments as usize).max(1)
+}
+
+/// Joins turning less than this many radians are left as a plain seam between the segments,
+/// since the gap is too small to see
+const MIN_JOIN_ANGLE: f32 = 0.02;
+
 fn perpendicular(v: Vec2) -> Vec2 {
     vec2(-v.y, v.x)
 }
@@ -126,20 +471,29 @@ fn signed_angle(a: Vec2, b: Vec2) -> f32 {
     a.perp_dot(b).atan2(a.dot(b))
 }
 
-fn push_triangle(verts: &mut Vec<Vec2>, a: Vec2, b: Vec2, c: Vec2) {
+fn push_triangle<T>(verts: &mut Vec<T>, a: T, b: T, c: T) {
     verts.push(a);
     verts.push(b);
     verts.push(c);
 }
 
+/// `center` is the point the join is drawn around, and the index of its source
 fn generate_round_join(
-    verts: &mut Vec<Vec2>,
-    center: Vec2,
+    verts: &mut Vec<(Vec2, usize)>,
+    (center, source): (Vec2, usize),
     dir_in: Vec2,
     dir_out: Vec2,
     half_width: f32,
-    segments: usize,
+    tolerance: f32,
 ) {
+    // there's no telling which way a degenerate segment turns
+    if dir_in == Vec2::ZERO || dir_out == Vec2::ZERO {
+        return;
+    }
+    if signed_angle(dir_in, dir_out).abs() < MIN_JOIN_ANGLE {
+        return;
+    }
+
     // Outward normals
     let n1 = perpendicular(dir_in);
     let n2 = perpendicular(dir_out);
@@ -160,6 +514,7 @@ fn generate_round_join(
         angle -= 2.0 * PI;
     }
 
+    let segments = arc_segments(angle, half_width, tolerance);
     let step = angle / segments as f32;
 
     for i in 0..segments {
@@ -167,16 +522,16 @@ fn generate_round_join(
         let a1 = (i + 1) as f32 * step;
         let p0 = center + Mat2::from_angle(a0) * start * half_width;
         let p1 = center + Mat2::from_angle(a1) * start * half_width;
-        push_triangle(verts, center, p0, p1);
+        push_triangle(verts, (center, source), (p0, source), (p1, source));
     }
 }
 
 fn generate_round_cap(
-    verts: &mut Vec<Vec2>,
-    center: Vec2,
+    verts: &mut Vec<(Vec2, usize)>,
+    (center, source): (Vec2, usize),
     dir: Vec2,
     half_width: f32,
-    segments: usize,
+    tolerance: f32,
     at_start: bool,
 ) {
     // Flip for start cap
@@ -188,6 +543,7 @@ fn generate_round_cap(
     let end = normal;
     let angle = signed_angle(start, end); // should be +PI
 
+    let segments = arc_segments(angle, half_width, tolerance);
     let step = angle / segments as f32;
 
     for i in 0..segments {
@@ -195,6 +551,147 @@ fn generate_round_cap(
         let a1 = (i + 1) as f32 * step;
         let p0 = center + Mat2::from_angle(a0) * start * half_width;
         let p1 = center + Mat2::from_angle(a1) * start * half_width;
-        push_triangle(verts, center, p0, p1);
+        push_triangle(verts, (center, source), (p0, source), (p1, source));
+    }
+}
+
+fn generate_square_cap(
+    verts: &mut Vec<(Vec2, usize)>,
+    (center, source): (Vec2, usize),
+    dir: Vec2,
+    half_width: f32,
+    at_start: bool,
+) {
+    // Flip for start cap
+    let dir = if at_start { -dir } else { dir };
+    let normal = perpendicular(dir) * half_width;
+    let tip = center + dir * half_width;
+
+    let a = (center + normal, source);
+    let b = (center - normal, source);
+    let c = (tip + normal, source);
+    let d = (tip - normal, source);
+    push_triangle(verts, a, b, c);
+    push_triangle(verts, c, b, d);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HALF_WIDTH: f32 = 4.0;
+    const TOLERANCE: f32 = 0.05;
+
+    /// Triangles in the round join where a line heading along `dir_in` turns to `dir_out`
+    fn join_triangles(dir_in: Vec2, dir_out: Vec2) -> usize {
+        let mut verts = vec![];
+        generate_round_join(
+            &mut verts,
+            (Vec2::ZERO, 0),
+            dir_in,
+            dir_out,
+            HALF_WIDTH,
+            TOLERANCE,
+        );
+        assert_eq!(verts.len() % 3, 0);
+        verts.len() / 3
+    }
+
+    #[test]
+    fn straight_join_is_skipped() {
+        assert_eq!(join_triangles(Vec2::X, Vec2::X), 0);
+        // turns below the threshold count as straight
+        let slight = Vec2::from_angle(MIN_JOIN_ANGLE * 0.5);
+        assert_eq!(join_triangles(Vec2::X, slight), 0);
+    }
+
+    #[test]
+    fn right_angle_join_fans_a_quarter_turn() {
+        let left = join_triangles(Vec2::X, Vec2::Y);
+        let right = join_triangles(Vec2::X, Vec2::NEG_Y);
+        assert_eq!(left, arc_segments(PI / 2.0, HALF_WIDTH, TOLERANCE));
+        assert_eq!(left, right);
+        assert!(left >= 1);
+    }
+
+    #[test]
+    fn u_turn_join_fans_a_half_turn() {
+        let u_turn = join_triangles(Vec2::X, Vec2::NEG_X);
+        assert_eq!(u_turn, arc_segments(PI, HALF_WIDTH, TOLERANCE));
+        assert!(u_turn > join_triangles(Vec2::X, Vec2::Y));
+    }
+
+    #[test]
+    fn merging_collinear_runs_cuts_the_vertex_count() {
+        // a square sampled every unit along its sides, like a wall traced by marching squares
+        let side = 20;
+        let corners = [
+            vec2(0.0, 0.0),
+            vec2(1.0, 0.0),
+            vec2(1.0, 1.0),
+            vec2(0.0, 1.0),
+        ];
+        let points: Vec<Vec2> = (0..4)
+            .flat_map(|c| {
+                let (a, b) = (corners[c], corners[(c + 1) % 4]);
+                (0..side).map(move |i| a * side as f32 + (b - a) * i as f32)
+            })
+            .collect();
+
+        let stroke = |points: &[Vec2]| {
+            polyline_to_triangles(
+                points,
+                HALF_WIDTH * 2.0,
+                TOLERANCE,
+                true,
+                LineStyle::Solid,
+                CapStyle::Round,
+            )
+            .len()
+        };
+        // only the corners survive, plus the last point, which is always kept
+        let merged = merge_collinear(&points, 0.01);
+        assert_eq!(
+            merged,
+            [
+                vec2(0.0, 0.0),
+                vec2(20.0, 0.0),
+                vec2(20.0, 20.0),
+                vec2(0.0, 20.0),
+                vec2(0.0, 1.0),
+            ]
+        );
+        assert!(stroke(&merged) * 4 < stroke(&points));
+    }
+
+    #[test]
+    fn concave_polygons_are_fully_covered() {
+        // a four pointed star, pinched in between its points
+        let star: Vec<Vec2> = (0..8)
+            .map(|i| Vec2::from_angle(i as f32 * PI / 4.0) * if i % 2 == 0 { 4.0 } else { 1.0 })
+            .collect();
+        let shoelace: f32 = (0..star.len())
+            .map(|i| star[i].perp_dot(star[(i + 1) % star.len()]))
+            .sum::<f32>()
+            / 2.0;
+
+        let triangles = polygon_to_triangles(&star);
+        assert_eq!(triangles.len(), (star.len() - 2) * 3);
+        let area: f32 = triangles
+            .chunks_exact(3)
+            .map(|t| (t[1] - t[0]).perp_dot(t[2] - t[0]).abs() / 2.0)
+            .sum();
+        assert!((area - shoelace.abs()).abs() < 1e-3, "{area} vs {shoelace}");
+    }
+
+    #[test]
+    fn sharper_turns_never_get_fewer_triangles() {
+        let mut previous = 0;
+        for degrees in (0..=180).step_by(5) {
+            let dir_out = Vec2::from_angle((degrees as f32).to_radians());
+            let triangles = join_triangles(Vec2::X, dir_out);
+            assert!(triangles >= previous, "{degrees} degrees");
+            previous = triangles;
+        }
     }
 }
@@ -1,8 +1,185 @@
+use glam::{Vec2, Vec3, Vec4, vec2, vec4};
 use wasm_bindgen::JsValue;
 use web_sys::{WebGl2RenderingContext, WebGlFramebuffer};
+use web_time::Instant;
 
 use crate::{shader::Shader, texture::Texture};
 
+/// Largest supported supersampling factor, to bound the size of the supersampled target
+const MAX_SUPERSAMPLE: i32 = 4;
+
+/// Smallest fraction of the screen resolution the bloom may be blurred at
+const MIN_BLOOM_SCALE: f32 = 0.125;
+
+/// Streaks shorter than this many pixels aren't worth blurring, and longer ones blend in fully
+const MOTION_BLUR_FADE_PIXELS: f32 = 4.0;
+
+/// A glowing frame stroked around the edge of the playfield, like an arcade cabinet bezel
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Border {
+    pub color: Vec4,
+    /// Thickness of the solid part of the frame, in pixels. The glow extends a few times further
+    pub width: f32,
+    /// Whether the frame is drawn into the scene, so it bends with the CRT curvature and feeds
+    /// bloom, or over the final image, staying straight
+    pub curved: bool,
+}
+
+impl Default for Border {
+    fn default() -> Self {
+        Self {
+            color: vec4(0.6, 0.8, 1.0, 1.0),
+            width: 3.0,
+            curved: true,
+        }
+    }
+}
+
+/// Which post-processing stages run. Disabled stages are skipped entirely, passing the image
+/// through untouched, so turning them off saves their cost
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PostProcessorConfig {
+    /// Glow around bright parts of the scene
+    pub bloom: bool,
+    /// Screen curvature, scanlines and vignetting
+    pub crt: bool,
+    /// Compressing bright colours into the displayable range
+    pub tonemap: bool,
+    /// Smoothing jagged edges with FXAA, just before the CRT effect
+    pub fxaa: bool,
+}
+
+impl Default for PostProcessorConfig {
+    fn default() -> Self {
+        Self {
+            bloom: true,
+            crt: true,
+            tonemap: true,
+            fxaa: false,
+        }
+    }
+}
+
+/// Tunables for the CRT effect
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CrtParams {
+    /// How gently the screen bulges: higher is flatter, and 0 turns the bulge off altogether
+    pub curvature: f32,
+    /// How far apart the red and blue channels are split, as a fraction of the screen width
+    pub aberration: f32,
+    /// How much the scanlines darken the image, from 0 to 1
+    pub scanline_intensity: f32,
+    /// How quickly the image darkens towards the corners. 0 turns vignetting off
+    pub vignette: f32,
+}
+
+impl Default for CrtParams {
+    fn default() -> Self {
+        Self {
+            curvature: 3.0,
+            aberration: 0.001,
+            scanline_intensity: 0.1,
+            vignette: 1.5,
+        }
+    }
+}
+
+/// Tunables for FXAA
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FxaaParams {
+    /// How much to soften detail smaller than a pixel, from 0 for none to 1 for the softest
+    pub subpix: f32,
+    /// The contrast an edge needs, relative to its brightest side, to be smoothed. Lower values
+    /// catch more edges at a higher cost
+    pub edge_threshold: f32,
+    /// The contrast below which dark edges are left alone, however they compare
+    pub edge_threshold_min: f32,
+}
+
+impl Default for FxaaParams {
+    /// The defaults of FXAA 3.11's quality preset
+    fn default() -> Self {
+        Self {
+            subpix: 0.75,
+            edge_threshold: 0.166,
+            edge_threshold_min: 0.0833,
+        }
+    }
+}
+
+/// How bright colours are compressed into the displayable range, when tone mapping is enabled
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ToneMap {
+    /// Colours are left alone, and clip at white
+    Linear,
+    /// Extended Reinhard, which rolls off gradually and reaches pure white at `max_white`
+    Reinhard { max_white: f32 },
+    /// A filmic curve, with more contrast in the midtones and softer highlights
+    Aces(AcesFit),
+}
+
+impl Default for ToneMap {
+    fn default() -> Self {
+        ToneMap::Reinhard { max_white: 1.0 }
+    }
+}
+
+impl ToneMap {
+    /// Parses the lowercase name of an operator, such as `"aces"`, with its default parameters
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(ToneMap::Linear),
+            "reinhard" => Some(ToneMap::default()),
+            "aces" => Some(ToneMap::Aces(AcesFit::default())),
+            _ => None,
+        }
+    }
+}
+
+/// Coefficients of the rational fit `x(ax + b) / (x(cx + d) + e)` to the ACES filmic curve
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AcesFit {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+}
+
+impl Default for AcesFit {
+    /// Krzysztof Narkowicz's fit
+    fn default() -> Self {
+        Self {
+            a: 2.51,
+            b: 0.03,
+            c: 2.43,
+            d: 0.59,
+            e: 0.14,
+        }
+    }
+}
+
+/// How an internal resolution is scaled up to fill the canvas
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Upscale {
+    /// Blocky pixels, for a uniform retro look
+    #[default]
+    Nearest,
+    /// Smoothly interpolated
+    Linear,
+}
+
+impl Upscale {
+    /// Parses the lowercase name of a filter, such as `"linear"`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nearest" => Some(Upscale::Nearest),
+            "linear" => Some(Upscale::Linear),
+            _ => None,
+        }
+    }
+}
+
 pub struct PostProcessor {
     context: WebGl2RenderingContext,
     scene_fbo: WebGlFramebuffer,
@@ -11,16 +188,77 @@ pub struct PostProcessor {
     ping_texture: Texture,
     pong_fbo: WebGlFramebuffer,
     pong_texture: Texture,
+    /// The bloomed and tone mapped image, at full resolution since ping and pong may not be
+    composite_fbo: WebGlFramebuffer,
+    composite_texture: Texture,
+    supersample_fbo: WebGlFramebuffer,
+    supersample_texture: Texture,
+    /// Half resolution, since the streaks are blurry anyway
+    motion_fbo: WebGlFramebuffer,
+    motion_texture: Texture,
     threshold_shader: Shader,
     blur_shader_h: Shader,
     blur_shader_v: Shader,
     composite_shader: Shader,
     crt_shader: Shader,
+    copy_shader: Shader,
+    downsample_shader: Shader,
+    border_shader: Shader,
+    motion_blur_shader: Shader,
+    dim_shader: Shader,
+    grain_shader: Shader,
+    fxaa_shader: Shader,
+    config: PostProcessorConfig,
+    tonemap: ToneMap,
+    crt_params: CrtParams,
+    fxaa_params: FxaaParams,
+    /// Brightness above which parts of the scene bloom
+    bloom_threshold: f32,
+    /// Resolution of the bloom blur, as a fraction of the render resolution
+    bloom_scale: f32,
+    supersample: i32,
+    damage_flash: f32,
+    /// A full-screen flash, added over the final image while it fades out
+    flash_color: Vec4,
+    flash_duration: f32,
+    flash_remaining: f32,
+    border: Option<Border>,
+    motion_blur: f32,
+    /// How far the view moved since the last frame, as a fraction of the screen
+    motion: Vec2,
+    dim_color: Vec3,
+    /// Strength of the film grain, 0 for none
+    grain: f32,
+    /// When the post-processor was created, to animate the grain
+    start_time: Instant,
+    /// Fixed size to render at, or `None` to match the canvas
+    internal_resolution: Option<(i32, i32)>,
+    upscale: Upscale,
+    /// Size of the render targets, which everything but the final pass draws at
     w: i32,
     h: i32,
+    /// Size of the canvas the final pass presents to
+    canvas_w: i32,
+    canvas_h: i32,
 }
 
 impl PostProcessor {
+    /// Sources of every program the post-processor uses, for `precompile_shaders`
+    pub const SHADERS: &[(&str, &str)] = &[
+        (FULLSCREEN_QUAD_VS, THRESHOLD_FS),
+        (FULLSCREEN_QUAD_VS, BLUR_H_FS),
+        (FULLSCREEN_QUAD_VS, BLUR_V_FS),
+        (FULLSCREEN_QUAD_VS, COMPOSITE_FS),
+        (FULLSCREEN_QUAD_VS, CRT_FS),
+        (FULLSCREEN_QUAD_VS, COPY_FS),
+        (FULLSCREEN_QUAD_VS, DOWNSAMPLE_FS),
+        (FULLSCREEN_QUAD_VS, BORDER_FS),
+        (FULLSCREEN_QUAD_VS, MOTION_BLUR_FS),
+        (FULLSCREEN_QUAD_VS, DIM_FS),
+        (FULLSCREEN_QUAD_VS, GRAIN_FS),
+        (FULLSCREEN_QUAD_VS, FXAA_FS),
+    ];
+
     pub fn new(context: &WebGl2RenderingContext) -> Result<Self, JsValue> {
         let scene_texture = Texture::new(
             context,
@@ -43,6 +281,30 @@ impl PostProcessor {
             WebGl2RenderingContext::RGBA,
             WebGl2RenderingContext::UNSIGNED_BYTE,
         );
+        let supersample_texture = Texture::new(
+            context,
+            1,
+            1,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+        );
+        let composite_texture = Texture::new(
+            context,
+            1,
+            1,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+        );
+        // the blur is upsampled when composited, if it's at a lower resolution
+        ping_texture.set_filter(WebGl2RenderingContext::LINEAR);
+        pong_texture.set_filter(WebGl2RenderingContext::LINEAR);
+        let motion_texture = Texture::new(
+            context,
+            1,
+            1,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+        );
 
         let scene_fbo = context
             .create_framebuffer()
@@ -85,184 +347,67 @@ impl PostProcessor {
             0,
         );
 
-        context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
-
-        let fullscreen_quad_vs = r##"#version 300 es
-    
-        in vec4 position;
-
-        out vec2 v_uv;
-
-        void main() {
-            // Hardcoded positions for a fullscreen quad in clip space
-            // (x, y) pairs: (-1,-1), (1,-1), (-1,1), (1,1)
-            vec2 positions[4] = vec2[](
-                vec2(-1.0, -1.0),
-                vec2( 1.0, -1.0),
-                vec2(-1.0,  1.0),
-                vec2( 1.0,  1.0)
-            );
-
-            vec2 pos = positions[gl_VertexID];
-            v_uv = pos * 0.5 + 0.5;
-            gl_Position = vec4(pos, 0.0, 1.0);
-        }
-        "##;
-
-        let threshold_shader = Shader::new(
-            context,
-            fullscreen_quad_vs,
-            r##"#version 300 es
-    
-        precision highp float;
-
-        uniform sampler2D u_texture;
-        uniform float u_texel_width;
-
-        in vec2 v_uv;
-
-        out vec4 outColor;
-        
-        void main() {
-            vec3 color = texture(u_texture, v_uv).rgb;
-            float brightness = max(max(color.r, color.g), color.b); // simple luminance
-            vec3 bloom = brightness > 0.5 ? color : vec3(0.0);
-            outColor = vec4(bloom, 1.0);
-        }
-        "##,
+        let supersample_fbo = context
+            .create_framebuffer()
+            .ok_or("failed to create framebuffer")?;
+        context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&supersample_fbo));
+        context.framebuffer_texture_2d(
+            WebGl2RenderingContext::FRAMEBUFFER,
+            WebGl2RenderingContext::COLOR_ATTACHMENT0,
+            WebGl2RenderingContext::TEXTURE_2D,
+            Some(&supersample_texture.texture),
+            0,
         );
 
-        let blur_shader_h = Shader::new(
-            context,
-            fullscreen_quad_vs,
-            r##"#version 300 es
-    
-        precision highp float;
-
-        uniform sampler2D u_texture;
-        uniform float u_texel_width;
-
-        in vec2 v_uv;
-
-        out vec4 outColor;
-        
-        void main() {
-            vec4 color = vec4(0.0);
-            float weights[5] = float[](0.227, 0.194, 0.121, 0.054, 0.016); // Gaussian weights
-            for (int i = -4; i <= 4; ++i) {
-                float w = weights[abs(i)];
-                vec2 offset = float(i*2) * vec2(u_texel_width, 0.0);
-                color += texture(u_texture, v_uv + offset) * w;
-            }
-            outColor = color;
-        }
-        "##,
+        let motion_fbo = context
+            .create_framebuffer()
+            .ok_or("failed to create framebuffer")?;
+        context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&motion_fbo));
+        context.framebuffer_texture_2d(
+            WebGl2RenderingContext::FRAMEBUFFER,
+            WebGl2RenderingContext::COLOR_ATTACHMENT0,
+            WebGl2RenderingContext::TEXTURE_2D,
+            Some(&motion_texture.texture),
+            0,
         );
 
-        let blur_shader_v = Shader::new(
-            context,
-            fullscreen_quad_vs,
-            r##"#version 300 es
-    
-        precision highp float;
-
-        uniform sampler2D u_texture;
-        uniform float u_texel_height;
-
-        in vec2 v_uv;
-
-        out vec4 outColor;
-        
-        void main() {
-            vec4 color = vec4(0.0);
-            float weights[5] = float[](0.227, 0.194, 0.121, 0.054, 0.016); // Gaussian weights
-            for (int i = -4; i <= 4; ++i) {
-                float w = weights[abs(i)];
-                vec2 offset = float(i*2) * vec2(0.0, u_texel_height);
-                color += texture(u_texture, v_uv + offset) * w;
-            }
-            outColor = color;
-        }
-        "##,
+        let composite_fbo = context
+            .create_framebuffer()
+            .ok_or("failed to create framebuffer")?;
+        context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&composite_fbo));
+        context.framebuffer_texture_2d(
+            WebGl2RenderingContext::FRAMEBUFFER,
+            WebGl2RenderingContext::COLOR_ATTACHMENT0,
+            WebGl2RenderingContext::TEXTURE_2D,
+            Some(&composite_texture.texture),
+            0,
         );
 
-        let composite_shader = Shader::new(
-            context,
-            fullscreen_quad_vs,
-            r##"#version 300 es
-    
-        precision highp float;
+        context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
 
-        uniform sampler2D u_texture;
-        uniform sampler2D u_blur;
+        let threshold_shader = Shader::new(context, FULLSCREEN_QUAD_VS, THRESHOLD_FS)?;
 
-        in vec2 v_uv;
+        let blur_shader_h = Shader::new(context, FULLSCREEN_QUAD_VS, BLUR_H_FS)?;
 
-        out vec4 outColor;
-        
-        vec3 reinhard_extended(vec3 v, float max_white) {
-            vec3 numerator = v * (1.0f + (v / vec3(max_white * max_white)));
-            return numerator / (1.0f + v);
-        }
+        let blur_shader_v = Shader::new(context, FULLSCREEN_QUAD_VS, BLUR_V_FS)?;
 
-        void main() {
-            vec4 scene = texture(u_texture, v_uv);
-            vec4 bloom = texture(u_blur, v_uv);
-            vec4 color = max(scene, bloom);
-            color.rgb = reinhard_extended(color.rgb, 1.0);
-            outColor = color;
-        }
-        "##,
-        );
+        let composite_shader = Shader::new(context, FULLSCREEN_QUAD_VS, COMPOSITE_FS)?;
 
-        let crt_shader = Shader::new(
-            context,
-            fullscreen_quad_vs,
-            r##"#version 300 es
-    
-        precision highp float;
+        let crt_shader = Shader::new(context, FULLSCREEN_QUAD_VS, CRT_FS)?;
 
-        uniform sampler2D u_texture;
+        let copy_shader = Shader::new(context, FULLSCREEN_QUAD_VS, COPY_FS)?;
 
-        in vec2 v_uv;
+        let downsample_shader = Shader::new(context, FULLSCREEN_QUAD_VS, DOWNSAMPLE_FS)?;
 
-        out vec4 outColor;
-        
-        float curvature = 3.0;
+        let border_shader = Shader::new(context, FULLSCREEN_QUAD_VS, BORDER_FS)?;
 
-        vec2 curveRemap(vec2 uv) {
-            uv = uv * 2.0 - 1.0;
-            vec2 offset = abs(uv.yx) / vec2(curvature, curvature);
-            uv = uv + uv * offset * offset;
-            uv = uv * 0.5 + 0.5;
-            return uv;
-        }
-
-        void main() {
-            vec2 uv = curveRemap(v_uv);
+        let motion_blur_shader = Shader::new(context, FULLSCREEN_QUAD_VS, MOTION_BLUR_FS)?;
 
-            vec3 color = texture(u_texture, uv).rgb;
+        let dim_shader = Shader::new(context, FULLSCREEN_QUAD_VS, DIM_FS)?;
 
-            // Slight chromatic aberration
-            float aberr = 0.001;
-            float r = texture(u_texture, uv + vec2(aberr, 0.0)).r;
-            float g = texture(u_texture, uv).g;
-            float b = texture(u_texture, uv - vec2(aberr, 0.0)).b;
-            color = vec3(r, g, b);
+        let grain_shader = Shader::new(context, FULLSCREEN_QUAD_VS, GRAIN_FS)?;
 
-            // Horizontal scanlines
-            float scanline = sin(uv.y * 200.0 * 2.0 * 3.14159) * 0.1;
-            color -= scanline;
-
-            // Vignette
-            float dist = distance(v_uv, vec2(0.5));
-            float vignette = pow(1.0 - dist, 1.5);
-            color *= vignette;
-
-            outColor = vec4(color, 1.0);
-        }
-        "##,
-        );
+        let fxaa_shader = Shader::new(context, FULLSCREEN_QUAD_VS, FXAA_FS)?;
 
         Ok(Self {
             context: context.clone(),
@@ -272,95 +417,1006 @@ impl PostProcessor {
             ping_texture,
             pong_fbo,
             pong_texture,
+            composite_fbo,
+            composite_texture,
+            supersample_fbo,
+            supersample_texture,
+            motion_fbo,
+            motion_texture,
             threshold_shader,
             blur_shader_h,
             blur_shader_v,
             composite_shader,
             crt_shader,
+            copy_shader,
+            downsample_shader,
+            border_shader,
+            motion_blur_shader,
+            dim_shader,
+            grain_shader,
+            fxaa_shader,
+            config: PostProcessorConfig::default(),
+            tonemap: ToneMap::default(),
+            crt_params: CrtParams::default(),
+            fxaa_params: FxaaParams::default(),
+            bloom_threshold: 0.5,
+            bloom_scale: 1.0,
+            supersample: 1,
+            damage_flash: 0.0,
+            flash_color: Vec4::ZERO,
+            flash_duration: 0.0,
+            flash_remaining: 0.0,
+            border: None,
+            motion_blur: 0.0,
+            motion: Vec2::ZERO,
+            dim_color: Vec3::ZERO,
+            grain: 0.0,
+            start_time: Instant::now(),
+            internal_resolution: None,
+            upscale: Upscale::Nearest,
             w: 1,
             h: 1,
+            canvas_w: 1,
+            canvas_h: 1,
         })
     }
 
+    /// Tells the post-processor the size of the canvas. The render targets follow it, unless
+    /// there's an internal resolution
     pub fn on_resize(&mut self, w: i32, h: i32) {
+        self.canvas_w = w;
+        self.canvas_h = h;
+
+        let (w, h) = self.internal_resolution.unwrap_or((w, h));
         self.w = w;
         self.h = h;
 
         self.scene_texture.write(w, h, None);
-        self.ping_texture.write(w, h, None);
-        self.pong_texture.write(w, h, None);
+        self.composite_texture.write(w, h, None);
+        let (bloom_w, bloom_h) = self.bloom_size();
+        self.ping_texture.write(bloom_w, bloom_h, None);
+        self.pong_texture.write(bloom_w, bloom_h, None);
+        self.motion_texture
+            .write((w / 2).max(1), (h / 2).max(1), None);
+
+        if self.supersample > 1 {
+            self.supersample_texture
+                .write(w * self.supersample, h * self.supersample, None);
+        } else {
+            // release the supersampled backing while it isn't in use
+            self.supersample_texture.write(1, 1, None);
+        }
     }
 
-    pub fn start_capture(&self) {
+    /// Renders the scene at `factor` times the resolution in each axis, and averages it back
+    /// down before post-processing. Smooths thin lines at the cost of fill rate; 1 disables it
+    pub fn set_supersample(&mut self, factor: i32) {
+        self.supersample = factor.clamp(1, MAX_SUPERSAMPLE);
+        self.on_resize(self.canvas_w, self.canvas_h);
+    }
+
+    /// Renders the scene and every effect at a fixed size, which only the final pass scales to
+    /// the canvas, keeping its aspect ratio. Fill cost and the look of the effects then no longer
+    /// depend on the display. `None`, the default, renders at the canvas size
+    pub fn set_internal_resolution(&mut self, resolution: Option<(i32, i32)>) {
+        self.internal_resolution = resolution.map(|(w, h)| (w.max(1), h.max(1)));
+        self.on_resize(self.canvas_w, self.canvas_h);
+    }
+
+    /// How the internal resolution is scaled up to the canvas
+    pub fn set_upscale(&mut self, upscale: Upscale) {
+        self.upscale = upscale;
+
+        // these are what the final pass samples from
+        self.scene_texture.set_filter(self.upscale_filter());
+        self.composite_texture.set_filter(self.upscale_filter());
+    }
+
+    fn upscale_filter(&self) -> u32 {
+        match self.upscale {
+            Upscale::Nearest => WebGl2RenderingContext::NEAREST,
+            Upscale::Linear => WebGl2RenderingContext::LINEAR,
+        }
+    }
+
+    /// Size of the image the scene is rendered into, in pixels
+    pub fn render_size(&self) -> (i32, i32) {
+        (self.w, self.h)
+    }
+
+    pub fn set_bloom(&mut self, enabled: bool) {
+        self.config.bloom = enabled;
+    }
+
+    /// How bright, from 0 to 1 in the brightest channel, part of the scene must be to bloom. Lower
+    /// thresholds make more of the scene glow. 0.5 by default
+    pub fn set_bloom_threshold(&mut self, threshold: f32) {
+        self.bloom_threshold = threshold;
+    }
+
+    /// Blurs the bloom at a fraction of the resolution, such as 0.5 or 0.25, which costs far less
+    /// and looks much the same since it's blurry anyway. 1, the default, blurs at full resolution
+    pub fn set_bloom_scale(&mut self, scale: f32) {
+        self.bloom_scale = scale.clamp(MIN_BLOOM_SCALE, 1.0);
+        self.on_resize(self.canvas_w, self.canvas_h);
+    }
+
+    /// Size of the bloom blur targets, in pixels
+    fn bloom_size(&self) -> (i32, i32) {
+        let scaled = |size: i32| ((size as f32 * self.bloom_scale).round() as i32).max(1);
+        (scaled(self.w), scaled(self.h))
+    }
+
+    pub fn set_crt(&mut self, enabled: bool) {
+        self.config.crt = enabled;
+    }
+
+    /// Dials the CRT effect in, for example flattening it for players who find the curvature
+    /// uncomfortable
+    pub fn set_crt_params(&mut self, params: CrtParams) {
+        self.crt_params = params;
+    }
+
+    pub fn set_fxaa(&mut self, enabled: bool) {
+        self.config.fxaa = enabled;
+    }
+
+    /// Trades how many edges FXAA smooths, and how much it softens fine detail, against cost and
+    /// sharpness
+    pub fn set_fxaa_params(&mut self, params: FxaaParams) {
+        self.fxaa_params = params;
+    }
+
+    pub fn set_tonemap_enabled(&mut self, enabled: bool) {
+        self.config.tonemap = enabled;
+    }
+
+    /// Which tone mapping operator to use, while tone mapping is enabled. Extended Reinhard with a
+    /// `max_white` of 1 by default
+    pub fn set_tonemap(&mut self, tonemap: ToneMap) {
+        self.tonemap = tonemap;
+    }
+
+    /// How strongly to tint the edges of the screen red, from 0 to 1
+    pub fn set_damage_flash(&mut self, amount: f32) {
+        self.damage_flash = amount.clamp(0.0, 1.0);
+    }
+
+    /// Flashes the whole screen, adding `color` scaled by its alpha, and fading out over
+    /// `duration` seconds. Replaces any flash still fading
+    pub fn trigger_flash(&mut self, color: Vec4, duration: f32) {
+        self.flash_color = color;
+        self.flash_duration = duration.max(f32::EPSILON);
+        self.flash_remaining = self.flash_duration;
+    }
+
+    /// Advances time-based effects by `dt` seconds
+    pub fn update(&mut self, dt: f32) {
+        self.flash_remaining = (self.flash_remaining - dt).max(0.0);
+    }
+
+    /// Frames the playfield with a glowing border, or `None` to leave it unframed
+    pub fn set_border(&mut self, border: Option<Border>) {
+        self.border = border;
+    }
+
+    /// Smears the image along the direction the view is moving. `strength` scales the length of
+    /// the streaks relative to the distance moved since the last frame; 0 disables it
+    pub fn set_motion_blur(&mut self, strength: f32) {
+        self.motion_blur = strength.max(0.0);
+    }
+
+    /// How far the view moved since the last frame, as a fraction of the screen in each axis
+    pub fn set_motion(&mut self, motion: Vec2) {
+        self.motion = motion;
+    }
+
+    /// Overlays animated film grain on the final image, from 0 for none to 1 for nothing but
+    /// noise. A few hundredths is enough to be felt rather than seen
+    pub fn set_grain(&mut self, intensity: f32) {
+        self.grain = intensity.clamp(0.0, 1.0);
+    }
+
+    /// Colour `draw_dim_overlay` fades towards. Black by default
+    pub fn set_dim_color(&mut self, color: Vec3) {
+        self.dim_color = color;
+    }
+
+    /// Darkens everything drawn so far into the bound framebuffer, by blending the dim colour over
+    /// the whole viewport at the given opacity. Used between capture and `finish`, it sits behind
+    /// anything drawn afterwards and still goes through the effects
+    pub fn draw_dim_overlay(&self, alpha: f32) {
+        if alpha <= 0.0 {
+            return;
+        }
+
+        self.context.enable(WebGl2RenderingContext::BLEND);
+        self.context.blend_func(
+            WebGl2RenderingContext::SRC_ALPHA,
+            WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
+        );
+        self.dim_shader
+            .uniform4f("u_color", self.dim_color.extend(alpha.min(1.0)));
         self.context
-            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.scene_fbo));
+            .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+        self.context.disable(WebGl2RenderingContext::BLEND);
+    }
+
+    pub fn start_capture(&self) {
+        if self.supersample > 1 {
+            self.context.bind_framebuffer(
+                WebGl2RenderingContext::FRAMEBUFFER,
+                Some(&self.supersample_fbo),
+            );
+            self.context
+                .viewport(0, 0, self.w * self.supersample, self.h * self.supersample);
+        } else {
+            self.context
+                .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.scene_fbo));
+            self.context.viewport(0, 0, self.w, self.h);
+        }
     }
 
     pub fn finish(&self) {
-        // ping
+        // every renderer turns blending back off once it's done, and the passes below rely on it
+        debug_assert!(
+            !self.context.is_enabled(WebGl2RenderingContext::BLEND),
+            "blending left on before post processing"
+        );
+
+        if self.supersample > 1 {
+            self.downsample_pass();
+        }
+
+        if self.motion_blur > 0.0 && self.motion != Vec2::ZERO {
+            self.motion_blur_pass();
+        }
+
+        if let Some(border) = self.border.filter(|border| border.curved) {
+            self.context
+                .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.scene_fbo));
+            self.border_pass(border, vec4(0.0, 0.0, self.w as f32, self.h as f32));
+        }
+
+        if self.config.bloom {
+            self.bloom_pass();
+        } else if self.config.tonemap {
+            self.tonemap_pass();
+        }
+
+        if self.config.fxaa {
+            self.fxaa_pass();
+        }
+
+        if self.config.crt {
+            self.crt_pass();
+        } else {
+            // present the image without the CRT effect
+            self.bind_screen();
+            self.copy_shader
+                .bind_texture("u_texture", 0, self.composited_texture());
+            self.copy_shader.uniform1f("u_damage", self.damage_flash);
+            self.context
+                .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+        }
+
+        if self.flash_remaining > 0.0 {
+            self.flash_pass();
+        }
+
+        // the screen is still bound, so a straight border goes over everything
+        if let Some(border) = self.border.filter(|border| !border.curved) {
+            self.border_pass(border, self.screen_rect());
+        }
+
+        // grain goes on last of all, so it neither blooms nor bends with the CRT
+        if self.grain > 0.0 {
+            self.grain_pass();
+        }
+    }
+
+    /// Adds the flash colour over whatever is bound, easing out as it fades
+    fn flash_pass(&self) {
+        let t = self.flash_remaining / self.flash_duration;
+        let strength = self.flash_color.w * t * t;
+
+        self.context.enable(WebGl2RenderingContext::BLEND);
         self.context
-            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.ping_fbo));
-        self.threshold_shader
-            .bind_texture("u_texture", 0, &self.scene_texture);
+            .blend_func(WebGl2RenderingContext::ONE, WebGl2RenderingContext::ONE);
+        self.dim_shader.uniform4f(
+            "u_color",
+            (self.flash_color.truncate() * strength).extend(0.0),
+        );
         self.context
             .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+        self.context.disable(WebGl2RenderingContext::BLEND);
+    }
+
+    /// Blends noise, different every frame, over whatever is bound
+    fn grain_pass(&self) {
+        self.context.enable(WebGl2RenderingContext::BLEND);
+        self.context.blend_func(
+            WebGl2RenderingContext::ONE,
+            WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
+        );
+        self.grain_shader.uniform1f("u_grain", self.grain);
+        self.grain_shader
+            .uniform1f("u_time", self.start_time.elapsed().as_secs_f32());
+        self.context
+            .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+        self.context.disable(WebGl2RenderingContext::BLEND);
+    }
+
+    /// Where on the canvas the final image goes, as x, y, width and height in pixels. With an
+    /// internal resolution, the image is scaled to fit and centred, so its shape is kept
+    fn screen_rect(&self) -> Vec4 {
+        let canvas = vec2(self.canvas_w as f32, self.canvas_h as f32);
+        if self.internal_resolution.is_none() {
+            return vec4(0.0, 0.0, canvas.x, canvas.y);
+        }
+
+        let image = vec2(self.w as f32, self.h as f32);
+        let size = (image * (canvas / image).min_element()).round();
+        let origin = ((canvas - size) * 0.5).floor();
+        vec4(origin.x, origin.y, size.x, size.y)
+    }
+
+    /// Binds the canvas for the final pass, clearing any bars left around the image
+    fn bind_screen(&self) {
+        self.context
+            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
 
-        // pong
+        let rect = self.screen_rect();
+        if self.internal_resolution.is_some() {
+            self.context.viewport(0, 0, self.canvas_w, self.canvas_h);
+            self.context.clear_color(0.0, 0.0, 0.0, 1.0);
+            self.context.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
+        }
         self.context
-            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.pong_fbo));
-        self.blur_shader_v
-            .bind_texture("u_texture", 0, &self.ping_texture);
-        self.blur_shader_v
-            .uniform1f("u_texel_height", 1.0 / self.h as f32);
+            .viewport(rect.x as i32, rect.y as i32, rect.z as i32, rect.w as i32);
+    }
+
+    /// Blurs the scene along the motion into the half resolution target, then blends that back
+    /// over the scene, more opaquely the longer the streaks
+    fn motion_blur_pass(&self) {
+        let streak = self.motion * self.motion_blur;
+        let streak_pixels = (streak * vec2(self.w as f32, self.h as f32)).length();
+        let opacity = (streak_pixels / MOTION_BLUR_FADE_PIXELS).min(1.0);
+
+        self.context
+            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.motion_fbo));
+        self.context
+            .viewport(0, 0, (self.w / 2).max(1), (self.h / 2).max(1));
+        self.motion_blur_shader
+            .bind_texture("u_texture", 0, &self.scene_texture);
+        self.motion_blur_shader
+            .uniform4f("u_motion", streak.extend(1.0).extend(0.0));
         self.context
             .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
 
-        // ping
+        // the blurred copy is at half resolution, so the same shader upsamples it with no further
+        // streaking
         self.context
-            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.ping_fbo));
-        self.blur_shader_h
-            .bind_texture("u_texture", 0, &self.pong_texture);
-        self.blur_shader_h
-            .uniform1f("u_texel_width", 1.0 / self.w as f32);
+            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.scene_fbo));
+        self.context.viewport(0, 0, self.w, self.h);
+        self.context.enable(WebGl2RenderingContext::BLEND);
+        self.context.blend_func(
+            WebGl2RenderingContext::SRC_ALPHA,
+            WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
+        );
+        self.motion_blur_shader
+            .bind_texture("u_texture", 0, &self.motion_texture);
+        self.motion_blur_shader
+            .uniform4f("u_motion", vec4(0.0, 0.0, opacity, 0.0));
+        self.context
+            .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+        self.context.disable(WebGl2RenderingContext::BLEND);
+    }
+
+    /// Blends the border over whatever is bound, framing `rect`: x, y, width and height in pixels
+    fn border_pass(&self, border: Border, rect: Vec4) {
+        self.context.enable(WebGl2RenderingContext::BLEND);
+        self.context.blend_func(
+            WebGl2RenderingContext::ONE,
+            WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
+        );
+        self.border_shader.uniform4f("u_color", border.color);
+        self.border_shader
+            .uniform4f("u_border", vec4(rect.z, rect.w, border.width, 0.0));
+        self.border_shader
+            .uniform4f("u_origin", vec4(rect.x, rect.y, 0.0, 0.0));
         self.context
             .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+        self.context.disable(WebGl2RenderingContext::BLEND);
+    }
 
-        // pong
+    /// Resolves the supersampled capture into the scene texture, so the rest of the chain runs at
+    /// screen resolution
+    fn downsample_pass(&self) {
         self.context
-            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.pong_fbo));
-        self.blur_shader_v
-            .bind_texture("u_texture", 0, &self.ping_texture);
-        self.blur_shader_v
-            .uniform1f("u_texel_height", 1.0 / self.h as f32);
+            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.scene_fbo));
+        self.context.viewport(0, 0, self.w, self.h);
+        self.downsample_shader
+            .bind_texture("u_texture", 0, &self.supersample_texture);
+        self.downsample_shader
+            .uniform1i("u_factor", self.supersample);
         self.context
             .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+    }
+
+    /// Extracts the bright parts of the scene, blurs them at the bloom resolution, and composites
+    /// the result into `composite`
+    fn bloom_pass(&self) {
+        let (bloom_w, bloom_h) = self.bloom_size();
+        self.context.viewport(0, 0, bloom_w, bloom_h);
 
         // ping
         self.context
             .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.ping_fbo));
-        self.blur_shader_h
-            .bind_texture("u_texture", 0, &self.pong_texture);
-        self.blur_shader_h
-            .uniform1f("u_texel_width", 1.0 / self.w as f32);
+        self.threshold_shader
+            .bind_texture("u_texture", 0, &self.scene_texture);
+        self.threshold_shader
+            .uniform1f("u_threshold", self.bloom_threshold);
+        self.threshold_shader.uniform4f(
+            "u_step",
+            vec4(0.25 / bloom_w as f32, 0.25 / bloom_h as f32, 0.0, 0.0),
+        );
         self.context
             .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
 
-        // composite
-        self.context
-            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.pong_fbo));
+        // blur vertically into pong, then horizontally back into ping, twice over
+        for _ in 0..2 {
+            self.context
+                .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.pong_fbo));
+            self.blur_shader_v
+                .bind_texture("u_texture", 0, &self.ping_texture);
+            self.blur_shader_v
+                .uniform1f("u_texel_height", 1.0 / bloom_h as f32);
+            self.context
+                .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+
+            self.context
+                .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.ping_fbo));
+            self.blur_shader_h
+                .bind_texture("u_texture", 0, &self.pong_texture);
+            self.blur_shader_h
+                .uniform1f("u_texel_width", 1.0 / bloom_w as f32);
+            self.context
+                .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+        }
+
+        // composite, back at full resolution
+        self.context.bind_framebuffer(
+            WebGl2RenderingContext::FRAMEBUFFER,
+            Some(&self.composite_fbo),
+        );
+        self.context.viewport(0, 0, self.w, self.h);
         self.composite_shader
             .bind_texture("u_texture", 0, &self.scene_texture);
         self.composite_shader
             .bind_texture("u_blur", 1, &self.ping_texture);
+        self.bind_tonemap(self.config.tonemap);
         self.context
             .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+    }
 
-        // CRT effect
+    /// Tone maps the scene into `composite` without bloom, by compositing the scene over itself
+    fn tonemap_pass(&self) {
+        self.context.bind_framebuffer(
+            WebGl2RenderingContext::FRAMEBUFFER,
+            Some(&self.composite_fbo),
+        );
+        self.context.viewport(0, 0, self.w, self.h);
+        self.composite_shader
+            .bind_texture("u_texture", 0, &self.scene_texture);
+        self.composite_shader
+            .bind_texture("u_blur", 1, &self.scene_texture);
+        self.bind_tonemap(true);
         self.context
-            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+            .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+    }
+
+    /// Sets the composite shader's tone mapping operator and its parameters, or none when disabled
+    fn bind_tonemap(&self, enabled: bool) {
+        let tonemap = if enabled {
+            self.tonemap
+        } else {
+            ToneMap::Linear
+        };
+        let (operator, params, fit) = match tonemap {
+            ToneMap::Linear => (0, Vec4::ZERO, AcesFit::default()),
+            ToneMap::Reinhard { max_white } => {
+                (1, vec4(max_white, 0.0, 0.0, 0.0), AcesFit::default())
+            }
+            ToneMap::Aces(fit) => (2, vec4(0.0, fit.e, 0.0, 0.0), fit),
+        };
+        self.composite_shader.uniform1i("u_operator", operator);
+        self.composite_shader.uniform4f("u_params", params);
+        self.composite_shader
+            .uniform4f("u_aces", vec4(fit.a, fit.b, fit.c, fit.d));
+    }
+
+    /// The image as it stands before the final pass: in `composite` if bloom or tone mapping ran,
+    /// or the scene itself. FXAA then swaps it into the other one
+    fn composited_texture(&self) -> &Texture {
+        let composited = self.config.bloom || self.config.tonemap;
+        if composited != self.config.fxaa {
+            &self.composite_texture
+        } else {
+            &self.scene_texture
+        }
+    }
+
+    /// Smooths the edges of the composited image, writing it into whichever of the scene and
+    /// `composite` it isn't in already
+    fn fxaa_pass(&self) {
+        let (source, target) = if self.config.bloom || self.config.tonemap {
+            (&self.composite_texture, &self.scene_fbo)
+        } else {
+            (&self.scene_texture, &self.composite_fbo)
+        };
+
+        self.context
+            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(target));
+        self.context.viewport(0, 0, self.w, self.h);
+
+        // FXAA leans on bilinear filtering to sample between texels
+        source.set_filter(WebGl2RenderingContext::LINEAR);
+        self.fxaa_shader.bind_texture("u_texture", 0, source);
+        self.fxaa_shader.uniform4f(
+            "u_texel",
+            vec4(1.0 / self.w as f32, 1.0 / self.h as f32, 0.0, 0.0),
+        );
+        let params = self.fxaa_params;
+        self.fxaa_shader.uniform4f(
+            "u_params",
+            vec4(
+                params.subpix,
+                params.edge_threshold,
+                params.edge_threshold_min,
+                0.0,
+            ),
+        );
+        self.context
+            .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+        source.set_filter(self.upscale_filter());
+    }
+
+    fn crt_pass(&self) {
+        // CRT effect
+        self.bind_screen();
         self.crt_shader
-            .bind_texture("u_texture", 0, &self.scene_texture);
+            .bind_texture("u_texture", 0, self.composited_texture());
+        self.crt_shader.uniform1f("u_damage", self.damage_flash);
+        let params = self.crt_params;
+        self.crt_shader.uniform1f("u_curvature", params.curvature);
+        self.crt_shader.uniform1f("u_aberration", params.aberration);
+        self.crt_shader
+            .uniform1f("u_scanline_intensity", params.scanline_intensity);
+        self.crt_shader.uniform1f("u_vignette", params.vignette);
         self.context
             .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
     }
 }
+
+const FULLSCREEN_QUAD_VS: &str = r##"#version 300 es
+
+in vec4 position;
+
+out vec2 v_uv;
+
+void main() {
+    // Hardcoded positions for a fullscreen quad in clip space
+    // (x, y) pairs: (-1,-1), (1,-1), (-1,1), (1,1)
+    vec2 positions[4] = vec2[](
+        vec2(-1.0, -1.0),
+        vec2( 1.0, -1.0),
+        vec2(-1.0,  1.0),
+        vec2( 1.0,  1.0)
+    );
+
+    vec2 pos = positions[gl_VertexID];
+    v_uv = pos * 0.5 + 0.5;
+    gl_Position = vec4(pos, 0.0, 1.0);
+}
+"##;
+
+const THRESHOLD_FS: &str = r##"#version 300 es
+
+precision highp float;
+
+uniform sampler2D u_texture;
+uniform float u_threshold;
+// a quarter of an output pixel, in uv units
+uniform vec4 u_step;
+
+in vec2 v_uv;
+
+out vec4 outColor;
+
+void main() {
+    // average the scene texels under this pixel, so thin lines aren't missed when downsampling
+    vec3 color = (texture(u_texture, v_uv + vec2(-u_step.x, -u_step.y)).rgb
+        + texture(u_texture, v_uv + vec2(u_step.x, -u_step.y)).rgb
+        + texture(u_texture, v_uv + vec2(-u_step.x, u_step.y)).rgb
+        + texture(u_texture, v_uv + vec2(u_step.x, u_step.y)).rgb) * 0.25;
+    float brightness = max(max(color.r, color.g), color.b); // simple luminance
+    vec3 bloom = brightness > u_threshold ? color : vec3(0.0);
+    outColor = vec4(bloom, 1.0);
+}
+"##;
+
+const BLUR_H_FS: &str = r##"#version 300 es
+
+precision highp float;
+
+uniform sampler2D u_texture;
+uniform float u_texel_width;
+
+in vec2 v_uv;
+
+out vec4 outColor;
+
+void main() {
+    vec4 color = vec4(0.0);
+    float weights[5] = float[](0.227, 0.194, 0.121, 0.054, 0.016); // Gaussian weights
+    for (int i = -4; i <= 4; ++i) {
+        float w = weights[abs(i)];
+        vec2 offset = float(i*2) * vec2(u_texel_width, 0.0);
+        color += texture(u_texture, v_uv + offset) * w;
+    }
+    outColor = color;
+}
+"##;
+
+const BLUR_V_FS: &str = r##"#version 300 es
+
+precision highp float;
+
+uniform sampler2D u_texture;
+uniform float u_texel_height;
+
+in vec2 v_uv;
+
+out vec4 outColor;
+
+void main() {
+    vec4 color = vec4(0.0);
+    float weights[5] = float[](0.227, 0.194, 0.121, 0.054, 0.016); // Gaussian weights
+    for (int i = -4; i <= 4; ++i) {
+        float w = weights[abs(i)];
+        vec2 offset = float(i*2) * vec2(0.0, u_texel_height);
+        color += texture(u_texture, v_uv + offset) * w;
+    }
+    outColor = color;
+}
+"##;
+
+const COMPOSITE_FS: &str = r##"#version 300 es
+
+precision highp float;
+
+uniform sampler2D u_texture;
+uniform sampler2D u_blur;
+// 0 for none, 1 for extended Reinhard, 2 for ACES
+uniform int u_operator;
+// the max white for Reinhard, then the ACES fit's e
+uniform vec4 u_params;
+// the ACES fit's a, b, c and d
+uniform vec4 u_aces;
+
+in vec2 v_uv;
+
+out vec4 outColor;
+
+vec3 reinhard_extended(vec3 v, float max_white) {
+    vec3 numerator = v * (1.0f + (v / vec3(max_white * max_white)));
+    return numerator / (1.0f + v);
+}
+
+vec3 aces(vec3 x) {
+    vec3 numerator = x * (u_aces.x * x + u_aces.y);
+    vec3 denominator = x * (u_aces.z * x + u_aces.w) + u_params.y;
+    return clamp(numerator / denominator, 0.0, 1.0);
+}
+
+void main() {
+    vec4 scene = texture(u_texture, v_uv);
+    vec4 bloom = texture(u_blur, v_uv);
+    vec4 color = max(scene, bloom);
+    if (u_operator == 1) {
+        color.rgb = reinhard_extended(color.rgb, u_params.x);
+    } else if (u_operator == 2) {
+        color.rgb = aces(color.rgb);
+    }
+    outColor = color;
+}
+"##;
+
+const CRT_FS: &str = r##"#version 300 es
+
+precision highp float;
+
+uniform sampler2D u_texture;
+uniform float u_damage;
+uniform float u_curvature;
+uniform float u_aberration;
+uniform float u_scanline_intensity;
+uniform float u_vignette;
+
+in vec2 v_uv;
+
+out vec4 outColor;
+
+vec2 curveRemap(vec2 uv) {
+    if (u_curvature <= 0.0) {
+        return uv;
+    }
+
+    uv = uv * 2.0 - 1.0;
+    vec2 offset = abs(uv.yx) / vec2(u_curvature, u_curvature);
+    uv = uv + uv * offset * offset;
+    uv = uv * 0.5 + 0.5;
+    return uv;
+}
+
+void main() {
+    vec2 uv = curveRemap(v_uv);
+
+    vec3 color = texture(u_texture, uv).rgb;
+
+    // Slight chromatic aberration
+    float aberr = u_aberration;
+    float r = texture(u_texture, uv + vec2(aberr, 0.0)).r;
+    float g = texture(u_texture, uv).g;
+    float b = texture(u_texture, uv - vec2(aberr, 0.0)).b;
+    color = vec3(r, g, b);
+
+    // Horizontal scanlines
+    float scanline = sin(uv.y * 200.0 * 2.0 * 3.14159) * u_scanline_intensity;
+    color -= scanline;
+
+    // Vignette
+    float dist = distance(v_uv, vec2(0.5));
+    float vignette = pow(1.0 - dist, u_vignette);
+    color *= vignette;
+
+    // Damage flash, tinting the edges of the screen red
+    float edge = smoothstep(0.2, 0.7, distance(v_uv, vec2(0.5)));
+    color = mix(color, vec3(1.0, 0.0, 0.0), u_damage * edge);
+
+    outColor = vec4(color, 1.0);
+}
+"##;
+
+const COPY_FS: &str = r##"#version 300 es
+
+precision highp float;
+
+uniform sampler2D u_texture;
+uniform float u_damage;
+
+in vec2 v_uv;
+
+out vec4 outColor;
+
+void main() {
+    vec3 color = texture(u_texture, v_uv).rgb;
+
+    // Damage flash, tinting the edges of the screen red
+    float edge = smoothstep(0.2, 0.7, distance(v_uv, vec2(0.5)));
+    color = mix(color, vec3(1.0, 0.0, 0.0), u_damage * edge);
+
+    outColor = vec4(color, 1.0);
+}
+"##;
+
+const DOWNSAMPLE_FS: &str = r##"#version 300 es
+
+precision highp float;
+
+uniform sampler2D u_texture;
+uniform int u_factor;
+
+out vec4 outColor;
+
+void main() {
+    // box filter over the block of supersampled texels covering this pixel
+    ivec2 base = ivec2(gl_FragCoord.xy) * u_factor;
+    vec4 color = vec4(0.0);
+    for (int y = 0; y < u_factor; ++y) {
+        for (int x = 0; x < u_factor; ++x) {
+            color += texelFetch(u_texture, base + ivec2(x, y), 0);
+        }
+    }
+    outColor = color / float(u_factor * u_factor);
+}
+"##;
+
+const BORDER_FS: &str = r##"#version 300 es
+
+precision highp float;
+
+uniform vec4 u_color;
+// width and height of the framed area, then the width of the border, all in pixels
+uniform vec4 u_border;
+// bottom left corner of the framed area, in pixels
+uniform vec4 u_origin;
+
+out vec4 outColor;
+
+void main() {
+    vec2 pixel = gl_FragCoord.xy - u_origin.xy;
+    float edge = min(min(pixel.x, u_border.x - pixel.x), min(pixel.y, u_border.y - pixel.y));
+
+    // a solid core, with a glow falling off inwards from it
+    float width = u_border.z;
+    float core = 1.0 - smoothstep(width - 1.0, width, edge);
+    float glow = exp(-max(edge - width, 0.0) / max(width, 1.0)) * 0.5;
+    float alpha = max(core, glow) * u_color.a;
+
+    outColor = vec4(u_color.rgb * alpha, alpha);
+}
+"##;
+
+const MOTION_BLUR_FS: &str = r##"#version 300 es
+
+precision highp float;
+
+uniform sampler2D u_texture;
+// the streak to blur along, in uv units, then the opacity of the output
+uniform vec4 u_motion;
+
+in vec2 v_uv;
+
+out vec4 outColor;
+
+const int SAMPLES = 8;
+
+void main() {
+    vec3 color = vec3(0.0);
+    for (int i = 0; i < SAMPLES; ++i) {
+        float t = float(i) / float(SAMPLES - 1) - 0.5;
+        color += texture(u_texture, v_uv + u_motion.xy * t).rgb;
+    }
+    outColor = vec4(color / float(SAMPLES), u_motion.z);
+}
+"##;
+
+const DIM_FS: &str = r##"#version 300 es
+
+precision highp float;
+
+uniform vec4 u_color;
+
+out vec4 outColor;
+
+void main() {
+    outColor = u_color;
+}
+"##;
+
+const GRAIN_FS: &str = r##"#version 300 es
+
+precision highp float;
+
+uniform float u_grain;
+uniform float u_time;
+
+out vec4 outColor;
+
+float hash(vec3 p) {
+    p = fract(p * 0.1031);
+    p += dot(p, p.zyx + 31.32);
+    return fract((p.x + p.y) * p.z);
+}
+
+void main() {
+    // a fresh pattern every sixtieth of a second, cycling before the seed gets too large to be
+    // precise
+    float noise = hash(vec3(gl_FragCoord.xy, mod(floor(u_time * 60.0), 1000.0)));
+    outColor = vec4(vec3(noise) * u_grain, u_grain);
+}
+"##;
+
+const FXAA_FS: &str = r##"#version 300 es
+
+precision highp float;
+
+uniform sampler2D u_texture;
+// the size of a texel, in uv units
+uniform vec4 u_texel;
+// subpixel softening, the relative edge threshold, and the absolute edge threshold
+uniform vec4 u_params;
+
+in vec2 v_uv;
+
+out vec4 outColor;
+
+// how far each step of the search along an edge goes, in texels
+const int SEARCH_STEPS = 10;
+const float SEARCH_STEP_SIZES[SEARCH_STEPS] = float[](1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 4.0, 8.0);
+// how much further an edge is assumed to go when the search runs out
+const float SEARCH_GUESS = 8.0;
+
+float luma(vec2 uv) {
+    return dot(texture(u_texture, uv).rgb, vec3(0.299, 0.587, 0.114));
+}
+
+// walks along an edge until the luma changes, returning the uv there and how the luma differs
+vec2 search(vec2 uv, vec2 step_along, float edge_luma, float threshold, out float delta) {
+    delta = 0.0;
+    for (int i = 0; i < SEARCH_STEPS; ++i) {
+        uv += step_along * SEARCH_STEP_SIZES[i];
+        delta = luma(uv) - edge_luma;
+        if (abs(delta) >= threshold) {
+            return uv;
+        }
+    }
+    return uv + step_along * SEARCH_GUESS;
+}
+
+void main() {
+    vec4 color = texture(u_texture, v_uv);
+    float m = dot(color.rgb, vec3(0.299, 0.587, 0.114));
+    float n = luma(v_uv + vec2(0.0, u_texel.y));
+    float s = luma(v_uv - vec2(0.0, u_texel.y));
+    float e = luma(v_uv + vec2(u_texel.x, 0.0));
+    float w = luma(v_uv - vec2(u_texel.x, 0.0));
+
+    // leave pixels alone that aren't on a visible edge
+    float highest = max(max(max(n, s), max(e, w)), m);
+    float lowest = min(min(min(n, s), min(e, w)), m);
+    float contrast = highest - lowest;
+    if (contrast < max(u_params.z, highest * u_params.y)) {
+        outColor = color;
+        return;
+    }
+
+    float ne = luma(v_uv + u_texel.xy);
+    float nw = luma(v_uv + vec2(-u_texel.x, u_texel.y));
+    float se = luma(v_uv + vec2(u_texel.x, -u_texel.y));
+    float sw = luma(v_uv - u_texel.xy);
+
+    // soften pixels that stand out from all their neighbours, such as the ends of thin lines
+    float average = (2.0 * (n + s + e + w) + ne + nw + se + sw) / 12.0;
+    float subpix = smoothstep(0.0, 1.0, clamp(abs(average - m) / contrast, 0.0, 1.0));
+    subpix = subpix * subpix * u_params.x;
+
+    // which way the edge runs, and which side of it this pixel is on
+    float horizontal = 2.0 * abs(n + s - 2.0 * m) + abs(ne + se - 2.0 * e) + abs(nw + sw - 2.0 * w);
+    float vertical = 2.0 * abs(e + w - 2.0 * m) + abs(ne + nw - 2.0 * n) + abs(se + sw - 2.0 * s);
+    bool is_horizontal = horizontal >= vertical;
+
+    float opposite = is_horizontal ? n : e;
+    float negative = is_horizontal ? s : w;
+    vec2 step_across = is_horizontal ? vec2(0.0, u_texel.y) : vec2(u_texel.x, 0.0);
+    vec2 step_along = is_horizontal ? vec2(u_texel.x, 0.0) : vec2(0.0, u_texel.y);
+    if (abs(negative - m) > abs(opposite - m)) {
+        step_across = -step_across;
+        opposite = negative;
+    }
+
+    // follow the edge both ways, halfway between this pixel and the one across it
+    vec2 edge_uv = v_uv + step_across * 0.5;
+    float edge_luma = (m + opposite) * 0.5;
+    float threshold = abs(opposite - m) * 0.25;
+    float positive_delta;
+    float negative_delta;
+    vec2 positive_end = search(edge_uv, step_along, edge_luma, threshold, positive_delta);
+    vec2 negative_end = search(edge_uv, -step_along, edge_luma, threshold, negative_delta);
+
+    float positive_distance = is_horizontal ? positive_end.x - v_uv.x : positive_end.y - v_uv.y;
+    float negative_distance = is_horizontal ? v_uv.x - negative_end.x : v_uv.y - negative_end.y;
+    float shortest = min(positive_distance, negative_distance);
+    float delta = positive_distance <= negative_distance ? positive_delta : negative_delta;
+
+    // blend across the edge more the nearer this pixel is to the end it slopes towards
+    float edge_blend = 0.0;
+    if ((delta >= 0.0) != (m - edge_luma >= 0.0)) {
+        edge_blend = 0.5 - shortest / (positive_distance + negative_distance);
+    }
+
+    outColor = texture(u_texture, v_uv + step_across * max(subpix, edge_blend));
+}
+"##;
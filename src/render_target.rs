@@ -0,0 +1,118 @@
+use wasm_bindgen::{Clamped, JsCast, JsValue};
+use web_sys::{
+    CanvasRenderingContext2d, HtmlCanvasElement, ImageData, WebGl2RenderingContext,
+    WebGlFramebuffer,
+};
+
+use crate::{document, texture::Texture};
+
+/// An offscreen framebuffer with a single colour texture attached
+pub struct RenderTarget {
+    context: WebGl2RenderingContext,
+    fbo: WebGlFramebuffer,
+    pub texture: Texture,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl RenderTarget {
+    pub fn new(context: &WebGl2RenderingContext, w: i32, h: i32) -> Result<Self, JsValue> {
+        let texture = Texture::new(
+            context,
+            w,
+            h,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+        );
+
+        let fbo = context
+            .create_framebuffer()
+            .ok_or("failed to create framebuffer")?;
+        context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&fbo));
+        context.framebuffer_texture_2d(
+            WebGl2RenderingContext::FRAMEBUFFER,
+            WebGl2RenderingContext::COLOR_ATTACHMENT0,
+            WebGl2RenderingContext::TEXTURE_2D,
+            Some(&texture.texture),
+            0,
+        );
+        context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+
+        Ok(Self {
+            context: context.clone(),
+            fbo,
+            texture,
+            w,
+            h,
+        })
+    }
+
+    /// Directs rendering into this target, and sets the viewport to cover it
+    pub fn bind(&self) {
+        self.context
+            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.fbo));
+        self.context.viewport(0, 0, self.w, self.h);
+    }
+
+    /// Reads back the contents as tightly packed RGBA rows, top row first
+    pub fn read_pixels(&self) -> Result<Vec<u8>, JsValue> {
+        let row = self.w as usize * 4;
+        let mut pixels = vec![0; row * self.h as usize];
+
+        self.context
+            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.fbo));
+        self.context.read_pixels_with_opt_u8_array(
+            0,
+            0,
+            self.w,
+            self.h,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+            Some(&mut pixels),
+        )?;
+
+        // GL returns the bottom row first
+        let mut flipped = Vec::with_capacity(pixels.len());
+        for y in (0..self.h as usize).rev() {
+            flipped.extend_from_slice(&pixels[y * row..(y + 1) * row]);
+        }
+
+        Ok(flipped)
+    }
+
+    /// Encodes the contents as a PNG `data:` URL, suitable for storing in local storage
+    pub fn to_png_data_url(&self) -> Result<String, JsValue> {
+        let mut pixels = self.read_pixels()?;
+
+        // the scene is opaque, but blending leaves arbitrary values in the alpha channel
+        for alpha in pixels.iter_mut().skip(3).step_by(4) {
+            *alpha = 255;
+        }
+
+        let canvas = document()
+            .create_element("canvas")?
+            .dyn_into::<HtmlCanvasElement>()?;
+        canvas.set_width(self.w as u32);
+        canvas.set_height(self.h as u32);
+
+        let context = canvas
+            .get_context("2d")?
+            .ok_or("failed to create 2d context")?
+            .dyn_into::<CanvasRenderingContext2d>()?;
+        let image = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&pixels),
+            self.w as u32,
+            self.h as u32,
+        )?;
+        context.put_image_data(&image, 0.0, 0.0)?;
+
+        canvas.to_data_url()
+    }
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        self.context.delete_framebuffer(Some(&self.fbo));
+        self.context.delete_texture(Some(&self.texture.texture));
+    }
+}
@@ -0,0 +1,407 @@
+use crate::settings::Difficulty;
+
+/// Bumped whenever the encoding changes, so old links are rejected rather than misread
+const VERSION: u8 = 2;
+
+/// Most input changes a replay holds. Recording stops here, keeping links a shareable length
+const MAX_CHANGES: usize = 4096;
+
+/// Longest fragment `from_url_fragment` will decode: a full replay with room to spare
+const MAX_FRAGMENT_LEN: usize = (16 + MAX_CHANGES * 6) * 4 / 3 + 4;
+
+/// Steps between no input and full input that a replay records, for analog sticks and triggers
+const INPUT_LEVELS: f32 = 7.0;
+
+/// The player's input for one fixed update
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Input {
+    /// Thrust amount in [0, 1]
+    pub thrust: f32,
+    /// Signed turn amount in [-1, 1], negative turns left and positive turns right
+    pub turn: f32,
+}
+
+impl Input {
+    /// Rounded to the steps a replay records, so playing it back steers exactly as the player did
+    pub fn quantized(self) -> Self {
+        Self::from_byte(self.to_byte()).unwrap()
+    }
+
+    /// Packs into a byte: bits 0 to 2 are the thrust level, and bits 3 to 6 the turn level, offset
+    /// so full left is 0
+    fn to_byte(self) -> u8 {
+        let thrust = (self.thrust.clamp(0.0, 1.0) * INPUT_LEVELS).round() as u8;
+        let turn = ((self.turn.clamp(-1.0, 1.0) + 1.0) * INPUT_LEVELS).round() as u8;
+        thrust | turn << 3
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        let turn = (byte >> 3) as f32;
+        (turn <= INPUT_LEVELS * 2.0).then_some(Self {
+            thrust: (byte & 0b111) as f32 / INPUT_LEVELS,
+            turn: turn / INPUT_LEVELS - 1.0,
+        })
+    }
+}
+
+/// The inputs of a whole run, which replays it exactly when fed back in from the same seed
+pub struct Replay {
+    pub seed: u32,
+    pub difficulty: Difficulty,
+    /// The tick each change of input happened on, and the input from then on
+    changes: Vec<(u32, Input)>,
+    /// Number of ticks the run lasted
+    length: u32,
+    /// Set once `MAX_CHANGES` is reached, after which the replay ends
+    full: bool,
+}
+
+impl Replay {
+    pub fn new(seed: u32, difficulty: Difficulty) -> Self {
+        Self {
+            seed,
+            difficulty,
+            changes: vec![],
+            length: 0,
+            full: false,
+        }
+    }
+
+    /// Notes the input on the given tick, which must not be earlier than the last one recorded
+    pub fn record(&mut self, tick: u32, input: Input) {
+        if self.full {
+            return;
+        }
+
+        let previous = self.changes.last().map(|&(_, input)| input);
+        if previous.unwrap_or_default().to_byte() != input.to_byte() {
+            if self.changes.len() == MAX_CHANGES {
+                self.full = true;
+                self.length = tick;
+                return;
+            }
+            self.changes.push((tick, input));
+        }
+    }
+
+    /// Marks the end of the run
+    pub fn finish(&mut self, ticks: u32) {
+        if !self.full {
+            self.length = ticks;
+        }
+    }
+
+    /// Number of ticks the run lasted
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// The input in effect on the given tick
+    pub fn input_at(&self, tick: u32) -> Input {
+        let i = self.changes.partition_point(|&(t, _)| t <= tick);
+        i.checked_sub(1)
+            .map(|i| self.changes[i].1)
+            .unwrap_or_default()
+    }
+
+    /// Encodes the replay as URL-safe base64, for the `#replay=` fragment of a link
+    pub fn to_url_fragment(&self) -> String {
+        let mut bytes = vec![VERSION, difficulty_to_byte(self.difficulty)];
+        write_varint(&mut bytes, self.seed);
+        write_varint(&mut bytes, self.length);
+        write_varint(&mut bytes, self.changes.len() as u32);
+
+        // ticks are stored as the gap since the previous change, which is usually small
+        let mut last = 0;
+        for &(tick, input) in &self.changes {
+            write_varint(&mut bytes, tick - last);
+            bytes.push(input.to_byte());
+            last = tick;
+        }
+
+        base64_encode(&bytes)
+    }
+
+    /// Decodes a replay made by `to_url_fragment`, or `None` if it's malformed, too long, or from
+    /// a different version
+    pub fn from_url_fragment(fragment: &str) -> Option<Self> {
+        if fragment.len() > MAX_FRAGMENT_LEN {
+            return None;
+        }
+
+        let bytes = base64_decode(fragment)?;
+        let mut reader = bytes.iter().copied();
+
+        if reader.next()? != VERSION {
+            return None;
+        }
+        let difficulty = difficulty_from_byte(reader.next()?)?;
+        let seed = read_varint(&mut reader)?;
+        let length = read_varint(&mut reader)?;
+        let count = read_varint(&mut reader)? as usize;
+        if count > MAX_CHANGES {
+            return None;
+        }
+
+        let mut changes = Vec::with_capacity(count);
+        let mut tick = 0u32;
+        for _ in 0..count {
+            tick = tick.checked_add(read_varint(&mut reader)?)?;
+            changes.push((tick, Input::from_byte(reader.next()?)?));
+        }
+
+        if reader.next().is_some() {
+            return None;
+        }
+
+        Some(Self {
+            seed,
+            difficulty,
+            changes,
+            length,
+            full: false,
+        })
+    }
+}
+
+fn difficulty_to_byte(difficulty: Difficulty) -> u8 {
+    match difficulty {
+        Difficulty::Easy => 0,
+        Difficulty::Normal => 1,
+        Difficulty::Hard => 2,
+    }
+}
+
+fn difficulty_from_byte(byte: u8) -> Option<Difficulty> {
+    match byte {
+        0 => Some(Difficulty::Easy),
+        1 => Some(Difficulty::Normal),
+        2 => Some(Difficulty::Hard),
+        _ => None,
+    }
+}
+
+/// LEB128: seven bits at a time, least significant first, with the top bit set on all but the
+/// last byte
+fn write_varint(bytes: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(reader: &mut impl Iterator<Item = u8>) -> Option<u32> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = reader.next()?;
+        value |= ((byte & 0x7f) as u32).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+const BASE64_URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Unpadded base64 with the URL-safe alphabet
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            encoded.push(BASE64_URL[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    encoded
+}
+
+fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(encoded.len() * 3 / 4);
+    for chunk in encoded.as_bytes().chunks(4) {
+        if chunk.len() < 2 {
+            return None;
+        }
+
+        let mut n = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let digit = BASE64_URL.iter().position(|&d| d == c)? as u32;
+            n |= digit << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            bytes.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An input as it's recorded, rounded to the levels a replay keeps
+    fn input(thrust: f32, turn: f32) -> Input {
+        Input { thrust, turn }.quantized()
+    }
+
+    /// A run with changes far enough apart that some gaps take several varint bytes
+    fn sample_replay() -> Replay {
+        let mut replay = Replay::new(u32::MAX, Difficulty::Hard);
+        replay.record(0, input(1.0, 0.0));
+        replay.record(3, input(1.0, -1.0));
+        replay.record(200, input(0.0, 1.0));
+        replay.record(70_000, input(0.5, -0.25));
+        replay.finish(70_500);
+        replay
+    }
+
+    /// Encodes raw bytes the way `to_url_fragment` would, to hand-craft malformed replays
+    fn fragment(bytes: &[u8]) -> String {
+        base64_encode(bytes)
+    }
+
+    #[test]
+    fn fragments_round_trip() {
+        let replay = sample_replay();
+        let decoded = Replay::from_url_fragment(&replay.to_url_fragment()).unwrap();
+
+        assert_eq!(decoded.seed, replay.seed);
+        assert_eq!(decoded.difficulty, replay.difficulty);
+        assert_eq!(decoded.length(), replay.length());
+        assert_eq!(decoded.changes, replay.changes);
+    }
+
+    #[test]
+    fn fragments_are_url_safe() {
+        let encoded = sample_replay().to_url_fragment();
+        assert!(
+            encoded
+                .bytes()
+                .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_'),
+            "{encoded}"
+        );
+    }
+
+    #[test]
+    fn input_holds_until_the_next_change() {
+        let replay = sample_replay();
+        assert_eq!(replay.input_at(0), input(1.0, 0.0));
+        assert_eq!(replay.input_at(2), input(1.0, 0.0));
+        assert_eq!(replay.input_at(3), input(1.0, -1.0));
+        assert_eq!(replay.input_at(199), input(1.0, -1.0));
+        assert_eq!(replay.input_at(200), input(0.0, 1.0));
+        assert_eq!(replay.input_at(u32::MAX), input(0.5, -0.25));
+
+        let mut late = Replay::new(0, Difficulty::Normal);
+        late.record(10, input(1.0, 0.0));
+        assert_eq!(late.input_at(9), Input::default());
+    }
+
+    #[test]
+    fn unchanged_input_is_not_recorded() {
+        let mut replay = Replay::new(0, Difficulty::Normal);
+        replay.record(0, Input::default());
+        replay.record(1, input(1.0, 0.0));
+        // close enough to round to the same level
+        replay.record(2, input(0.99, 0.01));
+        assert_eq!(replay.changes.len(), 1);
+    }
+
+    #[test]
+    fn inputs_pack_into_a_byte() {
+        // within half a level, allowing for rounding exactly halfway between two
+        let half_level = 0.5 / INPUT_LEVELS + 1e-6;
+        for (thrust, turn) in [(0.0, -1.0), (1.0, 1.0), (0.5, 0.0), (0.3, -0.6)] {
+            let quantized = Input { thrust, turn }.quantized();
+            assert!((quantized.thrust - thrust).abs() <= half_level);
+            assert!((quantized.turn - turn).abs() <= half_level);
+            assert_eq!(quantized.quantized(), quantized);
+        }
+        // out of range inputs are clamped rather than wrapping into the other field
+        assert_eq!(
+            Input {
+                thrust: 3.0,
+                turn: -5.0
+            }
+            .quantized(),
+            input(1.0, -1.0)
+        );
+        // turn levels past full right don't decode
+        assert_eq!(Input::from_byte(0b1111_1000), None);
+    }
+
+    #[test]
+    fn full_replays_fit_the_fragment_bound() {
+        let mut replay = Replay::new(u32::MAX, Difficulty::Easy);
+        for i in 0..=MAX_CHANGES as u32 {
+            let level = if i % 2 == 0 { 1.0 } else { -1.0 };
+            replay.record(i * 100_000, input(1.0, level));
+        }
+        replay.finish(u32::MAX);
+
+        assert_eq!(replay.changes.len(), MAX_CHANGES);
+        let encoded = replay.to_url_fragment();
+        assert!(encoded.len() <= MAX_FRAGMENT_LEN);
+        let decoded = Replay::from_url_fragment(&encoded).unwrap();
+        assert_eq!(decoded.length(), MAX_CHANGES as u32 * 100_000);
+        assert_eq!(decoded.changes, replay.changes);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut bytes = base64_decode(&sample_replay().to_url_fragment()).unwrap();
+        bytes[0] = VERSION + 1;
+        assert!(Replay::from_url_fragment(&fragment(&bytes)).is_none());
+    }
+
+    #[test]
+    fn trailing_bytes_are_rejected() {
+        let mut bytes = base64_decode(&sample_replay().to_url_fragment()).unwrap();
+        bytes.push(0);
+        assert!(Replay::from_url_fragment(&fragment(&bytes)).is_none());
+    }
+
+    #[test]
+    fn too_many_changes_are_rejected() {
+        let mut bytes = vec![VERSION, 1];
+        write_varint(&mut bytes, 0);
+        write_varint(&mut bytes, 0);
+        write_varint(&mut bytes, MAX_CHANGES as u32 + 1);
+        assert!(Replay::from_url_fragment(&fragment(&bytes)).is_none());
+    }
+
+    #[test]
+    fn truncated_varints_are_rejected() {
+        // the seed's continuation bit promises another byte that never comes
+        assert!(Replay::from_url_fragment(&fragment(&[VERSION, 1, 0x80])).is_none());
+        // or it runs on past the five bytes a u32 can take
+        let overlong = [VERSION, 1, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01, 0, 0];
+        assert!(Replay::from_url_fragment(&fragment(&overlong)).is_none());
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for value in [0, 1, 127, 128, 300, 16_384, u32::MAX] {
+            let mut bytes = vec![];
+            write_varint(&mut bytes, value);
+            assert_eq!(read_varint(&mut bytes.into_iter()), Some(value));
+        }
+    }
+
+    #[test]
+    fn over_long_fragments_are_rejected() {
+        let long = "A".repeat(MAX_FRAGMENT_LEN + 1);
+        assert!(Replay::from_url_fragment(&long).is_none());
+    }
+
+    #[test]
+    fn malformed_base64_is_rejected() {
+        assert!(Replay::from_url_fragment("not base64!").is_none());
+        // a lone character can't hold a whole byte
+        assert!(base64_decode("AAAAA").is_none());
+    }
+}
@@ -0,0 +1,94 @@
+/// A small deterministic PRNG (PCG32), so anything random in gameplay can be replayed exactly
+/// from the run's seed
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+const MULTIPLIER: u64 = 6364136223846793005;
+const INCREMENT: u64 = 1442695040888963407;
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        let mut rng = Self { state: 0 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed as u64);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let state = self.state;
+        self.state = state.wrapping_mul(MULTIPLIER).wrapping_add(INCREMENT);
+
+        let xorshifted = (((state >> 18) ^ state) >> 27) as u32;
+        let rotation = (state >> 59) as u32;
+        xorshifted.rotate_right(rotation)
+    }
+
+    /// A float uniformly distributed in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// A float uniformly distributed in [min, max)
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+/// Picks a fresh seed for a new run. This is the only place the game uses non-deterministic
+/// randomness.
+pub fn random_seed() -> u32 {
+    (js_sys::Math::random() * u32::MAX as f64) as u32
+}
+
+/// The seed for today's run, which everyone playing on the same UTC day shares
+pub fn daily_seed() -> u32 {
+    const MILLIS_PER_DAY: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+    let day = (js_sys::Date::now() / MILLIS_PER_DAY).floor() as u32;
+    // consecutive days would otherwise make consecutive seeds
+    Rng::new(day).next_u32()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(seed: u32) -> Vec<u32> {
+        let mut rng = Rng::new(seed);
+        (0..64).map(|_| rng.next_u32()).collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_sequence() {
+        for seed in [0, 1, 3, 12345, u32::MAX] {
+            assert_eq!(sequence(seed), sequence(seed), "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn different_seeds_diverge() {
+        let a = sequence(1);
+        let b = sequence(2);
+        assert_ne!(a[0], b[0]);
+        // they shouldn't just be the same sequence shifted along either
+        assert!(a.iter().zip(&b).filter(|(a, b)| a == b).count() < 4);
+        assert!(
+            !a.windows(8)
+                .any(|window| b.windows(8).any(|other| other == window))
+        );
+    }
+
+    #[test]
+    fn floats_stay_in_range() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let x = rng.next_f32();
+            assert!((0.0..1.0).contains(&x));
+            let y = rng.range(-3.0, 5.0);
+            assert!((-3.0..5.0).contains(&y));
+        }
+    }
+}
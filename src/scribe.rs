@@ -1,27 +1,111 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    hash::{BuildHasherDefault, Hasher},
+};
 
-use glam::{Mat4, Vec2, Vec4, vec4};
+use glam::{Vec2, Vec4, vec4};
 use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlVertexArrayObject};
 
 use crate::{
-    compile_shader, link_program, polyline::{lines_to_triangles, polyline_to_triangles}, reinterpret_cast_slice,
+    polyline::{
+        CapStyle, LineStyle, lines_to_triangles_with_sources, merge_collinear,
+        merge_collinear_indices, polygon_to_triangles, polyline_to_triangles,
+        polyline_to_triangles_variable, polyline_to_triangles_with_sources,
+    },
+    reinterpret_cast_slice,
+    shader::Shader,
+    uniform_block::CAMERA_BINDING,
 };
 
+/// A colour registered with a `Scribe`. Geometry is batched by colour, so handles stay cheap to
+/// hash while the actual values live in the scribe's palette
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
-pub enum Color {
-    White,
-    Yellow,
-    PaleBlue,
-}
+pub struct Color(u16);
 
 impl Color {
-    pub fn to_gl(&self) -> Vec4 {
-        match self {
-            Color::White => Vec4::ONE,
-            Color::Yellow => vec4(1.0, 1.0, 0.0, 1.0),
-            Color::PaleBlue => vec4(0.6, 0.6, 0.8, 1.0),
+    pub const WHITE: Color = Color(0);
+    pub const YELLOW: Color = Color(1);
+    pub const PALE_BLUE: Color = Color(2);
+}
+
+/// The colours every scribe starts with, in the order of the `Color` constants
+const BUILTIN_COLORS: [(&str, Vec4); 3] = [
+    ("white", Vec4::ONE),
+    ("yellow", vec4(1.0, 1.0, 0.0, 1.0)),
+    ("pale_blue", vec4(0.6, 0.6, 0.8, 1.0)),
+];
+
+/// Turns smaller than this many radians are merged away when `merge_collinear` is on, well under
+/// what a round join would draw anything visible for
+const COLLINEAR_TOLERANCE: f32 = 0.01;
+
+/// Most a round join or cap may stray from a true arc, in pixels
+const ARC_TOLERANCE_PIXELS: f32 = 0.25;
+
+/// Vertices closer than this, in world units, are merged into one when indexing
+const DEDUP_EPSILON: f32 = 1e-4;
+
+// Vertices carry their own colour, which is multiplied by the colour of the batch they are drawn
+// in. Most geometry uses white vertices, so the batch colour is used as-is.
+//
+// They are interleaved in one buffer as `position` (2 floats) then `color` (4 floats), 24 bytes a
+// vertex, and drawn as an indexed triangle list. Strokes come out of `polyline` wound clockwise, but
+// nothing is culled, so winding doesn't matter here.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Vertex {
+    pos: Vec2,
+    color: Vec4,
+}
+
+/// Identifies vertices near enough to share an index: the position snapped to `DEDUP_EPSILON`, and
+/// the exact colour. The snapped position is keyed by its bits rather than cast to an integer,
+/// which would saturate far from the origin
+#[derive(Hash, PartialEq, Eq, Debug)]
+struct VertexKey([u32; 2], [u32; 4]);
+
+impl VertexKey {
+    fn new(vertex: &Vertex) -> Self {
+        // adding zero turns -0 into 0, so both sides of an axis share keys
+        let snapped = (vertex.pos / DEDUP_EPSILON).round() + Vec2::ZERO;
+        Self(
+            snapped.to_array().map(f32::to_bits),
+            vertex.color.to_array().map(f32::to_bits),
+        )
+    }
+}
+
+/// Hashes `VertexKey`s for the dedup map, which is rebuilt every frame from thousands of them.
+/// Keys are plain bits from our own geometry, so there's nothing to gain from SipHash's defence
+/// against crafted keys, and this mixes each word in with a rotate and a multiply instead
+#[derive(Default)]
+struct VertexHasher(u64);
+
+impl Hasher for VertexHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(4) {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.write_u32(u32::from_le_bytes(word));
         }
     }
+
+    fn write_u32(&mut self, word: u32) {
+        self.0 = (self.0.rotate_left(5) ^ word as u64).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Vertex counts from every `render` since the last `take_stats`, to see how much indexing saves
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ScribeStats {
+    /// Vertices queued, three per triangle
+    pub queued_vertices: usize,
+    /// Distinct vertices actually uploaded
+    pub unique_vertices: usize,
 }
 
 pub struct Scribe {
@@ -29,47 +113,38 @@ pub struct Scribe {
     program: WebGlProgram,
     vao: WebGlVertexArrayObject,
     buffer: WebGlBuffer,
-    vertices: HashMap<Color, Vec<Vec2>>,
+    index_buffer: WebGlBuffer,
+    vertices: HashMap<Color, Vec<Vertex>>,
+    /// The distinct vertices of every batch, as uploaded. Kept between frames to reuse its memory
+    staging: Vec<Vertex>,
+    /// Every batch's triangles back to back, as indices into `staging`
+    indices: Vec<u32>,
+    /// Where each distinct vertex ended up in `staging`
+    dedup: HashMap<VertexKey, u32, BuildHasherDefault<VertexHasher>>,
+    /// Vertices and indices the GPU buffers have room for. They only grow, and are otherwise
+    /// updated in place
+    buffer_capacity: usize,
+    index_capacity: usize,
+    stats: ScribeStats,
+    palette: Vec<(String, Vec4)>,
+    merge_collinear: bool,
+    /// `ARC_TOLERANCE_PIXELS` in world units, at the current scale
+    arc_tolerance: f32,
 }
 
 impl Scribe {
-    pub fn new(context: &WebGl2RenderingContext) -> Self {
-        let vert_shader = compile_shader(
-            &context,
-            WebGl2RenderingContext::VERTEX_SHADER,
-            r##"#version 300 es
-    
-        uniform mat4 transform;
-
-        in vec4 position;
-    
-        void main() {
-            gl_Position = transform * vec4(position.xyz, 1.0);
-        }
-        "##,
-        )
-        .expect("vertex shader to compile");
-
-        let frag_shader = compile_shader(
-            &context,
-            WebGl2RenderingContext::FRAGMENT_SHADER,
-            r##"#version 300 es
-    
-        precision highp float;
+    /// Sources of the program lines are drawn with, for `precompile_shaders`
+    pub const SHADERS: &[(&str, &str)] = &[(SCRIBE_VS, SCRIBE_FS)];
 
-        uniform vec4 color;
-
-        out vec4 outColor;
-        
-        void main() {
-            outColor = color; // vec4(1, 1, 1, 1);
-        }
-        "##,
-        )
-        .expect("fragment shader to compile");
-        let program = link_program(&context, &vert_shader, &frag_shader).expect("program to link");
+    pub fn new(context: &WebGl2RenderingContext) -> Self {
+        let program = Shader::new(context, SCRIBE_VS, SCRIBE_FS)
+            .expect("scribe shader to compile")
+            .program;
         context.use_program(Some(&program));
 
+        let camera_index = context.get_uniform_block_index(&program, "Camera");
+        context.uniform_block_binding(&program, camera_index, CAMERA_BINDING);
+
         let vao = context
             .create_vertex_array()
             .ok_or("Could not create vertex array object")
@@ -88,67 +163,490 @@ impl Scribe {
             2,
             WebGl2RenderingContext::FLOAT,
             false,
-            std::mem::size_of::<f32>() as i32 * 2,
+            std::mem::size_of::<Vertex>() as i32,
             0,
         );
         context.enable_vertex_attrib_array(position_attribute_location as u32);
 
+        let color_attribute_location = context.get_attrib_location(&program, "color");
+        context.vertex_attrib_pointer_with_i32(
+            color_attribute_location as u32,
+            4,
+            WebGl2RenderingContext::FLOAT,
+            false,
+            std::mem::size_of::<Vertex>() as i32,
+            std::mem::offset_of!(Vertex, color) as i32,
+        );
+        context.enable_vertex_attrib_array(color_attribute_location as u32);
+
+        // bound while the VAO is, so the VAO remembers it
+        let index_buffer = context
+            .create_buffer()
+            .ok_or("Failed to create buffer")
+            .unwrap();
+        context.bind_buffer(
+            WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
+            Some(&index_buffer),
+        );
+
         Self {
             context: context.clone(),
             program,
             vao,
             buffer,
+            index_buffer,
             vertices: HashMap::new(),
+            staging: Vec::new(),
+            indices: Vec::new(),
+            dedup: HashMap::default(),
+            buffer_capacity: 0,
+            index_capacity: 0,
+            stats: ScribeStats::default(),
+            palette: BUILTIN_COLORS
+                .iter()
+                .map(|&(name, value)| (name.to_string(), value))
+                .collect(),
+            merge_collinear: true,
+            arc_tolerance: ARC_TOLERANCE_PIXELS,
+        }
+    }
+
+    /// Registers a named colour, returning a handle to draw with. Defining a name again updates
+    /// its value and returns the same handle
+    pub fn define_color(&mut self, name: &str, value: Vec4) -> Color {
+        if let Some(color) = self.color_named(name) {
+            self.palette[color.0 as usize].1 = value;
+            return color;
+        }
+
+        self.palette.push((name.to_string(), value));
+        Color((self.palette.len() - 1) as u16)
+    }
+
+    /// Looks up a colour previously registered with `define_color`, or a built-in one
+    pub fn color_named(&self, name: &str) -> Option<Color> {
+        self.palette
+            .iter()
+            .position(|(n, _)| n == name)
+            .map(|i| Color(i as u16))
+    }
+
+    pub fn color_value(&self, color: Color) -> Vec4 {
+        self.palette[color.0 as usize].1
+    }
+
+    /// Whether polylines are straightened out before tessellating, merging runs of nearly
+    /// collinear segments into one. On by default; turning it off keeps every input point
+    pub fn set_merge_collinear(&mut self, enabled: bool) {
+        self.merge_collinear = enabled;
+    }
+
+    pub fn merge_collinear(&self) -> bool {
+        self.merge_collinear
+    }
+
+    /// How many pixels one world unit covers, which sets how finely curves are divided
+    pub fn set_pixels_per_unit(&mut self, pixels_per_unit: f32) {
+        self.arc_tolerance = ARC_TOLERANCE_PIXELS / pixels_per_unit.max(f32::EPSILON);
+    }
+
+    /// Tessellates a polyline with round joins and caps, merging collinear runs first if enabled
+    fn stroke(
+        &self,
+        points: &[Vec2],
+        width: f32,
+        closed: bool,
+        style: LineStyle,
+        cap: CapStyle,
+    ) -> Vec<Vec2> {
+        let tolerance = self.arc_tolerance;
+        if self.merge_collinear {
+            let merged = merge_collinear(points, COLLINEAR_TOLERANCE);
+            polyline_to_triangles(&merged, width, tolerance, closed, style, cap)
+        } else {
+            polyline_to_triangles(points, width, tolerance, closed, style, cap)
         }
     }
 
     pub fn draw_poly_line(&mut self, points: &[Vec2], width: f32, closed: bool, color: Color) {
-        let vertices = polyline_to_triangles(points, width, 12, closed);
-        self.vertices.entry(color).or_default().extend(&vertices);
+        let vertices = self.stroke(points, width, closed, LineStyle::Solid, CapStyle::Round);
+        self.push_tinted(color, &vertices, Vec4::ONE);
+    }
+
+    /// Strokes an open polyline like `draw_poly_line_capped`, made translucent by `alpha`, from 0
+    /// for invisible to 1 for opaque
+    pub fn draw_poly_line_faded(
+        &mut self,
+        points: &[Vec2],
+        width: f32,
+        cap: CapStyle,
+        color: Color,
+        alpha: f32,
+    ) {
+        let vertices = self.stroke(points, width, false, LineStyle::Solid, cap);
+        self.push_tinted(color, &vertices, vec4(1.0, 1.0, 1.0, alpha));
+    }
+
+    /// Strokes an open polyline with the given style of end caps, rather than round ones
+    pub fn draw_poly_line_capped(
+        &mut self,
+        points: &[Vec2],
+        width: f32,
+        cap: CapStyle,
+        color: Color,
+    ) {
+        let vertices = self.stroke(points, width, false, LineStyle::Solid, cap);
+        self.push_tinted(color, &vertices, Vec4::ONE);
+    }
+
+    /// Strokes a polyline with a dashed or dotted pattern, which runs on unbroken round corners
+    pub fn draw_dashed_line(
+        &mut self,
+        points: &[Vec2],
+        width: f32,
+        closed: bool,
+        style: LineStyle,
+        color: Color,
+    ) {
+        let vertices = self.stroke(points, width, closed, style, CapStyle::Round);
+        self.push_tinted(color, &vertices, Vec4::ONE);
     }
 
-    pub fn draw_lines(&mut self, points: &[Vec2], width: f32, color: Color) {
-        let vertices = lines_to_triangles(points, width);
-        self.vertices.entry(color).or_default().extend(&vertices);
+    /// Strokes an open polyline whose width varies from point to point, blending along each
+    /// segment, for plumes and trails that taper off. `widths` are full widths, one per point
+    pub fn draw_tapered_line(&mut self, points: &[Vec2], widths: &[f32], color: Color) {
+        let half_widths = widths.iter().map(|w| w / 2.0).collect::<Vec<_>>();
+        let vertices =
+            polyline_to_triangles_variable(points, &half_widths, self.arc_tolerance, false);
+        self.push_tinted(color, &vertices, Vec4::ONE);
     }
 
-    pub fn render(&mut self, transform: Mat4) {
+    /// Strokes a polyline with a colour value per point, rather than a palette entry, blending
+    /// smoothly along each segment. Joins and caps take the colour of the point they are around.
+    /// When collinear runs are merged, the points that remain keep their own colours, and the ones
+    /// merged away are blended over. Nothing is drawn unless there is exactly one colour per point
+    pub fn draw_poly_line_gradient(
+        &mut self,
+        points: &[Vec2],
+        width: f32,
+        closed: bool,
+        colors: &[Vec4],
+    ) {
+        if !colors_match(points, colors) {
+            return;
+        }
+
+        let (points, colors) = if self.merge_collinear {
+            merge_collinear_indices(points, COLLINEAR_TOLERANCE)
+                .into_iter()
+                .map(|i| (points[i], colors[i]))
+                .unzip()
+        } else {
+            (points.to_vec(), colors.to_vec())
+        };
+
+        let vertices = polyline_to_triangles_with_sources(
+            &points,
+            width,
+            self.arc_tolerance,
+            closed,
+            CapStyle::Round,
+        );
+        self.vertices
+            .entry(Color::WHITE)
+            .or_default()
+            .extend(vertices.into_iter().map(|(pos, source)| Vertex {
+                pos,
+                color: colors[source],
+            }));
+    }
+
+    /// Strokes a polyline twice in the same batch: a wide, dim halo at `glow_width`, then the line
+    /// itself at `width` and full brightness on top. Since both layers share the batch colour,
+    /// this costs no extra draw calls.
+    pub fn draw_glow_line(
+        &mut self,
+        points: &[Vec2],
+        width: f32,
+        glow_width: f32,
+        closed: bool,
+        color: Color,
+    ) {
+        const GLOW_INTENSITY: f32 = 0.35;
+
+        let glow = self.stroke(
+            points,
+            glow_width,
+            closed,
+            LineStyle::Solid,
+            CapStyle::Round,
+        );
+        self.push_tinted(
+            color,
+            &glow,
+            vec4(GLOW_INTENSITY, GLOW_INTENSITY, GLOW_INTENSITY, 1.0),
+        );
+
+        let line = self.stroke(points, width, closed, LineStyle::Solid, CapStyle::Round);
+        self.push_tinted(color, &line, Vec4::ONE);
+    }
+
+    /// Fills a convex polygon, given its vertices in order around the outline
+    pub fn fill_convex(&mut self, points: &[Vec2], color: Color) {
+        if points.len() < 3 {
+            return;
+        }
+
+        let mut vertices = Vec::with_capacity((points.len() - 2) * 3);
+        for i in 1..points.len() - 1 {
+            vertices.extend([points[0], points[i], points[i + 1]]);
+        }
+        self.push_tinted(color, &vertices, Vec4::ONE);
+    }
+
+    /// Fills a simple polygon, convex or not, given its vertices in order around the outline. Either
+    /// winding works, since nothing is culled. Fewer than 3 points draws nothing
+    pub fn draw_polygon_filled(&mut self, points: &[Vec2], color: Color) {
+        if points.len() < 3 {
+            return;
+        }
+
+        let vertices = polygon_to_triangles(points);
+        self.push_tinted(color, &vertices, Vec4::ONE);
+    }
+
+    /// Draws an arrow from `from` to `to`: a stroked shaft of the given width, and a filled
+    /// triangular head `head_size` long pointing at `to`. Zero-length arrows draw nothing.
+    pub fn draw_arrow(&mut self, from: Vec2, to: Vec2, width: f32, head_size: f32, color: Color) {
+        let Some(dir) = (to - from).try_normalize() else {
+            return;
+        };
+
+        // never let the head overshoot the start of the arrow
+        let head_size = head_size.min(from.distance(to));
+        let base = to - dir * head_size;
+        let side = dir.perp() * head_size * 0.5;
+
+        self.draw_poly_line(&[from, base], width, false, color);
+        self.fill_convex(&[to, base + side, base - side], color);
+    }
+
+    /// Draws disconnected line segments (pairs of points) with a colour per point, which is
+    /// interpolated along each segment. Nothing is drawn unless there is exactly one colour per point
+    pub fn draw_lines_colored(&mut self, points: &[Vec2], colors: &[Vec4], width: f32) {
+        if !colors_match(points, colors) {
+            return;
+        }
+
+        let vertices = lines_to_triangles_with_sources(points, width);
+        self.vertices
+            .entry(Color::WHITE)
+            .or_default()
+            .extend(vertices.into_iter().map(|(pos, source)| Vertex {
+                pos,
+                color: colors[source],
+            }));
+    }
+
+    /// Fills a list of triangles, three points each, in an arbitrary colour
+    pub fn fill_triangles(&mut self, triangles: &[Vec2], color: Vec4) {
+        self.push_tinted(Color::WHITE, triangles, color);
+    }
+
+    /// Appends geometry to a batch, in draw order, with every vertex given the same `tint`
+    fn push_tinted(&mut self, color: Color, vertices: &[Vec2], tint: Vec4) {
+        self.vertices
+            .entry(color)
+            .or_default()
+            .extend(vertices.iter().map(|&pos| Vertex { pos, color: tint }));
+    }
+
+    /// Takes the vertex counts of everything rendered since the last call
+    pub fn take_stats(&mut self) -> ScribeStats {
+        std::mem::take(&mut self.stats)
+    }
+
+    /// Draws all the geometry queued since the last call, using the bound camera block
+    pub fn render(&mut self) {
         self.context.bind_vertex_array(Some(&self.vao));
         self.context.use_program(Some(&self.program));
 
         self.context
             .bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&self.buffer));
 
-        for (color, vertices) in &self.vertices {
-            unsafe {
-                let positions_array_buf_view =
-                    js_sys::Float32Array::view(reinterpret_cast_slice(&vertices));
+        self.context.enable(WebGl2RenderingContext::BLEND);
+        self.context.blend_func(
+            WebGl2RenderingContext::SRC_ALPHA,
+            WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
+        );
 
-                self.context.buffer_data_with_array_buffer_view(
-                    WebGl2RenderingContext::ARRAY_BUFFER,
-                    &positions_array_buf_view,
-                    WebGl2RenderingContext::STATIC_DRAW,
-                );
+        // strokes share a lot of vertices between their quads, joins and caps, so only upload
+        // each distinct vertex once, and draw every batch from its own range of indices
+        self.staging.clear();
+        self.indices.clear();
+        self.dedup.clear();
+        let mut ranges = Vec::with_capacity(self.vertices.len());
+        for (&color, vertices) in &self.vertices {
+            ranges.push((color, self.indices.len(), vertices.len()));
+            for vertex in vertices {
+                let index = *self.dedup.entry(VertexKey::new(vertex)).or_insert_with(|| {
+                    self.staging.push(*vertex);
+                    (self.staging.len() - 1) as u32
+                });
+                self.indices.push(index);
             }
+        }
+        self.stats.queued_vertices += self.indices.len();
+        self.stats.unique_vertices += self.staging.len();
+
+        if self.staging.len() > self.buffer_capacity {
+            // grow to the next power of two, so a slowly growing scene doesn't reallocate every frame
+            self.buffer_capacity = self.staging.len().next_power_of_two();
+            self.context.buffer_data_with_i32(
+                WebGl2RenderingContext::ARRAY_BUFFER,
+                (self.buffer_capacity * std::mem::size_of::<Vertex>()) as i32,
+                WebGl2RenderingContext::DYNAMIC_DRAW,
+            );
+        }
+
+        self.context.bind_buffer(
+            WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
+            Some(&self.index_buffer),
+        );
+        if self.indices.len() > self.index_capacity {
+            self.index_capacity = self.indices.len().next_power_of_two();
+            self.context.buffer_data_with_i32(
+                WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
+                (self.index_capacity * std::mem::size_of::<u32>()) as i32,
+                WebGl2RenderingContext::DYNAMIC_DRAW,
+            );
+        }
+
+        unsafe {
+            let positions_array_buf_view =
+                js_sys::Float32Array::view(reinterpret_cast_slice(&self.staging));
 
-            self.context.uniform_matrix4fv_with_f32_array(
-                self.context
-                    .get_uniform_location(&self.program, "transform")
-                    .as_ref(),
-                false,
-                transform.as_ref(),
+            self.context.buffer_sub_data_with_i32_and_array_buffer_view(
+                WebGl2RenderingContext::ARRAY_BUFFER,
+                0,
+                &positions_array_buf_view,
             );
+
+            let indices_array_buf_view = js_sys::Uint32Array::view(&self.indices);
+
+            self.context.buffer_sub_data_with_i32_and_array_buffer_view(
+                WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
+                0,
+                &indices_array_buf_view,
+            );
+        }
+
+        let batch_color = self
+            .context
+            .get_uniform_location(&self.program, "batch_color");
+        for (color, first, count) in ranges {
             self.context.uniform4fv_with_f32_array(
-                self.context
-                    .get_uniform_location(&self.program, "color")
-                    .as_ref(),
-                &color.to_gl().to_array(),
+                batch_color.as_ref(),
+                &self.palette[color.0 as usize].1.to_array(),
             );
 
-            self.context
-                .draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, vertices.len() as i32);
+            self.context.draw_elements_with_i32(
+                WebGl2RenderingContext::TRIANGLES,
+                count as i32,
+                WebGl2RenderingContext::UNSIGNED_INT,
+                (first * std::mem::size_of::<u32>()) as i32,
+            );
         }
 
+        // leave blending off for whatever draws next, as the rest of the renderers expect
+        self.context.disable(WebGl2RenderingContext::BLEND);
+
         self.vertices.clear();
     }
 }
+
+/// Whether there is a colour for every point, warning if not, since indexing past the colours
+/// would otherwise panic mid-frame
+fn colors_match<T>(points: &[Vec2], colors: &[T]) -> bool {
+    let matched = points.len() == colors.len();
+    if !matched {
+        log::warn!(
+            "{} colours for {} points, skipping the line",
+            colors.len(),
+            points.len()
+        );
+    }
+    matched
+}
+
+const SCRIBE_VS: &str = r##"#version 300 es
+
+layout(std140) uniform Camera {
+    mat4 view_projection;
+    float time;
+};
+
+in vec4 position;
+in vec4 color;
+
+out vec4 v_color;
+
+void main() {
+    v_color = color;
+    gl_Position = view_projection * vec4(position.xyz, 1.0);
+}
+"##;
+
+const SCRIBE_FS: &str = r##"#version 300 es
+
+precision highp float;
+
+uniform vec4 batch_color;
+
+in vec4 v_color;
+
+out vec4 outColor;
+
+void main() {
+    outColor = batch_color * v_color;
+}
+"##;
+
+#[cfg(test)]
+mod tests {
+    use glam::vec2;
+
+    use super::*;
+
+    fn key(pos: Vec2) -> VertexKey {
+        VertexKey::new(&Vertex {
+            pos,
+            color: Vec4::ONE,
+        })
+    }
+
+    #[test]
+    fn nearby_vertices_share_a_key() {
+        let p = vec2(12.5, -3.25);
+        assert_eq!(key(p), key(p + Vec2::splat(DEDUP_EPSILON * 0.1)));
+        assert_ne!(key(p), key(p + Vec2::splat(DEDUP_EPSILON * 2.0)));
+        assert_eq!(key(vec2(-DEDUP_EPSILON * 0.1, 0.0)), key(Vec2::ZERO));
+    }
+
+    #[test]
+    fn distant_vertices_keep_distinct_keys() {
+        // far enough out that snapping to an i32 would saturate
+        let far = vec2(1e6, -1e6);
+        assert_ne!(key(far), key(far + vec2(1.0, 0.0)));
+        assert_ne!(key(far), key(far + vec2(0.0, -1.0)));
+    }
+
+    #[test]
+    fn mismatched_colours_are_rejected() {
+        let points = [Vec2::ZERO, Vec2::X, Vec2::Y];
+        assert!(colors_match(&points, &[Vec4::ONE; 3]));
+        assert!(!colors_match(&points, &[Vec4::ONE; 2]));
+        assert!(!colors_match(&points, &[Color::WHITE; 4]));
+        assert!(colors_match::<Vec4>(&[], &[]));
+    }
+}
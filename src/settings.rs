@@ -0,0 +1,183 @@
+use crate::storage;
+
+const STORAGE_KEY: &str = "fathom.settings";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn starting_health(&self) -> usize {
+        match self {
+            Difficulty::Easy => 7,
+            Difficulty::Normal => 5,
+            Difficulty::Hard => 3,
+        }
+    }
+
+    fn next(&self) -> Self {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Easy,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    /// Parses the lowercase name of a difficulty, such as `"hard"`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+}
+
+/// The entries of the settings menu, in display order
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SettingsItem {
+    Bloom,
+    Crt,
+    FlatScreen,
+    Fxaa,
+    Sound,
+    ReducedMotion,
+    SmoothCamera,
+    Difficulty,
+}
+
+impl SettingsItem {
+    pub const ALL: [SettingsItem; 8] = [
+        SettingsItem::Bloom,
+        SettingsItem::Crt,
+        SettingsItem::FlatScreen,
+        SettingsItem::Fxaa,
+        SettingsItem::Sound,
+        SettingsItem::ReducedMotion,
+        SettingsItem::SmoothCamera,
+        SettingsItem::Difficulty,
+    ];
+}
+
+/// Player preferences, persisted to local storage
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub bloom: bool,
+    pub crt: bool,
+    /// Whether the CRT effect keeps the screen flat, for players who find the curvature
+    /// uncomfortable
+    pub flat_screen: bool,
+    /// Whether jagged edges are smoothed with FXAA
+    pub fxaa: bool,
+    pub sound: bool,
+    pub reduced_motion: bool,
+    /// Whether the camera eases after the ship, rather than being locked to it
+    pub smooth_camera: bool,
+    pub difficulty: Difficulty,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            bloom: true,
+            crt: true,
+            flat_screen: false,
+            fxaa: false,
+            sound: true,
+            reduced_motion: false,
+            smooth_camera: true,
+            difficulty: Difficulty::Normal,
+        }
+    }
+}
+
+impl Settings {
+    /// Loads saved settings, falling back to defaults for anything missing or unreadable
+    pub fn load() -> Self {
+        let mut settings = Self::default();
+
+        if let Some(saved) = storage::load(STORAGE_KEY) {
+            for entry in saved.split(';') {
+                match entry.split_once('=') {
+                    Some(("bloom", value)) => settings.bloom = value == "1",
+                    Some(("crt", value)) => settings.crt = value == "1",
+                    Some(("flat_screen", value)) => settings.flat_screen = value == "1",
+                    Some(("fxaa", value)) => settings.fxaa = value == "1",
+                    Some(("sound", value)) => settings.sound = value == "1",
+                    Some(("reduced_motion", value)) => settings.reduced_motion = value == "1",
+                    Some(("smooth_camera", value)) => settings.smooth_camera = value == "1",
+                    Some(("difficulty", value)) => {
+                        settings.difficulty =
+                            Difficulty::from_name(value).unwrap_or(settings.difficulty)
+                    }
+                    _ => log::warn!("ignoring unknown setting {entry:?}"),
+                }
+            }
+        }
+
+        settings
+    }
+
+    pub fn save(&self) {
+        storage::save(
+            STORAGE_KEY,
+            &format!(
+                "bloom={};crt={};flat_screen={};fxaa={};sound={};reduced_motion={};smooth_camera={};difficulty={}",
+                self.bloom as u8,
+                self.crt as u8,
+                self.flat_screen as u8,
+                self.fxaa as u8,
+                self.sound as u8,
+                self.reduced_motion as u8,
+                self.smooth_camera as u8,
+                self.difficulty.name()
+            ),
+        );
+    }
+
+    /// Flips a toggle, or cycles to the next option for multiple-choice settings
+    pub fn toggle(&mut self, item: SettingsItem) {
+        match item {
+            SettingsItem::Bloom => self.bloom = !self.bloom,
+            SettingsItem::Crt => self.crt = !self.crt,
+            SettingsItem::FlatScreen => self.flat_screen = !self.flat_screen,
+            SettingsItem::Fxaa => self.fxaa = !self.fxaa,
+            SettingsItem::Sound => self.sound = !self.sound,
+            SettingsItem::ReducedMotion => self.reduced_motion = !self.reduced_motion,
+            SettingsItem::SmoothCamera => self.smooth_camera = !self.smooth_camera,
+            SettingsItem::Difficulty => self.difficulty = self.difficulty.next(),
+        }
+    }
+
+    /// The menu label for an item, including its current value
+    pub fn label(&self, item: SettingsItem) -> String {
+        let on_off = |value: bool| if value { "on" } else { "off" };
+
+        match item {
+            SettingsItem::Bloom => format!("Bloom: {}", on_off(self.bloom)),
+            SettingsItem::Crt => format!("CRT: {}", on_off(self.crt)),
+            SettingsItem::FlatScreen => format!("Flat screen: {}", on_off(self.flat_screen)),
+            SettingsItem::Fxaa => format!("Anti-aliasing: {}", on_off(self.fxaa)),
+            SettingsItem::Sound => format!("Sound: {}", on_off(self.sound)),
+            SettingsItem::ReducedMotion => {
+                format!("Reduced motion: {}", on_off(self.reduced_motion))
+            }
+            SettingsItem::SmoothCamera => {
+                format!("Smooth camera: {}", on_off(self.smooth_camera))
+            }
+            SettingsItem::Difficulty => format!("Difficulty: {}", self.difficulty.name()),
+        }
+    }
+}
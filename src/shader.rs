@@ -1,28 +1,110 @@
-use glam::Mat4;
+use std::{cell::RefCell, collections::HashMap};
+
+use glam::{Mat4, Vec4};
 use web_sys::{WebGl2RenderingContext, WebGlProgram};
 
 use crate::{compile_shader, link_program, texture::Texture};
 
+thread_local! {
+    /// Programs linked by `precompile`, keyed by their sources, waiting to be claimed by
+    /// `Shader::new`
+    static PRECOMPILED: RefCell<HashMap<(String, String), WebGlProgram>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Compiles and links programs ahead of time, to be picked up by `Shader::new` with the same
+/// sources. Every program is submitted before any is checked, so browsers that compile in the
+/// background can overlap the work. Programs that fail are deleted, and left for `Shader::new`
+/// to compile again and report
+pub fn precompile(context: &WebGl2RenderingContext, sources: &[(&str, &str)]) {
+    let compile = |kind: u32, source: &str| {
+        let shader = context.create_shader(kind)?;
+        context.shader_source(&shader, source);
+        context.compile_shader(&shader);
+        Some(shader)
+    };
+
+    let programs: Vec<_> = sources
+        .iter()
+        .filter_map(|&(vertex, fragment)| {
+            let shaders = [
+                compile(WebGl2RenderingContext::VERTEX_SHADER, vertex)?,
+                compile(WebGl2RenderingContext::FRAGMENT_SHADER, fragment)?,
+            ];
+            let program = context.create_program()?;
+            for shader in &shaders {
+                context.attach_shader(&program, shader);
+            }
+            context.link_program(&program);
+            Some((vertex, fragment, program, shaders))
+        })
+        .collect();
+
+    PRECOMPILED.with(|precompiled| {
+        let mut precompiled = precompiled.borrow_mut();
+        for (vertex, fragment, program, shaders) in programs {
+            let linked = context
+                .get_program_parameter(&program, WebGl2RenderingContext::LINK_STATUS)
+                .as_bool()
+                .unwrap_or(false);
+
+            // a linked program keeps its own copy of the code, so the shaders can go either way
+            for shader in &shaders {
+                context.detach_shader(&program, shader);
+                context.delete_shader(Some(shader));
+            }
+
+            if linked {
+                precompiled.insert((vertex.to_string(), fragment.to_string()), program);
+            } else {
+                context.delete_program(Some(&program));
+            }
+        }
+    });
+}
+
 pub struct Shader {
     context: WebGl2RenderingContext,
     pub program: WebGlProgram,
 }
 
 impl Shader {
-    pub fn new(context: &WebGl2RenderingContext, vertex: &str, fragment: &str) -> Self {
+    /// Compiles and links a shader program, or claims one already built by `precompile`. Compile
+    /// errors include the offending source lines
+    pub fn new(
+        context: &WebGl2RenderingContext,
+        vertex: &str,
+        fragment: &str,
+    ) -> Result<Self, String> {
+        let precompiled = PRECOMPILED.with(|precompiled| {
+            precompiled
+                .borrow_mut()
+                .remove(&(vertex.to_string(), fragment.to_string()))
+        });
+        if let Some(program) = precompiled {
+            return Ok(Self {
+                context: context.clone(),
+                program,
+            });
+        }
+
         let program = link_program(
             context,
-            &compile_shader(context, WebGl2RenderingContext::VERTEX_SHADER, vertex)
-                .expect("compiled vertex shader"),
-            &compile_shader(context, WebGl2RenderingContext::FRAGMENT_SHADER, fragment)
-                .expect("compiled fragment shader"),
-        )
-        .expect("linked shader program");
-
-        Self {
+            &compile_shader(context, WebGl2RenderingContext::VERTEX_SHADER, vertex)?,
+            &compile_shader(context, WebGl2RenderingContext::FRAGMENT_SHADER, fragment)?,
+        )?;
+
+        Ok(Self {
             context: context.clone(),
             program,
-        }
+        })
+    }
+
+    /// Connects a uniform block declared in this program to a `UniformBlock` binding point
+    pub fn bind_uniform_block(&self, name: &str, binding: u32) {
+        let index = self.context.get_uniform_block_index(&self.program, name);
+        self.context
+            .uniform_block_binding(&self.program, index, binding);
     }
 
     pub fn bind_texture(&self, name: &str, unit: u32, texture: &Texture) {
@@ -50,6 +132,29 @@ impl Shader {
         );
     }
 
+    pub fn uniform1i(&self, name: &str, value: i32) {
+        self.context.use_program(Some(&self.program));
+        self.context.uniform1i(
+            self.context
+                .get_uniform_location(&self.program, name)
+                .as_ref(),
+            value,
+        );
+    }
+
+    pub fn uniform4f(&self, name: &str, value: Vec4) {
+        self.context.use_program(Some(&self.program));
+        self.context.uniform4f(
+            self.context
+                .get_uniform_location(&self.program, name)
+                .as_ref(),
+            value.x,
+            value.y,
+            value.z,
+            value.w,
+        );
+    }
+
     pub fn uniform_matrix4(&self, name: &str, value: Mat4) {
         self.context.use_program(Some(&self.program));
         self.context.uniform_matrix4fv_with_f32_array(
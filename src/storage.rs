@@ -0,0 +1,25 @@
+use crate::window;
+
+/// Reads a value previously saved to the browser's local storage
+pub fn load(key: &str) -> Option<String> {
+    window()
+        .local_storage()
+        .ok()
+        .flatten()?
+        .get_item(key)
+        .ok()
+        .flatten()
+}
+
+/// Saves a value to the browser's local storage. Failures (e.g. private browsing) are logged and
+/// otherwise ignored, since persistence is a convenience
+pub fn save(key: &str, value: &str) {
+    let Some(storage) = window().local_storage().ok().flatten() else {
+        log::warn!("local storage unavailable, not saving {key}");
+        return;
+    };
+
+    if let Err(err) = storage.set_item(key, value) {
+        log::warn!("failed to save {key}: {err:?}");
+    }
+}
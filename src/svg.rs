@@ -0,0 +1,48 @@
+use std::fmt::Write;
+
+use glam::Vec2;
+
+/// Writes contours traced from the cave as an SVG document covering `min` to `max` in world
+/// units. SVG's y axis points down, so the world is flipped to keep the shaft the right way up
+pub fn cave_svg(contours: &[Vec<Vec2>], seed: u32, min: Vec2, max: Vec2) -> String {
+    let size = max - min;
+    let to_svg = |p: Vec2| (p.x - min.x, max.y - p.y);
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {:.2} {:.2}" data-seed="{}" data-region="{:.2} {:.2} {:.2} {:.2}">"#,
+        size.x, size.y, seed, min.x, min.y, max.x, max.y
+    );
+    let _ = writeln!(
+        svg,
+        "<desc>Fathom cave with seed {}, from ({:.2}, {:.2}) to ({:.2}, {:.2})</desc>",
+        seed, min.x, min.y, max.x, max.y
+    );
+    let _ = writeln!(
+        svg,
+        r#"<g fill="none" stroke="black" stroke-width="1" stroke-linejoin="round">"#
+    );
+
+    for contour in contours {
+        let Some((first, rest)) = contour.split_first() else {
+            continue;
+        };
+
+        let (x, y) = to_svg(*first);
+        let mut d = format!("M{x:.2} {y:.2}");
+        for &p in rest {
+            let (x, y) = to_svg(p);
+            let _ = write!(d, " L{x:.2} {y:.2}");
+        }
+        // closed loops repeat their first point
+        if contour.len() > 2 && contour.last() == Some(first) {
+            d.push_str(" Z");
+        }
+
+        let _ = writeln!(svg, r#"<path d="{d}"/>"#);
+    }
+
+    svg.push_str("</g>\n</svg>\n");
+    svg
+}
@@ -1,11 +1,16 @@
-use glam::{Mat4, Vec2, vec2};
-use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlVertexArrayObject};
+use glam::{Mat3, Mat4, Vec2, Vec4, vec2};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{
+    WebGl2RenderingContext, WebGlBuffer, WebGlFramebuffer, WebGlTexture, WebGlVertexArrayObject,
+};
 
 use crate::{
     font::{Character, Font, Segment},
     reinterpret_cast_slice,
+    render_target::RenderTarget,
     shader::Shader,
     texture::Texture,
+    uniform_block::{CAMERA_BINDING, CAMERA_BLOCK_SIZE, UniformBlock, camera_block},
 };
 
 #[repr(C)]
@@ -15,18 +20,135 @@ struct Vertex {
     uv: Vec2,
     segment_offset: u16,
     len: u16,
+    color: Vec4,
+    /// Extra softening of the glyph edges, in uv units
+    blur: Vec2,
 }
 
+/// Identifies a font registered with `Text::add_font`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FontId(usize);
+
+#[derive(Clone, Copy)]
 pub enum Align {
     Left,
     Center,
     Right,
 }
 
+/// How `draw_block` sets out its lines
+#[derive(Clone, Copy)]
+pub struct BlockLayout {
+    pub font_size: f32,
+    /// World units between one line and the next
+    pub line_gap: f32,
+    /// Applied to each line separately
+    pub align: Align,
+}
+
+/// Glyph quads are dilated by this much in world units, so baked labels need the same margin
+const DILATE: Vec2 = vec2(0.5, 0.5);
+
+/// Vertices are addressed by u16 indices
+const MAX_VERTICES: usize = u16::MAX as usize + 1;
+
+/// Smaller positive font sizes are drawn at this size, so glyph uvs can't blow up to infinity
+const MIN_FONT_SIZE: f32 = 0.01;
+
+/// The smallest MAX_TEXTURE_SIZE WebGL2 guarantees, for when the query fails
+const MIN_MAX_TEXTURE_SIZE: f64 = 2048.0;
+
+/// A string rendered once into its own texture, so static labels can be redrawn as a single quad
+/// instead of re-evaluating every bezier each frame
+pub struct BakedLabel {
+    target: RenderTarget,
+    /// Size in world units, including the margin around the glyphs
+    size: Vec2,
+    pixels_per_unit: f32,
+}
+
+impl BakedLabel {
+    /// The pixel density the label was baked at. Rebake when this no longer matches the screen
+    pub fn pixels_per_unit(&self) -> f32 {
+        self.pixels_per_unit
+    }
+}
+
+/// A soft copy of each string drawn beneath it, to keep text legible over busy backgrounds
+#[derive(Clone, Copy, Debug)]
+struct Shadow {
+    offset: Vec2,
+    style: GlyphStyle,
+}
+
+/// How one pass over a string is painted
+#[derive(Clone, Copy, Debug)]
+struct GlyphStyle {
+    color: Vec4,
+    /// Softening of the glyph edges, in world units
+    blur: f32,
+}
+
+struct BakedQuad {
+    texture: WebGlTexture,
+    min: Vec2,
+    max: Vec2,
+}
+
+/// The framebuffer, viewport and camera block in use before baking, so they can be put back
+struct SavedBindings {
+    framebuffer: Option<WebGlFramebuffer>,
+    viewport: Vec<i32>,
+    camera: Option<WebGlBuffer>,
+}
+
+impl SavedBindings {
+    fn capture(context: &WebGl2RenderingContext) -> Result<Self, JsValue> {
+        let framebuffer = context
+            .get_parameter(WebGl2RenderingContext::FRAMEBUFFER_BINDING)?
+            .dyn_into()
+            .ok();
+        let viewport =
+            js_sys::Int32Array::from(context.get_parameter(WebGl2RenderingContext::VIEWPORT)?)
+                .to_vec();
+        let camera = context
+            .get_indexed_parameter(
+                WebGl2RenderingContext::UNIFORM_BUFFER_BINDING,
+                CAMERA_BINDING,
+            )?
+            .dyn_into()
+            .ok();
+
+        Ok(Self {
+            framebuffer,
+            viewport,
+            camera,
+        })
+    }
+
+    fn restore(&self, context: &WebGl2RenderingContext) {
+        context.bind_framebuffer(
+            WebGl2RenderingContext::FRAMEBUFFER,
+            self.framebuffer.as_ref(),
+        );
+        if let [x, y, w, h] = self.viewport[..] {
+            context.viewport(x, y, w, h);
+        }
+        context.bind_buffer_base(
+            WebGl2RenderingContext::UNIFORM_BUFFER,
+            CAMERA_BINDING,
+            self.camera.as_ref(),
+        );
+    }
+}
+
 // Implements https://www.shadertoy.com/view/sdXBDs for anti-aliased GPU-evaluated quadratic bezier text
+//
+// Any number of fonts can be registered, and text in all of them shares the same shader, path
+// texture and buffers, so mixing fonts in a frame costs no extra draw calls
 pub struct Text {
     context: WebGl2RenderingContext,
-    font: Font,
+    fonts: Vec<Font>,
     segments: Vec<Segment>,
     vertices: Vec<Vertex>,
     indices: Vec<u16>,
@@ -35,10 +157,38 @@ pub struct Text {
     vertex_buffer: WebGlBuffer,
     index_buffer: WebGlBuffer,
     vao: WebGlVertexArrayObject,
+    color: Vec4,
+    shadow: Option<Shadow>,
+    /// Fixed advance for every glyph, in ems, or `None` to use each glyph's own
+    monospace_advance: Option<f32>,
+    pixels_per_unit: f32,
+    bake_camera: UniformBlock,
+    baked_shader: Shader,
+    baked_quads: Vec<BakedQuad>,
+    stats: TextStats,
+    max_segments: usize,
+}
+
+/// The most text queued in any single `render` so far, to help tune `Text::reserve`
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TextStats {
+    pub peak_segments: usize,
+    pub peak_vertices: usize,
+    pub peak_indices: usize,
 }
 
 impl Text {
-    pub fn new(context: &WebGl2RenderingContext, font: Font) -> Self {
+    /// Sources of every program text rendering uses, for `precompile_shaders`
+    pub const SHADERS: &[(&str, &str)] = &[(TEXT_VS, TEXT_FS), (BAKED_VS, BAKED_FS)];
+
+    pub fn new(context: &WebGl2RenderingContext) -> Self {
+        let max_texture_size = context
+            .get_parameter(WebGl2RenderingContext::MAX_TEXTURE_SIZE)
+            .ok()
+            .and_then(|size| size.as_f64())
+            .unwrap_or(MIN_MAX_TEXTURE_SIZE);
+        let max_segments = (u16::MAX as usize).min(max_texture_size as usize);
+
         let texture = Texture::new(
             context,
             1,
@@ -47,99 +197,8 @@ impl Text {
             WebGl2RenderingContext::FLOAT,
         );
 
-        let shader = Shader::new(
-            context,
-            r#"#version 300 es
-        layout(location=0) in vec2 position;
-        layout(location=1) in vec2 uv;
-        layout(location=2) in uvec2 path;
-
-        uniform mat4 MVPmatrix;
-
-        out vec2 v_uv;
-        flat out uvec2 v_path;
-    
-        void main(void) {
-            v_uv = uv;
-            v_path = path;
-            gl_Position = MVPmatrix * vec4(position, 0.0, 1.0);
-        }
-        "#,
-            r#"#version 300 es
-        precision highp float;
-        
-        uniform sampler2D pathSampler;
-
-        layout(location=0) out vec4 fragColor;
-        
-        in vec2 v_uv;
-        flat in uvec2 v_path;
-
-        // evaluate only the x coordinate of the bezier specified by the control points
-        float eval_bezier_x(float t, vec2 p1, vec2 p2, vec2 p3) {
-            float s = 1.0 - t;
-            return s * s * p1.x + 2.0 * s * t * p2.x + t * t * p3.x;
-        }
-
-        // returns the x coordinate of the intersection beteen a horizontal line at y=b, and the
-        // bezier specified by the control points.
-        float bezier_t_intersect_horizontal_line(float y, vec2 p1, vec2 p2, vec2 p3) {    
-            float a = p1.y - 2.0*p2.y + p3.y;
-
-            
-            // bezier is a straight line, so we can calculate the intersection directly
-            if (abs(a) < 0.0002) {
-                return (y - p1.y) / (p3.y - p1.y);
-            }
-
-            float q = p1.y - p2.y + sqrt(y*a + p2.y*p2.y - p1.y*p3.y);
-            float ta = q / a;
-            float tb = (p1.y - y) / q;
-            return (0.0 <= ta && ta <= 1.0) ? ta : tb;
-        }
-
-        void main(void) {
-            vec2 uv = v_uv;
-
-            vec2 ddx = dFdx(uv);
-            vec2 ddy = dFdy(uv);
-            vec2 pixel_footprint = sqrt(ddx * ddx + ddy * ddy);
-        
-            float coverage = 0.0;
-
-            for (uint i = v_path.x; i < v_path.x + v_path.y; ++i) {
-                vec3 v0 = texelFetch(pathSampler, ivec2(0, i), 0).rgb;
-                vec3 v1 = texelFetch(pathSampler, ivec2(1, i), 0).rgb;
-
-                vec2 p1 = v0.xy;
-                vec2 p2 = vec2(v0.z, v1.x);
-                vec2 p3 = v1.yz;
-
-                // compute the overlap between pixel footprint and bezier in the y-axis
-                vec2 footprint_y = uv.y + vec2(-0.5, 0.5) * pixel_footprint.y;
-                vec2 window_y = clamp(vec2(p3.y, p1.y), footprint_y.x, footprint_y.y);
-                float overlap_y = (window_y.y - window_y.x) / pixel_footprint.y;
-
-                // no overlap, we're done here
-                if (overlap_y != 0.0) {
-                    // grab the intersection in terms of t as well as x
-                    float t = bezier_t_intersect_horizontal_line(0.5 * (window_y.x + window_y.y), p1, p2, p3);
-                    float x = eval_bezier_x(t, p1, p2, p3);
-
-                    // use the tangent at t to estimate overlap in the x-axis 
-                    vec2 tangent = mix(p2 - p1, p3 - p2, t);
-                    float f = ((x - uv.x) * abs(tangent.y)) / length(pixel_footprint * tangent.yx);
-                    float overlap_x = clamp(0.5 + 0.7 * f, 0.0, 1.0);
-
-                    // sum up the overlap from each curve
-                    coverage += overlap_x * overlap_y;
-                }
-            }
-
-            fragColor = vec4(sqrt(coverage));
-        }
-        "#,
-        );
+        let shader = Shader::new(context, TEXT_VS, TEXT_FS).expect("text shader to compile");
+        shader.bind_uniform_block("Camera", CAMERA_BINDING);
         context.use_program(Some(&shader.program));
 
         let vao = context
@@ -190,6 +249,28 @@ impl Text {
         );
         context.enable_vertex_attrib_array(path_attribute_location as u32);
 
+        let color_attribute_location: i32 = context.get_attrib_location(&shader.program, "color");
+        context.vertex_attrib_pointer_with_i32(
+            color_attribute_location as u32,
+            4,
+            WebGl2RenderingContext::FLOAT,
+            false,
+            std::mem::size_of::<Vertex>() as i32,
+            std::mem::offset_of!(Vertex, color) as i32,
+        );
+        context.enable_vertex_attrib_array(color_attribute_location as u32);
+
+        let blur_attribute_location: i32 = context.get_attrib_location(&shader.program, "blur");
+        context.vertex_attrib_pointer_with_i32(
+            blur_attribute_location as u32,
+            2,
+            WebGl2RenderingContext::FLOAT,
+            false,
+            std::mem::size_of::<Vertex>() as i32,
+            std::mem::offset_of!(Vertex, blur) as i32,
+        );
+        context.enable_vertex_attrib_array(blur_attribute_location as u32);
+
         context.bind_buffer(
             WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
             Some(&index_buffer),
@@ -202,9 +283,14 @@ impl Text {
 
         context.bind_vertex_array(None);
 
+        // baked labels are drawn as a single quad, with corners generated from the vertex id
+        let baked_shader =
+            Shader::new(context, BAKED_VS, BAKED_FS).expect("baked label shader to compile");
+        baked_shader.bind_uniform_block("Camera", CAMERA_BINDING);
+
         Self {
             context: context.clone(),
-            font,
+            fonts: vec![],
             segments: vec![],
             vertices: vec![],
             indices: vec![],
@@ -213,78 +299,343 @@ impl Text {
             vertex_buffer,
             index_buffer,
             vao,
+            color: Vec4::ONE,
+            shadow: None,
+            monospace_advance: None,
+            pixels_per_unit: 1.0,
+            bake_camera: UniformBlock::new(context, CAMERA_BLOCK_SIZE),
+            baked_shader,
+            baked_quads: vec![],
+            stats: TextStats::default(),
+            max_segments,
         }
     }
 
-    pub fn measure(&self, font_size: f32, text: &str) -> Vec2 {
-        let scale = font_size / self.font.units_per_em;
+    /// Grows the queues up front to fit `glyphs` more characters, averaging `segments_per_glyph`
+    /// bezier segments each. The queues keep their capacity between frames, so reserving once
+    /// for the busiest frame avoids reallocating during play
+    pub fn reserve(&mut self, glyphs: usize, segments_per_glyph: usize) {
+        self.segments.reserve(glyphs * segments_per_glyph);
+        self.vertices.reserve(glyphs * 4);
+        self.indices.reserve(glyphs * 6);
+    }
 
-        vec2(self.compute_string_width(text), self.font.height) * scale
+    pub fn stats(&self) -> TextStats {
+        self.stats
     }
 
-    pub fn draw(&mut self, x: f32, y: f32, font_size: f32, align: Align, text: &str) {
-        let width = self.compute_string_width(text);
+    /// Sets the colour of text drawn from now on
+    pub fn set_color(&mut self, color: Vec4) {
+        self.color = color;
+    }
 
-        let scale = font_size / self.font.units_per_em;
-        let descent = self.font.descender as f32 * scale;
+    /// Draws a shadow beneath text drawn from now on, shifted by `offset` and with its edges
+    /// softened over `blur`, both in world units
+    pub fn set_shadow(&mut self, offset: Vec2, color: Vec4, blur: f32) {
+        self.shadow = Some(Shadow {
+            offset,
+            style: GlyphStyle {
+                color,
+                blur: blur.max(0.0),
+            },
+        });
+    }
 
-        let offset_x = match align {
-            Align::Left => 0.0,
-            Align::Center => -width * scale / 2.0,
-            Align::Right => -width * scale,
+    /// Stops drawing shadows beneath text
+    pub fn clear_shadow(&mut self) {
+        self.shadow = None;
+    }
+
+    /// Lays out text drawn or measured from now on with every glyph advancing by `advance` ems,
+    /// centred in its cell, so columns of changing digits stay aligned. `None` goes back to each
+    /// glyph's natural advance
+    pub fn set_monospace_advance(&mut self, advance: Option<f32>) {
+        self.monospace_advance = advance;
+    }
+
+    /// Sets how many framebuffer pixels cover one world unit, which decides the resolution of
+    /// labels baked from now on
+    pub fn set_pixels_per_unit(&mut self, pixels_per_unit: f32) {
+        self.pixels_per_unit = pixels_per_unit;
+    }
+
+    pub fn add_font(&mut self, font: Font) -> FontId {
+        self.fonts.push(font);
+        FontId(self.fonts.len() - 1)
+    }
+
+    /// Loads any glyphs `text` needs that `font` hasn't loaded yet. Returns whether the font has
+    /// every character; any it lacks are skipped when drawing
+    pub fn ensure_glyphs(&mut self, font: FontId, text: &str) -> bool {
+        self.fonts[font.0].ensure_glyphs(text)
+    }
+
+    /// Every character `font` can currently draw
+    pub fn loaded_chars(&self, font: FontId) -> impl Iterator<Item = char> + '_ {
+        self.fonts[font.0].loaded_chars()
+    }
+
+    pub fn measure(&self, font: FontId, font_size: f32, text: &str) -> Vec2 {
+        let Some(font_size) = checked_font_size(font_size) else {
+            return Vec2::ZERO;
+        };
+        let font = &self.fonts[font.0];
+        let scale = font_size / font.units_per_em;
+
+        vec2(
+            string_width(font, self.monospace_advance, text),
+            font.height,
+        ) * scale
+    }
+
+    /// Size of a block of text split into lines at each `\n`, as drawn by `draw_block` with the
+    /// same layout. Every line counts towards the height, including empty ones at the end
+    pub fn measure_block(&self, font: FontId, layout: BlockLayout, text: &str) -> Vec2 {
+        let BlockLayout {
+            font_size,
+            line_gap,
+            ..
+        } = layout;
+        let mut size = Vec2::ZERO;
+        for (i, line) in text.split('\n').enumerate() {
+            let line_size = self.measure(font, font_size, line);
+            size.x = size.x.max(line_size.x);
+            size.y = self.line_y(font, font_size, line_gap, i) + line_size.y;
+        }
+        size
+    }
+
+    /// Draws text split into lines at each `\n`, set out by `layout`. Unlike `draw`, `y` is the
+    /// top of the block, which extends downwards
+    pub fn draw_block(&mut self, font: FontId, x: f32, y: f32, layout: BlockLayout, text: &str) {
+        let BlockLayout {
+            font_size,
+            line_gap,
+            align,
+        } = layout;
+        let line_height = self.measure(font, font_size, "").y;
+        for (i, line) in text.split('\n').enumerate() {
+            let top = y - self.line_y(font, font_size, line_gap, i);
+            self.draw(font, x, top - line_height, font_size, align, line);
+        }
+    }
+
+    /// Offset from the top of a block to the top of its `line`th line
+    fn line_y(&self, font: FontId, font_size: f32, line_gap: f32, line: usize) -> f32 {
+        let line_height = self.measure(font, font_size, "").y;
+        line as f32 * (line_height + line_gap)
+    }
+
+    /// Height of the baseline above the `y` passed to `draw`, which is the bottom of the descenders
+    pub fn baseline_offset(&self, font: FontId, font_size: f32) -> f32 {
+        let font = &self.fonts[font.0];
+        let font_size = checked_font_size(font_size).unwrap_or(0.0);
+        -font.descender * font_size / font.units_per_em
+    }
+
+    /// Height of capital letters above the baseline
+    pub fn cap_height(&self, font: FontId, font_size: f32) -> f32 {
+        let font = &self.fonts[font.0];
+        let font_size = checked_font_size(font_size).unwrap_or(0.0);
+        font.cap_height * font_size / font.units_per_em
+    }
+
+    /// Height of lowercase letters above the baseline, for centring mixed-case text optically
+    pub fn x_height(&self, font: FontId, font_size: f32) -> f32 {
+        let font = &self.fonts[font.0];
+        let font_size = checked_font_size(font_size).unwrap_or(0.0);
+        font.x_height * font_size / font.units_per_em
+    }
+
+    /// Finds the insertion point nearest to `local_x`, measured from the left edge of `text` as
+    /// drawn with `Align::Left`. Returns a byte offset into `text`, so it can be passed straight
+    /// to `String::insert`. Positions before the first glyph give 0, and past the last give
+    /// `text.len()`
+    pub fn caret_index_at(&self, font: FontId, font_size: f32, text: &str, local_x: f32) -> usize {
+        let Some(font_size) = checked_font_size(font_size) else {
+            return 0;
         };
-        let mut offset = vec2(x + offset_x, y - descent);
-
-        for c in text.chars() {
-            if let Some(Character { advance, path }) = self.font.chars.get(&c) {
-                let segment_offset = self.segments.len() as u16;
-                let len = path.segments.len() as u16;
-
-                self.segments.extend(path.segments.iter());
-
-                let i = self.vertices.len() as u16;
-                self.indices.extend([i, i + 1, i + 2, i, i + 2, i + 3]);
-
-                const DILATE: Vec2 = vec2(0.5, 0.5);
-
-                let p = offset + path.offset * scale - DILATE;
-                let q = offset + (path.offset + path.size) * scale + DILATE;
-
-                let d = DILATE / (path.size * scale);
-
-                self.vertices.extend(&[
-                    Vertex {
-                        pos: p,
-                        uv: vec2(-d.x, -d.y),
-                        segment_offset,
-                        len,
-                    },
-                    Vertex {
-                        pos: vec2(q.x, p.y),
-                        uv: vec2(1.0 + d.x, -d.y),
-                        segment_offset,
-                        len,
-                    },
-                    Vertex {
-                        pos: q,
-                        uv: vec2(1.0 + d.x, 1.0 + d.y),
-                        segment_offset,
-                        len,
-                    },
-                    Vertex {
-                        pos: vec2(p.x, q.y),
-                        uv: vec2(-d.x, 1.0 + d.y),
-                        segment_offset,
-                        len,
-                    },
-                ]);
-
-                offset.x += *advance * scale;
+        let font = &self.fonts[font.0];
+        let scale = font_size / font.units_per_em;
+
+        let mut x = 0.0;
+        for (i, c) in text.char_indices() {
+            let advance = font
+                .chars
+                .get(&c)
+                .map(|q| cell_advance(font, self.monospace_advance, q.advance))
+                .unwrap_or(0.0)
+                * scale;
+            // the caret goes before this glyph if we're left of its midpoint
+            if local_x < x + advance / 2.0 {
+                return i;
+            }
+            x += advance;
+        }
+
+        text.len()
+    }
+
+    pub fn draw(&mut self, font: FontId, x: f32, y: f32, font_size: f32, align: Align, text: &str) {
+        self.draw_with_transform(
+            font,
+            Mat3::from_translation(vec2(x, y)),
+            font_size,
+            align,
+            text,
+        );
+    }
+
+    /// Like `draw`, but lays the text out around the origin and then applies `transform` to the
+    /// glyph quads, so text can be rotated or scaled. Alignment is applied before the transform.
+    /// Sizes of zero or less draw nothing, as `layout_glyphs` places no glyphs for them
+    pub fn draw_with_transform(
+        &mut self,
+        font: FontId,
+        transform: Mat3,
+        font_size: f32,
+        align: Align,
+        text: &str,
+    ) {
+        // the whole shadow goes first, so it can't overlap glyphs earlier in the string
+        if let Some(shadow) = self.shadow {
+            self.queue_glyphs(
+                font,
+                Mat3::from_translation(shadow.offset) * transform,
+                font_size,
+                align,
+                text,
+                shadow.style,
+            );
+        }
+
+        let style = GlyphStyle {
+            color: self.color,
+            blur: 0.0,
+        };
+        self.queue_glyphs(font, transform, font_size, align, text, style);
+    }
+
+    /// Lays out a string and queues a quad for each glyph, painted in `style`
+    fn queue_glyphs(
+        &mut self,
+        font: FontId,
+        transform: Mat3,
+        font_size: f32,
+        align: Align,
+        text: &str,
+        style: GlyphStyle,
+    ) {
+        let glyphs = layout_glyphs(
+            &self.fonts[font.0],
+            self.monospace_advance,
+            transform,
+            font_size,
+            align,
+            text,
+            style,
+        );
+        self.vertices.reserve(glyphs.len() * 4);
+        self.indices.reserve(glyphs.len() * 6);
+
+        for PlacedGlyph { c, vertices } in glyphs {
+            let glyph_segments = self.fonts[font.0].chars[&c].path.segments.len();
+            if batch_full(
+                self.segments.len(),
+                self.vertices.len(),
+                glyph_segments,
+                self.max_segments,
+            ) {
+                self.render();
             }
+
+            let path = &self.fonts[font.0].chars[&c].path;
+            let segment_offset = self.segments.len() as u16;
+            let len = path.segments.len() as u16;
+            self.segments.extend(path.segments.iter());
+
+            let i = self.vertices.len() as u16;
+            self.indices.extend([i, i + 1, i + 2, i, i + 2, i + 3]);
+            self.vertices.extend(vertices.map(|vertex| Vertex {
+                segment_offset,
+                len,
+                ..vertex
+            }));
         }
     }
 
-    pub fn render(&mut self, transform: Mat4) {
+    /// Renders a string once into its own texture, at the density set by `set_pixels_per_unit`.
+    /// Anything already queued is left for the next `render`
+    pub fn bake(
+        &mut self,
+        font: FontId,
+        font_size: f32,
+        text: &str,
+        color: Vec4,
+    ) -> Result<BakedLabel, JsValue> {
+        let size = self.measure(font, font_size, text) + DILATE * 2.0;
+        let w = (size.x * self.pixels_per_unit).ceil().max(1.0) as i32;
+        let h = (size.y * self.pixels_per_unit).ceil().max(1.0) as i32;
+        // round up to whole pixels, so texels land exactly on screen pixels
+        let size = vec2(w as f32, h as f32) / self.pixels_per_unit;
+
+        let target = RenderTarget::new(&self.context, w, h)?;
+        target.texture.set_filter(WebGl2RenderingContext::LINEAR);
+
+        let saved = SavedBindings::capture(&self.context)?;
+        let segments = std::mem::take(&mut self.segments);
+        let vertices = std::mem::take(&mut self.vertices);
+        let indices = std::mem::take(&mut self.indices);
+        let baked_quads = std::mem::take(&mut self.baked_quads);
+        let previous_color = std::mem::replace(&mut self.color, color);
+        // the label is sized to fit the text alone
+        let previous_shadow = self.shadow.take();
+
+        target.bind();
+        self.context.clear_color(0.0, 0.0, 0.0, 0.0);
+        self.context.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
+
+        let projection = Mat4::orthographic_rh_gl(0.0, size.x, 0.0, size.y, -1.0, 1.0);
+        self.bake_camera.write(&camera_block(projection, 0.0));
+        self.bake_camera.bind_base(CAMERA_BINDING);
+
+        self.draw(font, DILATE.x, DILATE.y, font_size, Align::Left, text);
+        self.render();
+
+        self.segments = segments;
+        self.vertices = vertices;
+        self.indices = indices;
+        self.baked_quads = baked_quads;
+        self.color = previous_color;
+        self.shadow = previous_shadow;
+        saved.restore(&self.context);
+
+        Ok(BakedLabel {
+            target,
+            size,
+            pixels_per_unit: self.pixels_per_unit,
+        })
+    }
+
+    /// Queues a baked label, positioned the same way `draw` would position its text
+    pub fn draw_baked(&mut self, label: &BakedLabel, x: f32, y: f32, align: Align) {
+        let text_width = label.size.x - DILATE.x * 2.0;
+        let offset_x = match align {
+            Align::Left => 0.0,
+            Align::Center => -text_width / 2.0,
+            Align::Right => -text_width,
+        };
+        let min = vec2(x + offset_x, y) - DILATE;
+
+        self.baked_quads.push(BakedQuad {
+            texture: label.target.texture.texture.clone(),
+            min,
+            max: min + label.size,
+        });
+    }
+
+    /// Draws all the text queued since the last call, using the bound camera block
+    pub fn render(&mut self) {
         // log::info!("segments size {}", self.segments.len() * std::mem::size_of::<Segment>());
         self.texture.write(
             2,
@@ -315,11 +666,10 @@ impl Text {
         self.context.use_program(Some(&self.shader.program));
 
         self.shader.bind_texture("pathSampler", 0, &self.texture);
-        self.shader.uniform_matrix4("MVPmatrix", transform);
 
         self.context.enable(WebGl2RenderingContext::BLEND);
         self.context.blend_func(
-            WebGl2RenderingContext::SRC_ALPHA,
+            WebGl2RenderingContext::ONE,
             WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
         );
 
@@ -330,18 +680,383 @@ impl Text {
             0,
         );
 
+        if !self.baked_quads.is_empty() {
+            self.context.bind_vertex_array(None);
+            self.context.use_program(Some(&self.baked_shader.program));
+            self.context
+                .active_texture(WebGl2RenderingContext::TEXTURE0);
+            self.context.uniform1i(
+                self.context
+                    .get_uniform_location(&self.baked_shader.program, "labelSampler")
+                    .as_ref(),
+                0,
+            );
+
+            for quad in self.baked_quads.drain(..) {
+                self.context
+                    .bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&quad.texture));
+                self.baked_shader
+                    .uniform4f("rect", quad.min.extend(quad.max.x).extend(quad.max.y));
+                self.context
+                    .draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+            }
+        }
+
         self.context.disable(WebGl2RenderingContext::BLEND);
 
         // log::info!("text with {} segments, {} vertices, and {} indices", self.segments.len(), self.vertices.len(), self.indices.len());
 
+        self.stats.peak_segments = self.stats.peak_segments.max(self.segments.len());
+        self.stats.peak_vertices = self.stats.peak_vertices.max(self.vertices.len());
+        self.stats.peak_indices = self.stats.peak_indices.max(self.indices.len());
+
         self.segments.clear();
         self.vertices.clear();
         self.indices.clear();
     }
+}
+
+/// Clamps tiny sizes up to `MIN_FONT_SIZE`, or gives `None` for sizes that shouldn't draw at all
+fn checked_font_size(font_size: f32) -> Option<f32> {
+    (font_size > 0.0 && font_size.is_finite()).then(|| font_size.max(MIN_FONT_SIZE))
+}
+
+/// Width of `text` in font units, before scaling to the font size
+fn string_width(font: &Font, monospace_advance: Option<f32>, text: &str) -> f32 {
+    text.chars()
+        .map(|c| {
+            font.chars
+                .get(&c)
+                .map(|q| cell_advance(font, monospace_advance, q.advance))
+                .unwrap_or(0.0)
+        })
+        .sum()
+}
+
+/// How far a glyph with the given natural advance moves the pen, in font units, given a fixed
+/// advance in ems for monospaced text
+fn cell_advance(font: &Font, monospace_advance: Option<f32>, advance: f32) -> f32 {
+    monospace_advance.map_or(advance, |em| em * font.units_per_em)
+}
+
+/// A glyph placed by `layout_glyphs`, whose vertices are yet to be pointed at its curves
+struct PlacedGlyph {
+    c: char,
+    vertices: [Vertex; 4],
+}
+
+/// Lays out `text` and builds a quad for each glyph with an outline, painted in `style`. Sizes
+/// that shouldn't draw at all, such as zero or negative ones, place nothing
+fn layout_glyphs(
+    font: &Font,
+    monospace_advance: Option<f32>,
+    transform: Mat3,
+    font_size: f32,
+    align: Align,
+    text: &str,
+    GlyphStyle { color, blur }: GlyphStyle,
+) -> Vec<PlacedGlyph> {
+    let Some(font_size) = checked_font_size(font_size) else {
+        return vec![];
+    };
+
+    let width = string_width(font, monospace_advance, text);
+    let scale = font_size / font.units_per_em;
+    let descent = font.descender * scale;
+
+    let offset_x = match align {
+        Align::Left => 0.0,
+        Align::Center => -width * scale / 2.0,
+        Align::Right => -width * scale,
+    };
+    let mut pen = vec2(offset_x, -descent);
+
+    let mut glyphs = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        let Some(Character { advance, path }) = font.chars.get(&c) else {
+            continue;
+        };
+
+        // monospaced glyphs sit in the middle of their cell
+        let cell = cell_advance(font, monospace_advance, *advance);
+        let offset = pen + vec2((cell - advance) * 0.5 * scale, 0.0);
+        pen.x += cell * scale;
+
+        // whitespace has nothing to draw, and its empty box would divide by zero below
+        if path.segments.is_empty() {
+            continue;
+        }
+
+        // blurred edges spread further, so need a bigger quad to fit
+        let dilate = DILATE + Vec2::splat(blur);
+        let p = offset + path.offset * scale - dilate;
+        let q = offset + (path.offset + path.size) * scale + dilate;
+
+        let d = dilate / (path.size * scale);
+        let blur = Vec2::splat(blur) / (path.size * scale);
+
+        let vertex = |pos: Vec2, uv: Vec2| Vertex {
+            pos: transform.transform_point2(pos),
+            uv,
+            segment_offset: 0,
+            len: 0,
+            color,
+            blur,
+        };
+        glyphs.push(PlacedGlyph {
+            c,
+            vertices: [
+                vertex(p, vec2(-d.x, -d.y)),
+                vertex(vec2(q.x, p.y), vec2(1.0 + d.x, -d.y)),
+                vertex(q, vec2(1.0 + d.x, 1.0 + d.y)),
+                vertex(vec2(p.x, q.y), vec2(-d.x, 1.0 + d.y)),
+            ],
+        });
+    }
+    glyphs
+}
+
+/// Whether a glyph of `glyph_segments` would overflow the queued batch, so what's queued must be
+/// drawn first. Segment offsets and vertex indices are packed as u16, and each segment takes a
+/// row of the path texture, of which there are `max_segments`
+fn batch_full(
+    segments: usize,
+    vertices: usize,
+    glyph_segments: usize,
+    max_segments: usize,
+) -> bool {
+    segments + glyph_segments > max_segments || vertices + 4 > MAX_VERTICES
+}
+
+const TEXT_VS: &str = r#"#version 300 es
+layout(location=0) in vec2 position;
+layout(location=1) in vec2 uv;
+layout(location=2) in uvec2 path;
+layout(location=3) in vec4 color;
+layout(location=4) in vec2 blur;
+
+layout(std140) uniform Camera {
+    mat4 view_projection;
+    float time;
+};
+
+out vec2 v_uv;
+flat out uvec2 v_path;
+flat out vec4 v_color;
+flat out vec2 v_blur;
+
+void main(void) {
+    v_uv = uv;
+    v_path = path;
+    v_color = color;
+    v_blur = blur;
+    gl_Position = view_projection * vec4(position, 0.0, 1.0);
+}
+"#;
+
+const TEXT_FS: &str = r#"#version 300 es
+precision highp float;
+
+uniform sampler2D pathSampler;
+
+layout(location=0) out vec4 fragColor;
+
+in vec2 v_uv;
+flat in uvec2 v_path;
+flat in vec4 v_color;
+flat in vec2 v_blur;
+
+// evaluate only the x coordinate of the bezier specified by the control points
+float eval_bezier_x(float t, vec2 p1, vec2 p2, vec2 p3) {
+    float s = 1.0 - t;
+    return s * s * p1.x + 2.0 * s * t * p2.x + t * t * p3.x;
+}
+
+// returns the x coordinate of the intersection beteen a horizontal line at y=b, and the
+// bezier specified by the control points.
+float bezier_t_intersect_horizontal_line(float y, vec2 p1, vec2 p2, vec2 p3) {    
+    float a = p1.y - 2.0*p2.y + p3.y;
+
+    
+    // bezier is a straight line, so we can calculate the intersection directly
+    if (abs(a) < 0.0002) {
+        return (y - p1.y) / (p3.y - p1.y);
+    }
+
+    float q = p1.y - p2.y + sqrt(y*a + p2.y*p2.y - p1.y*p3.y);
+    float ta = q / a;
+    float tb = (p1.y - y) / q;
+    return (0.0 <= ta && ta <= 1.0) ? ta : tb;
+}
+
+void main(void) {
+    vec2 uv = v_uv;
+
+    vec2 ddx = dFdx(uv);
+    vec2 ddy = dFdy(uv);
+    // widening the footprint beyond a pixel averages coverage over a larger area, blurring edges
+    vec2 pixel_footprint = max(sqrt(ddx * ddx + ddy * ddy), v_blur);
+
+    float coverage = 0.0;
+
+    for (uint i = v_path.x; i < v_path.x + v_path.y; ++i) {
+        vec3 v0 = texelFetch(pathSampler, ivec2(0, i), 0).rgb;
+        vec3 v1 = texelFetch(pathSampler, ivec2(1, i), 0).rgb;
+
+        vec2 p1 = v0.xy;
+        vec2 p2 = vec2(v0.z, v1.x);
+        vec2 p3 = v1.yz;
+
+        // compute the overlap between pixel footprint and bezier in the y-axis
+        vec2 footprint_y = uv.y + vec2(-0.5, 0.5) * pixel_footprint.y;
+        vec2 window_y = clamp(vec2(p3.y, p1.y), footprint_y.x, footprint_y.y);
+        float overlap_y = (window_y.y - window_y.x) / pixel_footprint.y;
+
+        // no overlap, we're done here
+        if (overlap_y != 0.0) {
+            // grab the intersection in terms of t as well as x
+            float t = bezier_t_intersect_horizontal_line(0.5 * (window_y.x + window_y.y), p1, p2, p3);
+            float x = eval_bezier_x(t, p1, p2, p3);
+
+            // use the tangent at t to estimate overlap in the x-axis 
+            vec2 tangent = mix(p2 - p1, p3 - p2, t);
+            float f = ((x - uv.x) * abs(tangent.y)) / length(pixel_footprint * tangent.yx);
+            float overlap_x = clamp(0.5 + 0.7 * f, 0.0, 1.0);
+
+            // sum up the overlap from each curve
+            coverage += overlap_x * overlap_y;
+        }
+    }
+
+    // output premultiplied alpha, so edges blend correctly over coloured backgrounds
+    float alpha = sqrt(clamp(coverage, 0.0, 1.0)) * v_color.a;
+    fragColor = vec4(v_color.rgb * alpha, alpha);
+}
+"#;
+
+const BAKED_VS: &str = r#"#version 300 es
+layout(std140) uniform Camera {
+    mat4 view_projection;
+    float time;
+};
+
+uniform vec4 rect;
+
+out vec2 v_uv;
+
+void main(void) {
+    vec2 corner = vec2(gl_VertexID & 1, gl_VertexID >> 1);
+    v_uv = corner;
+    gl_Position = view_projection * vec4(mix(rect.xy, rect.zw, corner), 0.0, 1.0);
+}
+"#;
+
+const BAKED_FS: &str = r#"#version 300 es
+precision highp float;
+
+uniform sampler2D labelSampler;
+
+layout(location=0) out vec4 fragColor;
+
+in vec2 v_uv;
+
+void main(void) {
+    // the label was rendered with premultiplied alpha, so it can be blended as-is
+    fragColor = texture(labelSampler, v_uv);
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Queues far more glyphs than fit in one batch, flushing as `queue_glyphs` does, and checks
+    /// every glyph's offsets and indices fit their u16 attributes and the path texture
+    fn stress(max_segments: usize) {
+        let (mut segments, mut vertices, mut flushes) = (0usize, 0usize, 0);
+
+        for glyph in 0..200_000 {
+            // real glyphs run from a couple of segments for a dot to dozens for an ampersand
+            let glyph_segments = 1 + glyph % 40;
+
+            if batch_full(segments, vertices, glyph_segments, max_segments) {
+                segments = 0;
+                vertices = 0;
+                flushes += 1;
+            }
+
+            assert!(u16::try_from(segments).is_ok(), "segment offset overflows");
+            assert!(
+                u16::try_from(vertices + 3).is_ok(),
+                "vertex index overflows"
+            );
+            segments += glyph_segments;
+            vertices += 4;
+            assert!(segments <= max_segments, "path texture overflows");
+        }
+
+        assert!(flushes > 0, "stress test never crossed a batch boundary");
+    }
+
+    fn font() -> Font {
+        Font::from_slice(include_bytes!("../assets/KarmaticArcade-6Yrp1.ttf"), 0)
+    }
+
+    fn layout(font: &Font, font_size: f32, text: &str) -> Vec<PlacedGlyph> {
+        let style = GlyphStyle {
+            color: Vec4::ONE,
+            blur: 1.0,
+        };
+        layout_glyphs(
+            font,
+            None,
+            Mat3::IDENTITY,
+            font_size,
+            Align::Center,
+            text,
+            style,
+        )
+    }
+
+    #[test]
+    fn zero_font_size_places_nothing() {
+        assert!(layout(&font(), 0.0, "FATHOM").is_empty());
+        assert!(layout(&font(), -0.0, "FATHOM").is_empty());
+    }
+
+    #[test]
+    fn negative_font_size_places_nothing() {
+        assert!(layout(&font(), -12.0, "FATHOM").is_empty());
+        assert!(layout(&font(), f32::NEG_INFINITY, "FATHOM").is_empty());
+    }
+
+    #[test]
+    fn non_finite_font_size_places_nothing() {
+        assert!(layout(&font(), f32::NAN, "FATHOM").is_empty());
+        assert!(layout(&font(), f32::INFINITY, "FATHOM").is_empty());
+    }
+
+    #[test]
+    fn tiny_font_sizes_stay_finite() {
+        let font = font();
+        for font_size in [f32::MIN_POSITIVE, 1e-6, MIN_FONT_SIZE, 12.0] {
+            let glyphs = layout(&font, font_size, "Depth 42 m");
+            assert!(!glyphs.is_empty(), "size {font_size}");
+            for vertex in glyphs.iter().flat_map(|glyph| &glyph.vertices) {
+                assert!(vertex.pos.is_finite(), "size {font_size}");
+                assert!(vertex.uv.is_finite(), "size {font_size}");
+                assert!(vertex.blur.is_finite(), "size {font_size}");
+            }
+        }
+    }
+
+    #[test]
+    fn batches_stay_within_u16_offsets() {
+        stress(u16::MAX as usize);
+    }
 
-    fn compute_string_width(&self, text: &str) -> f32 {
-        text.chars()
-            .map(|c| self.font.chars.get(&c).map(|q| q.advance).unwrap_or(0.0))
-            .sum()
+    #[test]
+    fn batches_stay_within_the_smallest_path_texture() {
+        stress(MIN_MAX_TEXTURE_SIZE as usize);
     }
 }
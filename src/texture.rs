@@ -63,6 +63,24 @@ impl Texture {
         }
     }
 
+    /// Sets both the minification and magnification filter, e.g. `LINEAR` or `NEAREST`
+    pub fn set_filter(&self, filter: u32) {
+        self.context
+            .bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&self.texture));
+        self.context.tex_parameteri(
+            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::TEXTURE_MIN_FILTER,
+            filter as i32,
+        );
+        self.context.tex_parameteri(
+            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::TEXTURE_MAG_FILTER,
+            filter as i32,
+        );
+        self.context
+            .bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+    }
+
     pub fn write(&self, w: i32, h: i32, data: Option<&[u8]>) {
         self.context
             .bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&self.texture));
@@ -0,0 +1,41 @@
+use glam::Vec2;
+
+use crate::key_bindings::Action;
+
+/// Where on the screen to touch to fly the ship. Positions run from 0 to 1 across the canvas,
+/// with y pointing down as on the page
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TouchLayout {
+    /// Width of the turning strips down the left and right edges, as a fraction of the width
+    pub side_width: f32,
+    /// Height of the thrust area along the bottom, between the turning strips, as a fraction of
+    /// the height
+    pub thrust_height: f32,
+}
+
+impl Default for TouchLayout {
+    /// Thirds across the screen, with thrust low enough to reach with a thumb on a phone held
+    /// upright
+    fn default() -> Self {
+        Self {
+            side_width: 1.0 / 3.0,
+            thrust_height: 0.4,
+        }
+    }
+}
+
+impl TouchLayout {
+    /// The action a finger at `pos` takes. The turning strips run the full height, so the thrust
+    /// area is the bottom of the middle column
+    pub fn action(&self, pos: Vec2) -> Option<Action> {
+        if pos.x < self.side_width {
+            Some(Action::TurnLeft)
+        } else if pos.x > 1.0 - self.side_width {
+            Some(Action::TurnRight)
+        } else if pos.y > 1.0 - self.thrust_height {
+            Some(Action::Thrust)
+        } else {
+            None
+        }
+    }
+}
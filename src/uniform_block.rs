@@ -0,0 +1,73 @@
+use glam::Mat4;
+use web_sys::{WebGl2RenderingContext, WebGlBuffer};
+
+use crate::reinterpret_cast_slice;
+
+/// Binding point of the camera block shared by the scene shaders, declared as
+///
+/// ```glsl
+/// layout(std140) uniform Camera {
+///     mat4 view_projection;
+///     float time;
+/// };
+/// ```
+pub const CAMERA_BINDING: u32 = 0;
+
+/// Size of the camera block in std140 layout: a mat4, plus a float padded out to a vec4
+pub const CAMERA_BLOCK_SIZE: i32 = 20 * std::mem::size_of::<f32>() as i32;
+
+/// Lays out the contents of the camera block
+pub fn camera_block(view_projection: Mat4, time: f32) -> [f32; 20] {
+    let mut data = [0.0; 20];
+    data[..16].copy_from_slice(&view_projection.to_cols_array());
+    data[16] = time;
+    data
+}
+
+/// A uniform buffer object, holding uniforms shared by several shader programs so they can be
+/// uploaded once and bound once per frame
+pub struct UniformBlock {
+    context: WebGl2RenderingContext,
+    buffer: WebGlBuffer,
+}
+
+impl UniformBlock {
+    pub fn new(context: &WebGl2RenderingContext, size: i32) -> Self {
+        let buffer = context.create_buffer().expect("created buffer");
+
+        context.bind_buffer(WebGl2RenderingContext::UNIFORM_BUFFER, Some(&buffer));
+        context.buffer_data_with_i32(
+            WebGl2RenderingContext::UNIFORM_BUFFER,
+            size,
+            WebGl2RenderingContext::DYNAMIC_DRAW,
+        );
+        context.bind_buffer(WebGl2RenderingContext::UNIFORM_BUFFER, None);
+
+        Self {
+            context: context.clone(),
+            buffer,
+        }
+    }
+
+    /// Overwrites the start of the block. `data` must already be laid out to match the block
+    pub fn write(&self, data: &[f32]) {
+        self.context
+            .bind_buffer(WebGl2RenderingContext::UNIFORM_BUFFER, Some(&self.buffer));
+        self.context.buffer_sub_data_with_i32_and_u8_array(
+            WebGl2RenderingContext::UNIFORM_BUFFER,
+            0,
+            reinterpret_cast_slice(data),
+        );
+        self.context
+            .bind_buffer(WebGl2RenderingContext::UNIFORM_BUFFER, None);
+    }
+
+    /// Makes the block visible to every program whose block is bound to `index`
+    pub fn bind_base(&self, index: u32) {
+        self.context.bind_buffer_base(
+            WebGl2RenderingContext::UNIFORM_BUFFER,
+            index,
+            Some(&self.buffer),
+        );
+    }
+}